@@ -0,0 +1,5 @@
+// migrations are embedded at compile time: rebuild when they change
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-changed=migrations_sqlite");
+}
@@ -0,0 +1,3 @@
+ALTER TABLE signed_data DROP COLUMN renewal_id;
+ALTER TABLE signed_data DROP COLUMN receipt_hash_b64;
+DROP TABLE evidence_renewals;
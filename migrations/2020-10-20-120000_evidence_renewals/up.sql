@@ -0,0 +1,14 @@
+CREATE TABLE evidence_renewals (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  merkle_root_b64 VARCHAR(128) NOT NULL,
+  leaf_count BIGINT NOT NULL,
+  algorithm VARCHAR(64) NOT NULL,
+  signature_b64 TEXT NOT NULL,
+  signature_pq_b64 TEXT
+);
+
+ALTER TABLE signed_data ADD COLUMN receipt_hash_b64 VARCHAR(128);
+ALTER TABLE signed_data ADD COLUMN renewal_id BIGINT REFERENCES evidence_renewals (id);
+
+CREATE INDEX idx_signed_data_renewal ON signed_data (renewal_id);
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_signed_data_serial;
+ALTER TABLE signed_data DROP COLUMN prev_receipt_hash_b64;
+ALTER TABLE signed_data DROP COLUMN serial;
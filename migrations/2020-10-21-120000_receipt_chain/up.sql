@@ -0,0 +1,7 @@
+-- receipts issued before the chain keep their id as serial, and no previous receipt
+ALTER TABLE signed_data ADD COLUMN serial BIGINT;
+UPDATE signed_data SET serial = id;
+ALTER TABLE signed_data ALTER COLUMN serial SET NOT NULL;
+ALTER TABLE signed_data ADD COLUMN prev_receipt_hash_b64 VARCHAR(128);
+
+CREATE UNIQUE INDEX idx_signed_data_serial ON signed_data (serial);
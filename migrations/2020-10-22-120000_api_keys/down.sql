@@ -0,0 +1,3 @@
+DROP TABLE account_usage;
+DROP TABLE api_keys;
+DROP TABLE accounts;
@@ -0,0 +1,24 @@
+CREATE TABLE accounts (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  name VARCHAR(128) NOT NULL UNIQUE,
+  pow_mode VARCHAR(16) NOT NULL,
+  daily_quota BIGINT NOT NULL
+);
+
+-- only a hash of the secret part of keys is stored
+CREATE TABLE api_keys (
+  id BIGSERIAL PRIMARY KEY,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  account_id BIGINT NOT NULL REFERENCES accounts (id),
+  key_id VARCHAR(32) NOT NULL UNIQUE,
+  secret_hash_b64 VARCHAR(128) NOT NULL,
+  revoked_at TIMESTAMP
+);
+
+CREATE TABLE account_usage (
+  account_id BIGINT NOT NULL REFERENCES accounts (id),
+  day DATE NOT NULL,
+  count BIGINT NOT NULL,
+  PRIMARY KEY (account_id, day)
+);
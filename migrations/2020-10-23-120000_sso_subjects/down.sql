@@ -0,0 +1,3 @@
+DROP TABLE subject_usage;
+ALTER TABLE signed_data DROP COLUMN subject;
+ALTER TABLE signed_data DROP COLUMN subject_issuer;
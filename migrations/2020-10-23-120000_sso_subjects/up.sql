@@ -0,0 +1,11 @@
+-- who requested each receipt, for receipts requested with an SSO token
+ALTER TABLE signed_data ADD COLUMN subject_issuer VARCHAR(256);
+ALTER TABLE signed_data ADD COLUMN subject VARCHAR(256);
+
+CREATE TABLE subject_usage (
+  issuer VARCHAR(256) NOT NULL,
+  subject VARCHAR(256) NOT NULL,
+  day DATE NOT NULL,
+  count BIGINT NOT NULL,
+  PRIMARY KEY (issuer, subject, day)
+);
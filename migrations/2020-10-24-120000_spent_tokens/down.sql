@@ -0,0 +1 @@
+DROP TABLE spent_tokens;
@@ -0,0 +1,5 @@
+-- redeemed privacy tokens, by their nonce's hash. Unlinked to any receipt.
+CREATE TABLE spent_tokens (
+  nonce_hash_b64 VARCHAR(64) PRIMARY KEY,
+  spent_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+ALTER TABLE signed_data DROP COLUMN receipt_json;
+-- fails if duplicates were signed meanwhile
+ALTER TABLE signed_data ADD CONSTRAINT signed_data_data_hash_b64_key UNIQUE (data_hash_b64);
@@ -0,0 +1,4 @@
+-- duplicates are checked by the server, depending on its duplicate_policy
+ALTER TABLE signed_data DROP CONSTRAINT signed_data_data_hash_b64_key;
+-- the receipt as returned, to answer resubmissions. Null for receipts issued before
+ALTER TABLE signed_data ADD COLUMN receipt_json TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE evidence_renewals DROP COLUMN previous_path_json;
+ALTER TABLE evidence_renewals DROP COLUMN delegation_json;
@@ -0,0 +1,4 @@
+-- the delegation the renewal was signed under, and the Merkle path of the previous renewal's
+-- leaf, so evidence records don't rehash every renewal. Null for renewals made before
+ALTER TABLE evidence_renewals ADD COLUMN delegation_json TEXT;
+ALTER TABLE evidence_renewals ADD COLUMN previous_path_json TEXT;
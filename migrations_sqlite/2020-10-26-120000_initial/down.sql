@@ -0,0 +1,7 @@
+DROP TABLE spent_tokens;
+DROP TABLE subject_usage;
+DROP TABLE account_usage;
+DROP TABLE api_keys;
+DROP TABLE accounts;
+DROP TABLE signed_data;
+DROP TABLE evidence_renewals;
@@ -0,0 +1,66 @@
+-- the schema of `migrations/` as of 2020-10-25, at once
+CREATE TABLE evidence_renewals (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  merkle_root_b64 VARCHAR(128) NOT NULL,
+  leaf_count BIGINT NOT NULL,
+  algorithm VARCHAR(64) NOT NULL,
+  signature_b64 TEXT NOT NULL,
+  signature_pq_b64 TEXT
+);
+
+CREATE TABLE signed_data (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  data_hash_b64 VARCHAR(128) NOT NULL,
+  receipt_hash_b64 VARCHAR(128),
+  renewal_id BIGINT REFERENCES evidence_renewals (id),
+  serial BIGINT NOT NULL,
+  prev_receipt_hash_b64 VARCHAR(128),
+  subject_issuer VARCHAR(256),
+  subject VARCHAR(256),
+  receipt_json TEXT
+);
+
+CREATE INDEX idx_signed_data_hash ON signed_data (data_hash_b64);
+CREATE INDEX idx_signed_data_renewal ON signed_data (renewal_id);
+CREATE UNIQUE INDEX idx_signed_data_serial ON signed_data (serial);
+
+CREATE TABLE accounts (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  name VARCHAR(128) NOT NULL UNIQUE,
+  pow_mode VARCHAR(16) NOT NULL,
+  daily_quota BIGINT NOT NULL
+);
+
+-- only a hash of the secret part of keys is stored
+CREATE TABLE api_keys (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  account_id BIGINT NOT NULL REFERENCES accounts (id),
+  key_id VARCHAR(32) NOT NULL UNIQUE,
+  secret_hash_b64 VARCHAR(128) NOT NULL,
+  revoked_at TIMESTAMP
+);
+
+CREATE TABLE account_usage (
+  account_id BIGINT NOT NULL REFERENCES accounts (id),
+  day DATE NOT NULL,
+  count BIGINT NOT NULL,
+  PRIMARY KEY (account_id, day)
+);
+
+CREATE TABLE subject_usage (
+  issuer VARCHAR(256) NOT NULL,
+  subject VARCHAR(256) NOT NULL,
+  day DATE NOT NULL,
+  count BIGINT NOT NULL,
+  PRIMARY KEY (issuer, subject, day)
+);
+
+-- redeemed privacy tokens, by their nonce's hash. Unlinked to any receipt.
+CREATE TABLE spent_tokens (
+  nonce_hash_b64 VARCHAR(64) PRIMARY KEY,
+  spent_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
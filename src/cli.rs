@@ -0,0 +1,124 @@
+use anyhow::{Context, Error as AnyErr, Result};
+use chrono::NaiveDateTime;
+use std::path::PathBuf;
+//
+use crate::routes::middleware::auth::PowMode;
+
+const USAGE: &str = "usage:
+    [serve]                 run the HTTP server
+    signer                  run the signer daemon
+    migrate                 apply the database migrations built in
+    keygen                  generate the signing key
+    split-key <k> <n>       print k-of-n shares of the signing key
+    combine-key             restore the signing key from shares on stdin
+    delegate <root_keyfile> <online_pubkey_base64> <days> [<online_pq_pubkey_base64>]
+    revoke <issuer_keyfile> <revoked_pubkey_base64> <reason> [<compromised_since>]
+    renew                   re-timestamp receipts into a new evidence renewal
+    account-create <name> <daily_quota> <pow_mode: full|digest|exempt>
+    apikey-create <account_name>    print a new API key for the account
+    apikey-revoke <key_id>";
+
+/// What the binary was asked to do, from its command-line arguments.
+/// Without arguments, the HTTP server is started.
+pub enum Cmd {
+    Serve,
+    Signer,
+    Migrate,
+    Keygen,
+    SplitKey {
+        k: u8,
+        n: u8,
+    },
+    CombineKey,
+    Delegate {
+        root_keyfile: PathBuf,
+        online_pubkey_base64: String,
+        days: i64,
+        online_pq_pubkey_base64: Option<String>,
+    },
+    Revoke {
+        issuer_keyfile: PathBuf,
+        revoked_pubkey_base64: String,
+        reason: String,
+        compromised_since: Option<NaiveDateTime>,
+    },
+    Renew,
+    AccountCreate {
+        name: String,
+        daily_quota: i64,
+        pow_mode: PowMode,
+    },
+    ApiKeyCreate {
+        account_name: String,
+    },
+    ApiKeyRevoke {
+        key_id: String,
+    },
+}
+impl Cmd {
+    pub fn from_args() -> Result<Self, AnyErr> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match args.as_slice() {
+            [] | ["serve"] => Ok(Cmd::Serve),
+            ["signer"] => Ok(Cmd::Signer),
+            ["migrate"] => Ok(Cmd::Migrate),
+            ["keygen"] => Ok(Cmd::Keygen),
+            ["split-key", k, n] => Ok(Cmd::SplitKey {
+                k: k.parse().context("k must be a number of shares")?,
+                n: n.parse().context("n must be a number of shares")?,
+            }),
+            ["combine-key"] => Ok(Cmd::CombineKey),
+            ["delegate", root_keyfile, online_pubkey_base64, days] => Ok(Cmd::Delegate {
+                root_keyfile: root_keyfile.into(),
+                online_pubkey_base64: online_pubkey_base64.to_string(),
+                days: days.parse().context("days must be a number")?,
+                online_pq_pubkey_base64: None,
+            }),
+            ["delegate", root_keyfile, online_pubkey_base64, days, online_pq_pubkey_base64] => {
+                Ok(Cmd::Delegate {
+                    root_keyfile: root_keyfile.into(),
+                    online_pubkey_base64: online_pubkey_base64.to_string(),
+                    days: days.parse().context("days must be a number")?,
+                    online_pq_pubkey_base64: Some(online_pq_pubkey_base64.to_string()),
+                })
+            }
+            ["revoke", issuer_keyfile, revoked_pubkey_base64, reason] => Ok(Cmd::Revoke {
+                issuer_keyfile: issuer_keyfile.into(),
+                revoked_pubkey_base64: revoked_pubkey_base64.to_string(),
+                reason: reason.to_string(),
+                compromised_since: None,
+            }),
+            ["revoke", issuer_keyfile, revoked_pubkey_base64, reason, compromised_since] => {
+                Ok(Cmd::Revoke {
+                    issuer_keyfile: issuer_keyfile.into(),
+                    revoked_pubkey_base64: revoked_pubkey_base64.to_string(),
+                    reason: reason.to_string(),
+                    compromised_since: Some(
+                        compromised_since
+                            .parse()
+                            .context("compromised_since must be like 2020-10-12T18:45:18")?,
+                    ),
+                })
+            }
+            ["renew"] => Ok(Cmd::Renew),
+            ["account-create", name, daily_quota, pow_mode] => Ok(Cmd::AccountCreate {
+                name: name.to_string(),
+                daily_quota: daily_quota
+                    .parse()
+                    .context("daily_quota must be a number")?,
+                pow_mode: pow_mode.parse()?,
+            }),
+            ["apikey-create", account_name] => Ok(Cmd::ApiKeyCreate {
+                account_name: account_name.to_string(),
+            }),
+            ["apikey-revoke", key_id] => Ok(Cmd::ApiKeyRevoke {
+                key_id: key_id.to_string(),
+            }),
+            _ => Err(AnyErr::msg(format!(
+                "unknown command: {:?}\n{}",
+                args, USAGE
+            ))),
+        }
+    }
+}
@@ -1,31 +1,227 @@
 use anyhow::{Context, Error as AnyErr, Result};
+use chrono::Utc;
 use config::{Config as ConfigLoader, Environment, File};
+use ed25519_dalek::PublicKey;
 use std::borrow::Cow;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 //
-use crate::utils::crypto_sign::KeyPair;
+use crate::models::{Storage, StorageBackend};
+use crate::routes::middleware::ip_ratelimit::{Cidr, RateLimiter};
+use crate::routes::sign_data::DuplicatePolicy;
+use crate::signer::Signer;
+use crate::utils::blind_sign::EpochKeys;
+use crate::utils::clock::{ClockGuard, ClockSource};
+use crate::utils::crypto_sign::{KeyMode, KeyPair};
+use crate::utils::crypto_sign_pq::PqKeyPair;
+use crate::utils::delegation::Delegation;
+use crate::utils::jwt::Issuers;
+use crate::utils::logging::LogFormat;
+use crate::utils::tokens::TokenKey;
 
 lazy_static::lazy_static! {
-    #[derive(Debug)]
     static ref CONFIG: Config<'static> = Config::load().expect("failed loading config");
     static ref PG_DSN: String = CONFIG.pg_dsn().expect("failed loading pg_dsn").to_string();
-    static ref KEYPAIR_SIGN: KeyPair = KeyPair::from_file_or_new(&CONFIG.keyfile_path).expect("failed getting keypair for signing");
+    static ref STORAGE: Box<dyn Storage> = crate::models::connect().expect("failed connecting storage");
+    static ref KEYPAIR_SIGN: KeyPair = KeyPair::load(&CONFIG.keyfile_path, CONFIG.key_mode())
+        .unwrap_or_else(|e| panic!("failed loading keypair for signing: {}", e));
+    static ref PQ_KEYPAIR_SIGN: Option<PqKeyPair> = match CONFIG.hybrid_signatures {
+        true => Some(PqKeyPair::load(&CONFIG.pq_keyfile_path, CONFIG.key_mode())
+            .unwrap_or_else(|e| panic!("failed loading post-quantum keypair for signing: {}", e))),
+        false => None,
+    };
+    static ref TOKEN_KEY: Option<TokenKey> = CONFIG.token_keyfile_path.as_ref().map(|path| {
+        TokenKey::load(path, CONFIG.key_mode())
+            .unwrap_or_else(|e| panic!("failed loading token key: {}", e))
+    });
+    static ref SIGNER: Signer = new_signer().expect("failed setting up signer");
+    static ref EPOCH_KEYS: EpochKeys = EpochKeys::new(blind_epoch());
+    static ref DELEGATION: Option<Delegation> = load_delegation().expect("failed loading delegation");
+    static ref JWT_ISSUERS: Option<Issuers> = load_jwt_issuers().expect("failed loading JWT issuers");
+    static ref RATE_LIMITER: RateLimiter = RateLimiter::new(
+        CONFIG.ratelimit_ip_per_min,
+        CONFIG.ratelimit_subnet_per_min,
+        Cidr::parse_list(&CONFIG.trusted_proxies).expect("checked when loading"),
+    );
+    static ref CLOCK_GUARD: ClockGuard = ClockGuard::new(
+        ClockSource::parse_list(&CONFIG.clock_sources).expect("checked when loading"),
+        chrono::Duration::milliseconds(CONFIG.max_clock_skew_ms as i64),
+        Duration::from_secs(CONFIG.clock_recheck_secs),
+    );
 }
 
 pub fn pg_dsn<'a>() -> &'a str {
     &PG_DSN
 }
+/// Connected on first use
+pub fn storage() -> &'static dyn Storage {
+    &**STORAGE
+}
+pub fn storage_backend() -> StorageBackend {
+    CONFIG.storage
+}
+pub fn sqlite_path<'a>() -> &'a Path {
+    &CONFIG.sqlite_path
+}
+pub fn sled_dir<'a>() -> &'a Path {
+    &CONFIG.sled_dir
+}
+/// An `EnvFilter` directive, e.g. `info,crypto_timestamp_api=debug`
+pub fn rust_log<'a>() -> &'a str {
+    &CONFIG.rust_log
+}
+pub fn log_format() -> LogFormat {
+    CONFIG.log_format
+}
+/// None if spans aren't exported. Only set with the otlp feature
+pub fn otlp_endpoint<'a>() -> Option<&'a str> {
+    CONFIG.otlp_endpoint.as_deref()
+}
+/// Whether `serve` applies pending migrations itself, instead of a `migrate` job
+pub fn migrate_on_startup() -> bool {
+    CONFIG.migrate_on_startup
+}
 pub fn port() -> u16 {
     CONFIG.http_port
 }
-pub fn keypair<'a>() -> &'a KeyPair {
+pub fn keypair() -> &'static KeyPair {
     &KEYPAIR_SIGN
 }
+pub fn keyfile_path<'a>() -> &'a Path {
+    &CONFIG.keyfile_path
+}
+/// Only loaded if hybrid (Ed25519 + post-quantum) signatures are enabled
+pub fn pq_keypair() -> Option<&'static PqKeyPair> {
+    PQ_KEYPAIR_SIGN.as_ref()
+}
+pub fn pq_keyfile_path<'a>() -> Option<&'a Path> {
+    match CONFIG.hybrid_signatures {
+        true => Some(&CONFIG.pq_keyfile_path),
+        false => None,
+    }
+}
+/// The key privacy tokens are issued and redeemed with, if `token_keyfile_path` is set
+pub fn token_key() -> Option<&'static TokenKey> {
+    TOKEN_KEY.as_ref()
+}
+pub fn signer<'a>() -> &'a Signer {
+    &SIGNER
+}
+pub fn signer_socket<'a>() -> Result<&'a Path, AnyErr> {
+    CONFIG
+        .signer_socket
+        .as_deref()
+        .context("signer_socket must be set to run the signer")
+}
+
+pub fn revocations_dir<'a>() -> &'a Path {
+    &CONFIG.revocations_dir
+}
+pub fn delegation<'a>() -> Option<&'a Delegation> {
+    DELEGATION.as_ref()
+}
+/// None if the server doesn't renew evidence records itself
+pub fn renewal_interval() -> Option<Duration> {
+    match CONFIG.renewal_interval_secs {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
+/// Roughtime is served with the key in this process: not available with a signer socket
+pub fn roughtime_port() -> Option<u16> {
+    CONFIG.roughtime_port
+}
+pub fn rate_limiter<'a>() -> &'a RateLimiter {
+    &RATE_LIMITER
+}
+/// None if bearer tokens aren't accepted
+pub fn jwt_issuers<'a>() -> Option<&'a Issuers> {
+    JWT_ISSUERS.as_ref()
+}
+/// How long each blind signing key is used: the precision of blind receipts' timestamps
+pub fn blind_epoch() -> chrono::Duration {
+    chrono::Duration::seconds(CONFIG.blind_epoch_secs as i64)
+}
+/// The blind signing keys of this process: used by the signer daemon, or the in-process signer
+pub fn epoch_keys<'a>() -> &'a EpochKeys {
+    &EPOCH_KEYS
+}
+/// What signing already signed data does
+pub fn duplicate_policy() -> DuplicatePolicy {
+    CONFIG.duplicate_policy
+}
+pub fn clock_guard<'a>() -> &'a ClockGuard {
+    &CLOCK_GUARD
+}
+/// The key clients pin: the root key when signing is delegated, else the signing key itself
+pub fn trust_anchor() -> PublicKey {
+    match delegation() {
+        Some(_) => root_pubkey().ok().flatten().expect("checked when loading"),
+        None => signer().pubkey(),
+    }
+}
+/// The offline root key delegations must be signed by. None if signing isn't delegated
+fn root_pubkey() -> Result<Option<PublicKey>, AnyErr> {
+    match &CONFIG.root_pubkey_base64 {
+        Some(b64) => Ok(Some(
+            PublicKey::from_bytes(&base64::decode(b64)?).context("invalid root_pubkey_base64")?,
+        )),
+        None => Ok(None),
+    }
+}
+
+fn load_delegation() -> Result<Option<Delegation>, AnyErr> {
+    let path = match &CONFIG.delegation_path {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let root = root_pubkey()?.context("checked when loading")?;
+    let delegation = Delegation::from_file(path, &root)
+        .with_context(|| format!("invalid delegation at {}", path.display()))?;
+    anyhow::ensure!(
+        delegation.online_pubkey()? == signer().pubkey(),
+        "delegation at {} is for another key than the signing key",
+        path.display()
+    );
+    anyhow::ensure!(
+        delegation.online_pq_pubkey()?.as_deref() == signer().pq_pubkey(),
+        "delegation at {} doesn't certify the post-quantum signing key, or certifies another",
+        path.display()
+    );
+    if !delegation.covers(Utc::now()) {
+        warn!("delegation at {} isn't valid now", path.display());
+    }
+    Ok(Some(delegation))
+}
+
+fn load_jwt_issuers() -> Result<Option<Issuers>, AnyErr> {
+    match &CONFIG.jwt_issuers_path {
+        Some(path) => {
+            Ok(Some(Issuers::from_file(path).with_context(|| {
+                format!("invalid JWT issuers at {}", path.display())
+            })?))
+        }
+        None => Ok(None),
+    }
+}
+
+// With a signer socket configured, the keyfile is never read by this process
+fn new_signer() -> Result<Signer, AnyErr> {
+    match &CONFIG.signer_socket {
+        Some(socket_path) => Ok(Signer::Remote(
+            crate::signer::client::SignerClient::connect(socket_path)
+                .with_context(|| format!("failed reaching signer at {}", socket_path.display()))?,
+        )),
+        None => Ok(Signer::Local(keypair(), pq_keypair())),
+    }
+}
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 struct Config<'a> {
     rust_log: String,
     rust_backtrace: String,
+    log_format: LogFormat,
+    otlp_endpoint: Option<String>,
     http_port: u16,
     #[serde(borrow, rename = "database_url")]
     pg_dsn: Option<Cow<'a, str>>,
@@ -37,9 +233,41 @@ struct Config<'a> {
     pg_db: Option<Cow<'a, str>>,
     #[serde(borrow, rename = "postgres_host")]
     pg_host: Option<Cow<'a, str>>,
+    storage: StorageBackend,
+    sqlite_path: PathBuf,
+    sled_dir: PathBuf,
     keyfile_path: PathBuf,
+    key_mode: Option<KeyMode>,
+    production: bool,
+    migrate_on_startup: bool,
+    duplicate_policy: DuplicatePolicy,
+    signer_socket: Option<PathBuf>,
+    delegation_path: Option<PathBuf>,
+    root_pubkey_base64: Option<String>,
+    revocations_dir: PathBuf,
+    hybrid_signatures: bool,
+    pq_keyfile_path: PathBuf,
+    token_keyfile_path: Option<PathBuf>,
+    renewal_interval_secs: u64,
+    clock_sources: String,
+    max_clock_skew_ms: u64,
+    clock_recheck_secs: u64,
+    roughtime_port: Option<u16>,
+    blind_epoch_secs: u64,
+    jwt_issuers_path: Option<PathBuf>,
+    ratelimit_ip_per_min: u32,
+    ratelimit_subnet_per_min: u32,
+    trusted_proxies: String,
 }
 impl<'a> Config<'a> {
+    // production never mints keys: a lost key file must be noticed, not silently replaced
+    fn key_mode(&self) -> KeyMode {
+        match (self.key_mode, self.production) {
+            (Some(mode), _) => mode,
+            (None, true) => KeyMode::Strict,
+            (None, false) => KeyMode::GenerateIfMissing,
+        }
+    }
     fn pg_env_vars(&self) -> Result<Option<(&str, &str, &str, &str)>, AnyErr> {
         match (&self.pg_user, &self.pg_pass, &self.pg_host, &self.pg_db) {
             (Some(u), Some(p), Some(h), Some(db)) => Ok(Some((u, p, h, db))),
@@ -67,9 +295,33 @@ impl<'a> Config<'a> {
     fn load() -> Result<Self, AnyErr> {
         let mut s = ConfigLoader::new();
         s.set_default("http_port", 8080)?;
-        s.set_default("rust_log", "auth-rs-warp=debug")?;
+        s.set_default("rust_log", "info")?;
+        s.set_default("log_format", "json")?;
         s.set_default("rust_backtrace", 1)?;
+        // tests run without a database server
+        s.set_default("storage", if cfg!(test) { "memory" } else { "postgres" })?;
+        s.set_default("sqlite_path", "./.data/timestamps.sqlite")?;
+        s.set_default("sled_dir", "./.data/sled")?;
         s.set_default("keyfile_path", "./.config/keys/keypair_sign")?;
+        s.set_default("production", false)?;
+        s.set_default("migrate_on_startup", false)?;
+        s.set_default("duplicate_policy", "strict")?;
+        s.set_default("revocations_dir", "./.config/revocations")?;
+        s.set_default("hybrid_signatures", false)?;
+        s.set_default("pq_keyfile_path", "./.config/keys/keypair_sign_pq")?;
+        s.set_default("renewal_interval_secs", 24 * 60 * 60)?;
+        s.set_default("clock_sources", "")?;
+        s.set_default("max_clock_skew_ms", 1000)?;
+        s.set_default("clock_recheck_secs", 60)?;
+        s.set_default("blind_epoch_secs", 60 * 60)?;
+        s.set_default("ratelimit_ip_per_min", 60)?;
+        s.set_default("ratelimit_subnet_per_min", 600)?;
+        s.set_default("trusted_proxies", "")?;
+        // bearer tokens from the test issuer (see src/tests/fixtures), and privacy tokens
+        if cfg!(test) {
+            s.set_default("jwt_issuers_path", "./src/tests/fixtures/jwt_issuers.json")?;
+            s.set_default("token_keyfile_path", "./.config/keys/token_key")?;
+        }
         s.merge(File::with_name("./.config/api_config").required(false))?;
         s.merge(Environment::new())?;
 
@@ -83,6 +335,33 @@ impl<'a> Config<'a> {
     fn validate(&self) -> Result<(), AnyErr> {
         anyhow::ensure!(self.http_port != 0, "http port can't be 0");
         anyhow::ensure!(self.pg_env_vars().is_ok(), "{}");
+        anyhow::ensure!(
+            !(self.production && self.key_mode() == KeyMode::GenerateIfMissing),
+            "key_mode generate_if_missing is not allowed in production"
+        );
+        anyhow::ensure!(
+            !(self.production && self.storage == StorageBackend::Memory),
+            "storage memory is not allowed in production: receipts would be lost on exit"
+        );
+        anyhow::ensure!(
+            self.delegation_path.is_none() || self.root_pubkey_base64.is_some(),
+            "delegation_path needs root_pubkey_base64, the root key to check it against"
+        );
+        ClockSource::parse_list(&self.clock_sources)?;
+        Cidr::parse_list(&self.trusted_proxies)?;
+        anyhow::ensure!(self.blind_epoch_secs != 0, "blind_epoch_secs can't be 0");
+        anyhow::ensure!(
+            cfg!(feature = "otlp") || self.otlp_endpoint.is_none(),
+            "otlp_endpoint is set, but this build doesn't have the otlp feature"
+        );
+        anyhow::ensure!(
+            !(self.roughtime_port.is_some() && self.signer_socket.is_some()),
+            "roughtime_port needs the signing key in-process, it can't be used with signer_socket"
+        );
+        anyhow::ensure!(
+            !(self.token_keyfile_path.is_some() && self.signer_socket.is_some()),
+            "token_keyfile_path needs the token key in-process, it can't be used with signer_socket"
+        );
         match (self.pg_dsn.as_ref(), self.pg_env_vars()) {
             (Some(dsn), Ok(Some(_))) => {
                 anyhow::ensure!(
@@ -1,29 +1,50 @@
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 use std::convert::Infallible;
 use warp::filters::body::BodyDeserializeError;
-use warp::http::StatusCode;
+use warp::http::{header, HeaderValue, StatusCode};
 use warp::{Rejection, Reply};
 //
-use crate::routes::SignDataErr;
+use crate::routes::middleware::auth::AuthErr;
+use crate::routes::middleware::ip_ratelimit::RateLimitErr;
+use crate::routes::{BlindSignErr, IssueTokensErr, KeyStatusErr, SignBatchErr, SignDataErr};
+use crate::utils::evidence::EvidenceErr;
+use crate::utils::metrics::MetricsErr;
 
 pub async fn handle_rejection(r: Rejection) -> Result<impl Reply, Infallible> {
-    Ok(ErrResp::from(r).into_reply())
+    let resp = ErrResp::from(&r);
+    // clients only see a generic message: the cause goes to the request's log span
+    if resp.statuscode.is_server_error() {
+        error!("{}: {:?}", resp.message, r);
+    }
+    Ok(resp.into_reply())
 }
 
 /// An API error serializable to JSON responses
-struct ErrResp {
+pub(crate) struct ErrResp {
     statuscode: StatusCode,
     message: String,
+    // seconds, sent as the `Retry-After` header
+    retry_after: Option<u64>,
 }
 impl ErrResp {
     pub fn new(code: StatusCode, msg: &str) -> Self {
         ErrResp {
             statuscode: code,
             message: msg.into(),
+            retry_after: None,
         }
     }
     pub fn into_reply(&self) -> impl warp::Reply {
-        warp::reply::with_status(warp::reply::json(&self), self.statuscode)
+        let mut res =
+            warp::reply::with_status(warp::reply::json(&self), self.statuscode).into_response();
+        if let Some(secs) = self.retry_after {
+            res.headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        res
     }
 }
 impl Serialize for ErrResp {
@@ -38,20 +59,66 @@ impl Serialize for ErrResp {
         state.end()
     }
 }
-impl From<Rejection> for ErrResp {
-    fn from(r: Rejection) -> Self {
+impl JsonSchema for ErrResp {
+    fn schema_name() -> String {
+        "ErrResp".to_string()
+    }
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        // as serialized above
+        #[derive(JsonSchema)]
+        #[allow(dead_code)]
+        struct Serialized {
+            code: u16,
+            message: String,
+            status: String,
+        }
+        Serialized::json_schema(gen)
+    }
+}
+impl From<&Rejection> for ErrResp {
+    fn from(r: &Rejection) -> Self {
         if r.is_not_found() {
             return ErrResp::new(StatusCode::NOT_FOUND, "Not found");
         }
+        if let Some(e) = r.find::<RateLimitErr>() {
+            return ErrResp::from(e);
+        }
         if let Some(e) = r.find::<SignDataErr>() {
             return ErrResp::from(e);
         }
+        if let Some(e) = r.find::<SignBatchErr>() {
+            return ErrResp::new(StatusCode::BAD_REQUEST, &format!("Bad Request: {}", e));
+        }
+        if let Some(e) = r.find::<BlindSignErr>() {
+            return ErrResp::from(e);
+        }
+        if let Some(e) = r.find::<IssueTokensErr>() {
+            return ErrResp::from(e);
+        }
+        if let Some(e) = r.find::<AuthErr>() {
+            return ErrResp::from(e);
+        }
+        if let Some(e) = r.find::<EvidenceErr>() {
+            return ErrResp::from(e);
+        }
+        if let Some(KeyStatusErr::UnknownKey) = r.find::<KeyStatusErr>() {
+            return ErrResp::new(StatusCode::NOT_FOUND, "Unknown key");
+        }
         if let Some(e) = r.find::<BodyDeserializeError>() {
             return ErrResp::new(
                 StatusCode::BAD_REQUEST,
                 &format!("Bad Request: {}", e).to_owned(),
             );
         }
+        if let Some(_) = r.find::<MetricsErr>() {
+            return ErrResp::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error");
+        }
+        if let Some(_) = r.find::<warp::reject::PayloadTooLarge>() {
+            return ErrResp::new(StatusCode::PAYLOAD_TOO_LARGE, "Payload too large");
+        }
+        if let Some(_) = r.find::<warp::reject::LengthRequired>() {
+            return ErrResp::new(StatusCode::LENGTH_REQUIRED, "Content-Length required");
+        }
         if let Some(_) = r.find::<warp::reject::MethodNotAllowed>() {
             return ErrResp::from(StatusCode::METHOD_NOT_ALLOWED);
         }
@@ -79,7 +146,10 @@ impl From<&SignDataErr> for ErrResp {
                 ModelErr::AlreadyExists(_) => {
                     ErrResp::new(StatusCode::CONFLICT, "Resource already exists")
                 }
-                ModelErr::OtherDieselErr(_) => {
+                ModelErr::OtherDieselErr(_)
+                | ModelErr::Kv(_)
+                | ModelErr::KvRecord(_)
+                | ModelErr::Corrupt(_) => {
                     ErrResp::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
                 }
             },
@@ -94,6 +164,116 @@ impl From<&SignDataErr> for ErrResp {
                 StatusCode::BAD_REQUEST,
                 "PoW proof didn't pass verification",
             ),
+            SignDataErr::AlreadySigned => {
+                ErrResp::new(StatusCode::CONFLICT, "Resource already exists")
+            }
+            SignDataErr::StoredReceipt(_) => {
+                ErrResp::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+            }
+            SignDataErr::Auth(e) => ErrResp::from(e),
+            SignDataErr::Signer(_) | SignDataErr::Task(_) => {
+                ErrResp::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+            }
+            SignDataErr::DelegationExpired => ErrResp::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Signing key delegation isn't valid",
+            ),
+            SignDataErr::KeyRevoked => {
+                ErrResp::new(StatusCode::SERVICE_UNAVAILABLE, "Signing key is revoked")
+            }
+            SignDataErr::Clock(_) => ErrResp::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Server clock can't be trusted right now",
+            ),
+        }
+    }
+}
+impl From<&EvidenceErr> for ErrResp {
+    fn from(e: &EvidenceErr) -> Self {
+        match e {
+            EvidenceErr::UnknownReceipt => ErrResp::new(StatusCode::NOT_FOUND, "Unknown receipt"),
+            EvidenceErr::NotRenewedYet => ErrResp::new(
+                StatusCode::NOT_FOUND,
+                "Receipt not renewed yet, retry after the next renewal",
+            ),
+            EvidenceErr::PredatesEvidence => ErrResp::new(
+                StatusCode::NOT_FOUND,
+                "Receipt issued before evidence records",
+            ),
+            _ => ErrResp::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
+        }
+    }
+}
+impl From<&BlindSignErr> for ErrResp {
+    fn from(e: &BlindSignErr) -> Self {
+        use crate::utils::blind_sign::BlindErr;
+        match e {
+            BlindSignErr::PowRejected => ErrResp::new(
+                StatusCode::BAD_REQUEST,
+                "PoW proof didn't pass verification",
+            ),
+            BlindSignErr::B64(e) => ErrResp::new(
+                StatusCode::BAD_REQUEST,
+                &format!("Invalid base64 field: {}", e),
+            ),
+            BlindSignErr::Blind(BlindErr::BadBlindedMessage) => ErrResp::new(
+                StatusCode::BAD_REQUEST,
+                "Blinded message must be as long as the key's modulus, and smaller",
+            ),
+            BlindSignErr::EpochOver | BlindSignErr::Blind(BlindErr::EpochOver) => ErrResp::new(
+                StatusCode::CONFLICT,
+                "Key epoch is over, blind again for the current key",
+            ),
+            BlindSignErr::Conditions(e) => ErrResp::from(e),
+            BlindSignErr::Auth(e) => ErrResp::from(e),
+            _ => ErrResp::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
+        }
+    }
+}
+impl From<&IssueTokensErr> for ErrResp {
+    fn from(e: &IssueTokensErr) -> Self {
+        match e {
+            IssueTokensErr::PowRejected => ErrResp::new(
+                StatusCode::BAD_REQUEST,
+                "PoW proof didn't pass verification",
+            ),
+            IssueTokensErr::Disabled => {
+                ErrResp::new(StatusCode::NOT_FOUND, "Privacy tokens aren't enabled")
+            }
+            IssueTokensErr::Auth(e) => ErrResp::from(e),
+            IssueTokensErr::Task(_) | IssueTokensErr::DbConn(_) | IssueTokensErr::Model(_) => {
+                ErrResp::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+            }
+            e => ErrResp::new(StatusCode::BAD_REQUEST, &format!("Bad Request: {}", e)),
+        }
+    }
+}
+impl From<&RateLimitErr> for ErrResp {
+    fn from(e: &RateLimitErr) -> Self {
+        match e {
+            RateLimitErr::TooManyRequests { retry_after_secs } => ErrResp {
+                retry_after: Some(*retry_after_secs),
+                ..ErrResp::new(StatusCode::TOO_MANY_REQUESTS, "Too many requests")
+            },
+            _ => ErrResp::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
+        }
+    }
+}
+impl From<&AuthErr> for ErrResp {
+    fn from(e: &AuthErr) -> Self {
+        match e {
+            AuthErr::UnsupportedScheme => {
+                ErrResp::new(StatusCode::UNAUTHORIZED, "Unsupported authorization scheme")
+            }
+            AuthErr::InvalidKey => ErrResp::new(StatusCode::UNAUTHORIZED, "Invalid API key"),
+            AuthErr::InvalidToken => ErrResp::new(StatusCode::UNAUTHORIZED, "Invalid bearer token"),
+            AuthErr::NoTier => ErrResp::new(StatusCode::FORBIDDEN, "Not allowed to sign"),
+            AuthErr::InvalidPrivateToken => ErrResp::new(StatusCode::UNAUTHORIZED, "Invalid token"),
+            AuthErr::TokenSpent => ErrResp::new(StatusCode::UNAUTHORIZED, "Token already spent"),
+            AuthErr::QuotaExceeded => {
+                ErrResp::new(StatusCode::TOO_MANY_REQUESTS, "Daily quota exceeded")
+            }
+            _ => ErrResp::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
         }
     }
 }
@@ -4,39 +4,343 @@ extern crate openssl; // for musl (if using alpine). Before diesel macro imports
 #[macro_use]
 extern crate diesel;
 #[macro_use]
+extern crate diesel_migrations;
+#[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate log;
 use std::convert::Infallible;
+use std::io::Write;
 use std::net::SocketAddr;
 use warp::{body, get, path, post, Filter, Reply};
 //
+mod cli;
 mod config;
 mod errors;
 mod models;
+mod roughtime;
 mod routes;
+mod signer;
 mod utils;
-use utils::db_conn;
+use models::Lock;
 
 #[cfg(test)]
 mod tests {
+    mod blind_sign;
+    mod clock;
+    mod crypto_sign;
+    mod crypto_sign_pq;
+    mod delegation;
+    mod ip_ratelimit;
+    mod jwt;
+    mod key_shares;
+    mod merkle;
+    mod migrations;
+    mod revocation;
+    mod roughtime;
     mod routes;
+    mod signer;
+    mod storage;
+    mod tokens;
 }
 
 pub fn router(
 ) -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone + Send + Sync + 'static {
-    (get().and(path::end()).and_then(routes::getRoot))
-        .or(get().and(path("health")).and_then(routes::getRoot))
+    use routes::middleware::request_id;
+    let routes = (get().and(path::end()).and_then(routes::getRoot))
+        .or(get()
+            .and(path("health"))
+            .and(path::end())
+            .and_then(routes::health_ready))
+        .or(get()
+            .and(warp::path!("health" / "live"))
+            .and_then(routes::health_live))
+        .or(get()
+            .and(warp::path!("health" / "ready"))
+            .and_then(routes::health_ready))
         .or(get().and(path("pubkey")).and_then(routes::pubkey))
-        .or(post().and(path("sign_data").and(body::json().and_then(routes::sign_data))))
+        .or(get().and(path("metrics")).and_then(routes::metrics))
+        .or(get().and(path("openapi.json")).and_then(routes::openapi))
+        .or(get().and(path("docs")).and_then(routes::docs))
+        .or(get()
+            .and(warp::path!("keys" / String / "status"))
+            .and_then(routes::key_status))
+        .or(post()
+            .and(path("sign_data"))
+            .and(path::end())
+            .and(routes::middleware::ip_ratelimit::limit())
+            .and(routes::middleware::auth::client())
+            .and(body::json())
+            .and_then(routes::sign_data))
+        .or(post()
+            .and(warp::path!("sign_data" / "batch"))
+            .and(routes::middleware::ip_ratelimit::limit())
+            .and(routes::middleware::auth::client())
+            .and(body::content_length_limit(
+                routes::sign_data_batch::MAX_BODY_BYTES,
+            ))
+            .and(body::json())
+            .and_then(routes::sign_data_batch))
+        .or(post()
+            .and(warp::path!("tokens" / "issue"))
+            .and(routes::middleware::ip_ratelimit::limit())
+            .and(routes::middleware::auth::client())
+            .and(body::json())
+            .and_then(routes::issue_tokens))
+        .or(get()
+            .and(warp::path!("tokens" / "pubkey"))
+            .and_then(routes::token_pubkey))
+        .or(post()
+            .and(warp::path!("blind" / "sign"))
+            .and(routes::middleware::ip_ratelimit::limit())
+            .and(routes::middleware::auth::client())
+            .and(body::json())
+            .and_then(routes::blind_sign))
+        .or(get()
+            .and(warp::path!("blind" / "key"))
+            .and_then(routes::blind_key))
+        .or(post()
+            .and(path("evidence_record"))
+            .and(routes::middleware::ip_ratelimit::limit())
+            .and(body::json())
+            .and_then(routes::evidence_record))
         .recover(errors::handle_rejection)
+        .with(warp::log::custom(utils::logging::request_completed));
+
+    request_id::request_id()
+        .and(routes)
+        .map(request_id::with_header)
+        .with(warp::trace(utils::logging::request_span))
 }
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    // auto-loaded: config, logger, db-conn-pool
-    pretty_env_logger::init();
-    lazy_static::initialize(&db_conn::DB_CONN_POOL);
+    let _log_guard = utils::logging::init()?;
+    match cli::Cmd::from_args()? {
+        cli::Cmd::Serve => serve().await,
+        cli::Cmd::Signer => Ok(signer::daemon::run(
+            config::signer_socket()?,
+            config::keypair(),
+            config::pq_keypair(),
+            config::epoch_keys(),
+        )?),
+        cli::Cmd::Migrate => migrate(),
+        cli::Cmd::Keygen => keygen(),
+        cli::Cmd::SplitKey { k, n } => split_key(k, n),
+        cli::Cmd::CombineKey => combine_key(),
+        cli::Cmd::Delegate {
+            root_keyfile,
+            online_pubkey_base64,
+            days,
+            online_pq_pubkey_base64,
+        } => delegate(
+            &root_keyfile,
+            &online_pubkey_base64,
+            days,
+            online_pq_pubkey_base64.as_deref(),
+        ),
+        cli::Cmd::Revoke {
+            issuer_keyfile,
+            revoked_pubkey_base64,
+            reason,
+            compromised_since,
+        } => revoke(
+            &issuer_keyfile,
+            &revoked_pubkey_base64,
+            &reason,
+            compromised_since,
+        ),
+        cli::Cmd::Renew => renew(),
+        cli::Cmd::AccountCreate {
+            name,
+            daily_quota,
+            pow_mode,
+        } => account_create(&name, daily_quota, pow_mode),
+        cli::Cmd::ApiKeyCreate { account_name } => apikey_create(&account_name),
+        cli::Cmd::ApiKeyRevoke { key_id } => apikey_revoke(&key_id),
+    }
+}
+
+// e.g as a deploy job, before the matching build serves
+fn migrate() -> Result<(), anyhow::Error> {
+    match config::storage().migrate()? {
+        true => println!("migrated the {:?} schema", config::storage_backend()),
+        false => println!("{:?} schema already up to date", config::storage_backend()),
+    }
+    Ok(())
+}
+
+fn keygen() -> Result<(), anyhow::Error> {
+    use utils::crypto_sign::KeyPair;
+    let keyfile = config::keyfile_path();
+    let keypair = KeyPair::generate_to_file(keyfile)?;
+    println!(
+        "generated key at {}. pubkey: {}",
+        keyfile.display(),
+        base64::encode(keypair.pubkey().as_bytes())
+    );
+    if let Some(pq_keyfile) = config::pq_keyfile_path() {
+        let pq_keypair = utils::crypto_sign_pq::PqKeyPair::generate_to_file(pq_keyfile)?;
+        println!(
+            "generated post-quantum key at {}. pubkey: {}",
+            pq_keyfile.display(),
+            base64::encode(pq_keypair.pubkey_bytes())
+        );
+    }
+    Ok(())
+}
+
+// prints k-of-n shares of the signing key, one per line, to hand out to custodians
+fn split_key(k: u8, n: u8) -> Result<(), anyhow::Error> {
+    for share in utils::key_shares::split(config::keypair(), k, n)? {
+        println!("{}", share);
+    }
+    Ok(())
+}
+
+// reads shares from stdin, one per line, and restores the key file from them
+fn combine_key() -> Result<(), anyhow::Error> {
+    use std::io::BufRead;
+    let shares = std::io::stdin()
+        .lock()
+        .lines()
+        .filter(|line| !matches!(line, Ok(l) if l.trim().is_empty()))
+        .collect::<Result<Vec<String>, _>>()?;
+    let keypair = utils::key_shares::combine(&shares)?;
+    let keyfile = config::keyfile_path();
+    keypair.to_file(keyfile)?;
+    println!(
+        "restored key at {}. pubkey: {}",
+        keyfile.display(),
+        base64::encode(keypair.pubkey().as_bytes())
+    );
+    Ok(())
+}
+
+// run on the offline machine holding the root key: prints a delegation to save as `delegation_path`
+fn delegate(
+    root_keyfile: &std::path::Path,
+    online_pubkey_base64: &str,
+    days: i64,
+    online_pq_pubkey_base64: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    use utils::crypto_sign::{KeyMode, KeyPair};
+    let root = KeyPair::load(root_keyfile, KeyMode::Strict)?;
+    let online_pubkey =
+        ed25519_dalek::PublicKey::from_bytes(&base64::decode(online_pubkey_base64)?)
+            .map_err(utils::crypto_sign::KpErr::from)?;
+    let online_pq_pubkey = online_pq_pubkey_base64.map(base64::decode).transpose()?;
+    let delegation = utils::delegation::Delegation::issue(
+        &root,
+        &online_pubkey,
+        online_pq_pubkey.as_deref(),
+        chrono::Duration::days(days),
+    )?;
+    println!("{}", serde_json::to_string_pretty(&delegation)?);
+    Ok(())
+}
+
+// signed by the revoked key itself or the root key, saved where the server picks it up
+fn revoke(
+    issuer_keyfile: &std::path::Path,
+    revoked_pubkey_base64: &str,
+    reason: &str,
+    compromised_since: Option<chrono::NaiveDateTime>,
+) -> Result<(), anyhow::Error> {
+    use utils::crypto_sign::{KeyMode, KeyPair};
+    let issuer = KeyPair::load(issuer_keyfile, KeyMode::Strict)?;
+    let revoked = ed25519_dalek::PublicKey::from_bytes(&base64::decode(revoked_pubkey_base64)?)
+        .map_err(utils::crypto_sign::KpErr::from)?;
+    let revocation =
+        utils::revocation::Revocation::issue(&issuer, &revoked, compromised_since, reason)?;
+
+    let dir = config::revocations_dir();
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.json", revocation.fields_signed.key_id));
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?
+        .write_all(serde_json::to_string_pretty(&revocation)?.as_bytes())?;
+    println!("revocation written to {}", path.display());
+    Ok(())
+}
+
+// renews evidence records once, e.g from cron when the server's periodic renewal is disabled
+fn renew() -> Result<(), anyhow::Error> {
+    match utils::evidence::renew_now()? {
+        Some(renewal) => println!("{}", serde_json::to_string_pretty(&renewal)?),
+        None => println!("nothing to renew"),
+    }
+    Ok(())
+}
+
+fn account_create(
+    name: &str,
+    daily_quota: i64,
+    pow_mode: routes::middleware::auth::PowMode,
+) -> Result<(), anyhow::Error> {
+    let mut tx = config::storage().begin(Lock::None)?;
+    let account = tx.insert_account(models::NewAccount {
+        name,
+        pow_mode: pow_mode.as_str(),
+        daily_quota,
+    })?;
+    tx.commit()?;
+    println!("created account {} (id {})", account.name, account.id);
+    Ok(())
+}
+
+// the key is only ever shown here: hand it to the client right away
+fn apikey_create(account_name: &str) -> Result<(), anyhow::Error> {
+    let mut tx = config::storage().begin(Lock::None)?;
+    let account = tx
+        .account_by_name(account_name)?
+        .ok_or_else(|| anyhow::anyhow!("no account named {}", account_name))?;
+    let key = routes::middleware::auth::create_api_key(&mut *tx, &account)?;
+    tx.commit()?;
+    println!("{}", key);
+    Ok(())
+}
+
+fn apikey_revoke(key_id: &str) -> Result<(), anyhow::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    let mut tx = config::storage().begin(Lock::None)?;
+    if !tx.revoke_api_key(key_id, now)? {
+        anyhow::bail!("no active API key {}", key_id);
+    }
+    tx.commit()?;
+    println!("revoked API key {}", key_id);
+    Ok(())
+}
+
+async fn serve() -> Result<(), anyhow::Error> {
+    // auto-loaded: config, logger, storage, signer
+    let storage = config::storage();
+    // a mismatched schema would only show up as failing queries
+    if config::migrate_on_startup() && storage.migrate()? {
+        info!("migrated the {:?} schema", config::storage_backend());
+    }
+    storage.check_schema()?;
+    config::signer();
+    config::delegation();
+    config::jwt_issuers();
+    config::token_key();
+    // loaded before serving: a signing request mustn't be the first to find it unreadable
+    utils::revocation::current();
+    if let Some(port) = config::roughtime_port() {
+        let socket = roughtime::server::bind(("0.0.0.0", port))?;
+        let longterm_key = config::keypair();
+        std::thread::spawn(move || {
+            if let Err(e) = roughtime::server::run(socket, longterm_key) {
+                error!("roughtime server stopped: {}", e);
+            }
+        });
+    }
+    if let Some(period) = config::renewal_interval() {
+        tokio::spawn(utils::evidence::renew_periodically(period));
+    }
+    tokio::spawn(config::clock_guard().refresh_periodically());
 
     let addr: SocketAddr = ([0, 0, 0, 0], config::port()).into();
     info!("Listening on http://{}", addr);
@@ -1,7 +1,87 @@
+table! {
+    account_usage (account_id, day) {
+        account_id -> Int8,
+        day -> Date,
+        count -> Int8,
+    }
+}
+
+table! {
+    accounts (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        name -> Varchar,
+        pow_mode -> Varchar,
+        daily_quota -> Int8,
+    }
+}
+
+table! {
+    api_keys (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        account_id -> Int8,
+        key_id -> Varchar,
+        secret_hash_b64 -> Varchar,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    evidence_renewals (id) {
+        id -> Int8,
+        created_at -> Timestamp,
+        merkle_root_b64 -> Varchar,
+        leaf_count -> Int8,
+        algorithm -> Varchar,
+        signature_b64 -> Text,
+        signature_pq_b64 -> Nullable<Text>,
+        delegation_json -> Nullable<Text>,
+        previous_path_json -> Nullable<Text>,
+    }
+}
+
 table! {
     signed_data (id) {
         id -> Int8,
         created_at -> Timestamp,
         data_hash_b64 -> Varchar,
+        receipt_hash_b64 -> Nullable<Varchar>,
+        renewal_id -> Nullable<Int8>,
+        serial -> Int8,
+        prev_receipt_hash_b64 -> Nullable<Varchar>,
+        subject_issuer -> Nullable<Varchar>,
+        subject -> Nullable<Varchar>,
+        receipt_json -> Nullable<Text>,
+    }
+}
+
+table! {
+    spent_tokens (nonce_hash_b64) {
+        nonce_hash_b64 -> Varchar,
+        spent_at -> Timestamp,
+    }
+}
+
+table! {
+    subject_usage (issuer, subject, day) {
+        issuer -> Varchar,
+        subject -> Varchar,
+        day -> Date,
+        count -> Int8,
     }
 }
+
+joinable!(account_usage -> accounts (account_id));
+joinable!(api_keys -> accounts (account_id));
+joinable!(signed_data -> evidence_renewals (renewal_id));
+
+allow_tables_to_appear_in_same_query!(
+    account_usage,
+    accounts,
+    api_keys,
+    evidence_renewals,
+    signed_data,
+    spent_tokens,
+    subject_usage,
+);
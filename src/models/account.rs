@@ -0,0 +1,41 @@
+use chrono::NaiveDateTime;
+//
+use super::__generated_schema::{accounts, api_keys};
+
+/// A client of the API, identified by its API keys
+#[derive(Queryable, Serialize, Deserialize, Clone, Debug)]
+pub struct Account {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    //
+    pub name: String,
+    pub pow_mode: String, // see `PowMode`
+    pub daily_quota: i64,
+}
+
+#[derive(Insertable)]
+#[table_name = "accounts"]
+pub struct NewAccount<'a> {
+    pub name: &'a str,
+    pub pow_mode: &'a str,
+    pub daily_quota: i64,
+}
+
+#[derive(Queryable, Serialize, Deserialize, Clone, Debug)]
+pub struct ApiKey {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    //
+    pub account_id: i64,
+    pub key_id: String,
+    pub secret_hash_b64: String,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "api_keys"]
+pub struct NewApiKey<'a> {
+    pub account_id: i64,
+    pub key_id: &'a str,
+    pub secret_hash_b64: &'a str,
+}
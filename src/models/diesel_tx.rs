@@ -0,0 +1,122 @@
+//! What the SQL backends share: a transaction on a pooled connection, and the queries diesel
+//! builds alike for Postgres and SQLite. Inserts and upserts differ: SQLite has no RETURNING.
+//! Expanded in each backend's module, with its imports in scope
+
+/// The `$tx` struct on a pooled `$conn`, rolled back when dropped uncommitted
+macro_rules! diesel_tx {
+    ($tx:ident, $conn:ty) => {
+        /// Rolled back when dropped uncommitted
+        struct $tx {
+            conn: r2d2::PooledConnection<ConnectionManager<$conn>>,
+            open: bool,
+        }
+        impl Drop for $tx {
+            fn drop(&mut self) {
+                if self.open {
+                    if let Err(e) = self.conn.batch_execute("ROLLBACK") {
+                        error!("rollback failed: {}", e);
+                    }
+                }
+            }
+        }
+    };
+}
+
+/// The `Tx` methods written the same for both backends, in their `impl Tx`
+macro_rules! shared_queries {
+    () => {
+        fn commit(mut self: Box<Self>) -> Result<(), ModelErr> {
+            self.open = false;
+            Ok(self.conn.batch_execute("COMMIT")?)
+        }
+
+        fn last_receipt(&mut self) -> Result<Option<SignedData>, ModelErr> {
+            Ok(signedDataTable
+                .order(signed_data::serial.desc())
+                .first(&*self.conn)
+                .optional()?)
+        }
+        fn receipt_by_data_hash(&mut self, hash_b64: &str) -> Result<Option<SignedData>, ModelErr> {
+            Ok(signedDataTable
+                .filter(signed_data::data_hash_b64.eq(hash_b64))
+                .order(signed_data::serial.asc())
+                .first(&*self.conn)
+                .optional()?)
+        }
+        fn unrenewed_receipts(&mut self) -> Result<Vec<SignedData>, ModelErr> {
+            Ok(signedDataTable
+                .filter(signed_data::renewal_id.is_null())
+                .filter(signed_data::receipt_hash_b64.is_not_null())
+                .order(signed_data::id.asc())
+                .load(&*self.conn)?)
+        }
+        fn receipts_renewed_by(&mut self, renewal_id: i64) -> Result<Vec<SignedData>, ModelErr> {
+            Ok(signedDataTable
+                .filter(signed_data::renewal_id.eq(renewal_id))
+                .order(signed_data::id.asc())
+                .load(&*self.conn)?)
+        }
+        fn set_renewal(&mut self, ids: &[i64], renewal_id: i64) -> Result<usize, ModelErr> {
+            Ok(
+                diesel::update(signedDataTable.filter(signed_data::id.eq_any(ids)))
+                    .set(signed_data::renewal_id.eq(renewal_id))
+                    .execute(&*self.conn)?,
+            )
+        }
+
+        fn latest_renewal(&mut self) -> Result<Option<EvidenceRenewal>, ModelErr> {
+            Ok(evidenceRenewalsTable
+                .order(evidence_renewals::id.desc())
+                .first(&*self.conn)
+                .optional()?)
+        }
+        fn previous_renewal(&mut self, id: i64) -> Result<Option<EvidenceRenewal>, ModelErr> {
+            Ok(evidenceRenewalsTable
+                .filter(evidence_renewals::id.lt(id))
+                .order(evidence_renewals::id.desc())
+                .first(&*self.conn)
+                .optional()?)
+        }
+        fn renewals_since(&mut self, id: i64) -> Result<Vec<EvidenceRenewal>, ModelErr> {
+            Ok(evidenceRenewalsTable
+                .filter(evidence_renewals::id.ge(id))
+                .order(evidence_renewals::id.asc())
+                .load(&*self.conn)?)
+        }
+
+        fn account_by_name(&mut self, name: &str) -> Result<Option<Account>, ModelErr> {
+            Ok(accountsTable
+                .filter(accounts::name.eq(name))
+                .first(&*self.conn)
+                .optional()?)
+        }
+        fn account_by_key_id(
+            &mut self,
+            key_id: &str,
+        ) -> Result<Option<(ApiKey, Account)>, ModelErr> {
+            Ok(apiKeysTable
+                .inner_join(accountsTable)
+                .filter(api_keys::key_id.eq(key_id))
+                .filter(api_keys::revoked_at.is_null())
+                .first(&*self.conn)
+                .optional()?)
+        }
+        fn revoke_api_key(&mut self, key_id: &str, at: NaiveDateTime) -> Result<bool, ModelErr> {
+            let updated = diesel::update(
+                apiKeysTable
+                    .filter(api_keys::key_id.eq(key_id))
+                    .filter(api_keys::revoked_at.is_null()),
+            )
+            .set(api_keys::revoked_at.eq(at))
+            .execute(&*self.conn)?;
+            Ok(updated > 0)
+        }
+
+        fn spend_token(&mut self, nonce_hash_b64: &str) -> Result<(), ModelErr> {
+            diesel::insert_into(spentTokensTable)
+                .values(spent_tokens::nonce_hash_b64.eq(nonce_hash_b64))
+                .execute(&*self.conn)?;
+            Ok(())
+        }
+    };
+}
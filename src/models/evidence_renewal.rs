@@ -0,0 +1,35 @@
+use chrono::NaiveDateTime;
+//
+use super::__generated_schema::evidence_renewals;
+
+/// A signed Merkle root over the receipts issued since the previous renewal, and that renewal
+#[derive(Queryable, Serialize, Deserialize, Clone)]
+pub struct EvidenceRenewal {
+    pub id: i64,
+    pub created_at: NaiveDateTime, // the signed timestamp
+    //
+    pub merkle_root_b64: String,
+    pub leaf_count: i64,
+    pub algorithm: String,
+    pub signature_b64: String,
+    pub signature_pq_b64: Option<String>,
+    // JSON: the delegation the renewal was signed under, None for the root key, and the Merkle
+    // path of the previous renewal's leaf. None in renewals made before they were kept
+    #[serde(default)]
+    pub delegation_json: Option<String>,
+    #[serde(default)]
+    pub previous_path_json: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "evidence_renewals"]
+pub struct NewEvidenceRenewal<'a> {
+    pub created_at: NaiveDateTime,
+    pub merkle_root_b64: &'a str,
+    pub leaf_count: i64,
+    pub algorithm: &'a str,
+    pub signature_b64: &'a str,
+    pub signature_pq_b64: Option<&'a str>,
+    pub delegation_json: Option<&'a str>,
+    pub previous_path_json: Option<&'a str>,
+}
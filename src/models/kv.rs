@@ -0,0 +1,447 @@
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+//
+use super::migrations::{check_version, SchemaErr};
+use super::storage::{Lock, Storage, Tx};
+use super::{
+    Account, ApiKey, EvidenceRenewal, ModelErr, NewAccount, NewApiKey, NewEvidenceRenewal,
+    NewSignedData, SignedData,
+};
+use crate::utils::db_conn::DbConnErr;
+
+/// The layout this build expects, in the same format as migrations' versions
+pub const SCHEMA_VERSION: &str = "20201027120000";
+const VERSION_KEY: &[u8] = b"meta/schema_version";
+
+/// A sled database in one directory. Transactions run one at a time, whatever their lock:
+/// their writes are buffered, then applied as one atomic batch and flushed to disk on commit
+pub struct KvStorage {
+    db: sled::Db,
+    lock: Mutex<()>,
+}
+impl KvStorage {
+    pub fn open(dir: &Path) -> Result<Self, DbConnErr> {
+        Ok(Self {
+            db: sled::open(dir)?,
+            lock: Mutex::new(()),
+        })
+    }
+}
+impl Storage for KvStorage {
+    fn begin(&self, _lock: Lock) -> Result<Box<dyn Tx + '_>, DbConnErr> {
+        // a panic mid-transaction never reached the store: it is consistent
+        let guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(Box::new(KvTx {
+            db: &self.db,
+            _guard: guard,
+            writes: BTreeMap::new(),
+        }))
+    }
+    fn migrate(&self) -> Result<bool, SchemaErr> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        // the first layout: a fresh store only needs its version recorded
+        let before = schema_version(&self.db)?;
+        if before.is_none() {
+            set_schema_version(&self.db)?;
+        }
+        check_version(schema_version(&self.db)?, SCHEMA_VERSION)?;
+        Ok(before.is_none())
+    }
+    fn check_schema(&self) -> Result<(), SchemaErr> {
+        check_version(schema_version(&self.db)?, SCHEMA_VERSION)
+    }
+    fn ping(&self, _timeout: Duration) -> Result<(), DbConnErr> {
+        self.db.get(VERSION_KEY)?;
+        Ok(())
+    }
+    fn pool_state(&self) -> Option<r2d2::State> {
+        None
+    }
+}
+
+fn schema_version(db: &sled::Db) -> Result<Option<String>, ModelErr> {
+    Ok(db
+        .get(VERSION_KEY)?
+        .map(|v| String::from_utf8_lossy(&v).into_owned()))
+}
+fn set_schema_version(db: &sled::Db) -> Result<(), ModelErr> {
+    db.insert(VERSION_KEY, SCHEMA_VERSION.as_bytes())?;
+    db.flush()?;
+    Ok(())
+}
+
+/// `<table>/`, then each part: numbers big-endian so keys sort by them, strings length-prefixed
+#[derive(Clone)]
+struct Key(Vec<u8>);
+impl Key {
+    fn table(name: &str) -> Self {
+        let mut key = name.as_bytes().to_vec();
+        key.push(b'/');
+        Key(key)
+    }
+    fn id(mut self, id: i64) -> Self {
+        self.0.extend_from_slice(&encode_i64(id));
+        self
+    }
+    fn str(mut self, s: &str) -> Self {
+        self.0.extend_from_slice(&(s.len() as u32).to_be_bytes());
+        self.0.extend_from_slice(s.as_bytes());
+        self
+    }
+}
+// ids, counters, and index values
+fn encode_i64(n: i64) -> Vec<u8> {
+    (n as u64).to_be_bytes().to_vec()
+}
+// from the last 8 bytes: index keys end with the id
+fn decode_i64(bytes: &[u8]) -> Result<i64, ModelErr> {
+    if bytes.len() < 8 {
+        return Err(ModelErr::Corrupt(format!(
+            "{} bytes for an integer, expected 8",
+            bytes.len()
+        )));
+    }
+    let mut array = [0u8; 8];
+    array.copy_from_slice(&bytes[bytes.len() - 8..]);
+    Ok(u64::from_be_bytes(array) as i64)
+}
+// an index entry pointing to a missing row
+fn missing() -> ModelErr {
+    ModelErr::Corrupt("an index entry points to a missing row".to_string())
+}
+
+/// Rolled back when dropped uncommitted: nothing was written yet.
+/// Ids come from sequences written with the rows, so they have no gaps
+struct KvTx<'a> {
+    db: &'a sled::Db,
+    _guard: MutexGuard<'a, ()>,
+    // None for removed keys
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+impl KvTx<'_> {
+    fn get(&self, key: &Key) -> Result<Option<Vec<u8>>, ModelErr> {
+        match self.writes.get(&key.0) {
+            Some(value) => Ok(value.clone()),
+            None => Ok(self.db.get(&key.0)?.map(|v| v.to_vec())),
+        }
+    }
+    fn put(&mut self, key: Key, value: Vec<u8>) {
+        self.writes.insert(key.0, Some(value));
+    }
+    fn remove(&mut self, key: Key) {
+        self.writes.insert(key.0, None);
+    }
+    fn get_record<T: DeserializeOwned>(&self, key: &Key) -> Result<Option<T>, ModelErr> {
+        match self.get(key)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+    fn put_record<T: Serialize>(&mut self, key: Key, record: &T) -> Result<(), ModelErr> {
+        self.put(key, serde_json::to_vec(record)?);
+        Ok(())
+    }
+    fn next_id(&mut self, table: &str) -> Result<i64, ModelErr> {
+        let key = Key::table("seq").str(table);
+        let id = self.get(&key)?.map_or(Ok(0), |v| decode_i64(&v))? + 1;
+        self.put(key, encode_i64(id));
+        Ok(id)
+    }
+    /// The keys under `prefix`, in order
+    fn scan_keys(&self, prefix: &Key) -> Result<Vec<Vec<u8>>, ModelErr> {
+        let mut keys = BTreeSet::new();
+        for entry in self.db.scan_prefix(&prefix.0) {
+            keys.insert(entry?.0.to_vec());
+        }
+        for (key, value) in self.written(prefix) {
+            match value {
+                Some(_) => keys.insert(key.clone()),
+                None => keys.remove(key),
+            };
+        }
+        Ok(keys.into_iter().collect())
+    }
+    /// The value of the last key under `prefix`
+    fn last(&self, prefix: &Key) -> Result<Option<Vec<u8>>, ModelErr> {
+        let mut stored = self.db.scan_prefix(&prefix.0);
+        // overwritten or removed keys are the writes' to answer
+        let stored = loop {
+            match stored.next_back().transpose()? {
+                Some((key, _)) if self.writes.contains_key(&*key) => continue,
+                entry => break entry.map(|(key, value)| (key.to_vec(), value.to_vec())),
+            }
+        };
+        let written = self
+            .written(prefix)
+            .filter_map(|(key, value)| Some((key.clone(), value.clone()?)))
+            .last();
+        Ok(match (stored, written) {
+            (Some(stored), Some(written)) => Some(std::cmp::max(stored, written).1),
+            (stored, written) => stored.or(written).map(|(_, value)| value),
+        })
+    }
+    fn written<'b>(
+        &'b self,
+        prefix: &'b Key,
+    ) -> impl Iterator<Item = (&'b Vec<u8>, &'b Option<Vec<u8>>)> + 'b {
+        self.writes
+            .range(prefix.0.clone()..)
+            .take_while(move |(key, _)| key.starts_with(&prefix.0))
+    }
+    fn receipt(&self, id: i64) -> Result<SignedData, ModelErr> {
+        self.get_record(&Key::table("receipt").id(id))?
+            .ok_or_else(missing)
+    }
+    fn api_key(&self, key_id: &str) -> Result<Option<ApiKey>, ModelErr> {
+        match self.get(&Key::table("api_key_id").str(key_id))? {
+            Some(id) => self.get_record(&Key::table("api_key").id(decode_i64(&id)?)),
+            None => Ok(None),
+        }
+    }
+    fn count_usage(&mut self, key: Key, requests: i64) -> Result<i64, ModelErr> {
+        let count = self.get(&key)?.map_or(Ok(0), |v| decode_i64(&v))? + requests;
+        self.put(key, encode_i64(count));
+        Ok(count)
+    }
+}
+impl Tx for KvTx<'_> {
+    fn commit(self: Box<Self>) -> Result<(), ModelErr> {
+        let tx = *self;
+        let mut batch = sled::Batch::default();
+        for (key, value) in tx.writes {
+            match value {
+                Some(value) => batch.insert(key, value),
+                None => batch.remove(key),
+            }
+        }
+        // all or nothing, and on disk before the caller answers
+        tx.db.apply_batch(batch)?;
+        tx.db.flush()?;
+        Ok(())
+    }
+
+    fn last_receipt(&mut self) -> Result<Option<SignedData>, ModelErr> {
+        match self.last(&Key::table("receipt_serial"))? {
+            Some(id) => Ok(Some(self.receipt(decode_i64(&id)?)?)),
+            None => Ok(None),
+        }
+    }
+    fn receipt_by_data_hash(&mut self, hash_b64: &str) -> Result<Option<SignedData>, ModelErr> {
+        // keys end with the serial: the first is the earliest
+        let keys = self.scan_keys(&Key::table("receipt_hash").str(hash_b64))?;
+        match keys.first() {
+            Some(key) => {
+                let serial = decode_i64(key)?;
+                let id = self
+                    .get(&Key::table("receipt_serial").id(serial))?
+                    .ok_or_else(missing)?;
+                Ok(Some(self.receipt(decode_i64(&id)?)?))
+            }
+            None => Ok(None),
+        }
+    }
+    fn insert_receipt(&mut self, new: NewSignedData) -> Result<SignedData, ModelErr> {
+        let serial_key = Key::table("receipt_serial").id(new.serial);
+        if self.get(&serial_key)?.is_some() {
+            return Err(ModelErr::AlreadyExists(format!("serial {}", new.serial)));
+        }
+        let row = SignedData {
+            id: self.next_id("receipt")?,
+            created_at: new.created_at.unwrap_or_else(now),
+            data_hash_b64: new.data_hash_b64.to_string(),
+            receipt_hash_b64: new.receipt_hash_b64.map(str::to_string),
+            renewal_id: None,
+            serial: new.serial,
+            prev_receipt_hash_b64: new.prev_receipt_hash_b64.map(str::to_string),
+            subject_issuer: new.subject_issuer.map(str::to_string),
+            subject: new.subject.map(str::to_string),
+            receipt_json: new.receipt_json.map(str::to_string),
+        };
+        self.put_record(Key::table("receipt").id(row.id), &row)?;
+        self.put(serial_key, encode_i64(row.id));
+        self.put(
+            Key::table("receipt_hash")
+                .str(&row.data_hash_b64)
+                .id(row.serial),
+            vec![],
+        );
+        if row.receipt_hash_b64.is_some() {
+            self.put(Key::table("unrenewed").id(row.id), vec![]);
+        }
+        Ok(row)
+    }
+    fn unrenewed_receipts(&mut self) -> Result<Vec<SignedData>, ModelErr> {
+        self.scan_keys(&Key::table("unrenewed"))?
+            .iter()
+            .map(|key| self.receipt(decode_i64(key)?))
+            .collect()
+    }
+    fn receipts_renewed_by(&mut self, renewal_id: i64) -> Result<Vec<SignedData>, ModelErr> {
+        self.scan_keys(&Key::table("renewed").id(renewal_id))?
+            .iter()
+            .map(|key| self.receipt(decode_i64(key)?))
+            .collect()
+    }
+    fn set_renewal(&mut self, ids: &[i64], renewal_id: i64) -> Result<usize, ModelErr> {
+        let mut updated = 0;
+        for &id in ids {
+            let key = Key::table("receipt").id(id);
+            let mut row: SignedData = match self.get_record(&key)? {
+                Some(row) => row,
+                None => continue,
+            };
+            if let Some(previous) = row.renewal_id {
+                self.remove(Key::table("renewed").id(previous).id(id));
+            }
+            self.remove(Key::table("unrenewed").id(id));
+            self.put(Key::table("renewed").id(renewal_id).id(id), vec![]);
+            row.renewal_id = Some(renewal_id);
+            self.put_record(key, &row)?;
+            updated += 1;
+        }
+        Ok(updated)
+    }
+
+    fn latest_renewal(&mut self) -> Result<Option<EvidenceRenewal>, ModelErr> {
+        match self.last(&Key::table("renewal"))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+    fn previous_renewal(&mut self, id: i64) -> Result<Option<EvidenceRenewal>, ModelErr> {
+        self.get_record(&Key::table("renewal").id(id - 1))
+    }
+    fn renewals_since(&mut self, id: i64) -> Result<Vec<EvidenceRenewal>, ModelErr> {
+        let mut renewals = vec![];
+        while let Some(renewal) =
+            self.get_record(&Key::table("renewal").id(id + renewals.len() as i64))?
+        {
+            renewals.push(renewal);
+        }
+        Ok(renewals)
+    }
+    fn insert_renewal(&mut self, new: NewEvidenceRenewal) -> Result<EvidenceRenewal, ModelErr> {
+        let row = EvidenceRenewal {
+            id: self.next_id("renewal")?,
+            created_at: new.created_at,
+            merkle_root_b64: new.merkle_root_b64.to_string(),
+            leaf_count: new.leaf_count,
+            algorithm: new.algorithm.to_string(),
+            signature_b64: new.signature_b64.to_string(),
+            signature_pq_b64: new.signature_pq_b64.map(str::to_string),
+            delegation_json: new.delegation_json.map(str::to_string),
+            previous_path_json: new.previous_path_json.map(str::to_string),
+        };
+        self.put_record(Key::table("renewal").id(row.id), &row)?;
+        Ok(row)
+    }
+
+    fn insert_account(&mut self, new: NewAccount) -> Result<Account, ModelErr> {
+        let name_key = Key::table("account_name").str(new.name);
+        if self.get(&name_key)?.is_some() {
+            return Err(ModelErr::AlreadyExists(format!("account {}", new.name)));
+        }
+        let row = Account {
+            id: self.next_id("account")?,
+            created_at: now(),
+            name: new.name.to_string(),
+            pow_mode: new.pow_mode.to_string(),
+            daily_quota: new.daily_quota,
+        };
+        self.put_record(Key::table("account").id(row.id), &row)?;
+        self.put(name_key, encode_i64(row.id));
+        Ok(row)
+    }
+    fn account_by_name(&mut self, name: &str) -> Result<Option<Account>, ModelErr> {
+        match self.get(&Key::table("account_name").str(name))? {
+            Some(id) => self.get_record(&Key::table("account").id(decode_i64(&id)?)),
+            None => Ok(None),
+        }
+    }
+    fn account_by_key_id(&mut self, key_id: &str) -> Result<Option<(ApiKey, Account)>, ModelErr> {
+        match self.api_key(key_id)? {
+            Some(key) if key.revoked_at.is_none() => {
+                let account = self
+                    .get_record(&Key::table("account").id(key.account_id))?
+                    .ok_or_else(missing)?;
+                Ok(Some((key, account)))
+            }
+            _ => Ok(None),
+        }
+    }
+    fn count_account_usage(
+        &mut self,
+        account_id: i64,
+        day: NaiveDate,
+        requests: i64,
+    ) -> Result<i64, ModelErr> {
+        let key = Key::table("account_usage")
+            .id(account_id)
+            .str(&day.to_string());
+        self.count_usage(key, requests)
+    }
+    fn insert_api_key(&mut self, new: NewApiKey) -> Result<ApiKey, ModelErr> {
+        let key_id_key = Key::table("api_key_id").str(new.key_id);
+        if self.get(&key_id_key)?.is_some() {
+            return Err(ModelErr::AlreadyExists(format!("API key {}", new.key_id)));
+        }
+        if self
+            .get(&Key::table("account").id(new.account_id))?
+            .is_none()
+        {
+            return Err(ModelErr::Corrupt(format!("no account {}", new.account_id)));
+        }
+        let row = ApiKey {
+            id: self.next_id("api_key")?,
+            created_at: now(),
+            account_id: new.account_id,
+            key_id: new.key_id.to_string(),
+            secret_hash_b64: new.secret_hash_b64.to_string(),
+            revoked_at: None,
+        };
+        self.put_record(Key::table("api_key").id(row.id), &row)?;
+        self.put(key_id_key, encode_i64(row.id));
+        Ok(row)
+    }
+    fn revoke_api_key(&mut self, key_id: &str, at: NaiveDateTime) -> Result<bool, ModelErr> {
+        match self.api_key(key_id)? {
+            Some(mut key) if key.revoked_at.is_none() => {
+                key.revoked_at = Some(at);
+                self.put_record(Key::table("api_key").id(key.id), &key)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+    fn count_subject_usage(
+        &mut self,
+        issuer: &str,
+        subject: &str,
+        day: NaiveDate,
+        requests: i64,
+    ) -> Result<i64, ModelErr> {
+        let key = Key::table("subject_usage")
+            .str(issuer)
+            .str(subject)
+            .str(&day.to_string());
+        self.count_usage(key, requests)
+    }
+
+    fn spend_token(&mut self, nonce_hash_b64: &str) -> Result<(), ModelErr> {
+        let key = Key::table("spent_token").str(nonce_hash_b64);
+        if self.get(&key)?.is_some() {
+            return Err(ModelErr::AlreadyExists("token".to_string()));
+        }
+        // the value is when it was spent
+        self.put_record(key, &now())
+    }
+}
+
+// as Postgres' `DEFAULT NOW()`, in the server's local time
+fn now() -> NaiveDateTime {
+    Local::now().naive_local()
+}
@@ -0,0 +1,312 @@
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+//
+use super::migrations::SchemaErr;
+use super::storage::{Lock, Storage, Tx};
+use super::{
+    Account, ApiKey, EvidenceRenewal, ModelErr, NewAccount, NewApiKey, NewEvidenceRenewal,
+    NewSignedData, SignedData,
+};
+use crate::utils::db_conn::DbConnErr;
+
+/// Lost on exit. Transactions run one at a time, whatever their lock
+#[derive(Default)]
+pub struct MemStorage {
+    state: Mutex<State>,
+}
+impl Storage for MemStorage {
+    fn begin(&self, _lock: Lock) -> Result<Box<dyn Tx + '_>, DbConnErr> {
+        // a panic mid-transaction was rolled back while unwinding: the state is consistent
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(Box::new(MemTx {
+            state,
+            undo: vec![],
+        }))
+    }
+    fn migrate(&self) -> Result<bool, SchemaErr> {
+        Ok(false)
+    }
+    fn check_schema(&self) -> Result<(), SchemaErr> {
+        Ok(())
+    }
+    fn ping(&self, _timeout: Duration) -> Result<(), DbConnErr> {
+        Ok(())
+    }
+    fn pool_state(&self) -> Option<r2d2::State> {
+        None
+    }
+}
+
+// ids are positions + 1, as rows are never deleted
+#[derive(Default)]
+struct State {
+    receipts: Vec<SignedData>,
+    renewals: Vec<EvidenceRenewal>,
+    accounts: Vec<Account>,
+    api_keys: Vec<ApiKey>,
+    account_usage: HashMap<(i64, NaiveDate), i64>,
+    subject_usage: HashMap<(String, String, NaiveDate), i64>,
+    spent_tokens: HashSet<String>,
+}
+
+type Undo = Box<dyn FnOnce(&mut State)>;
+
+/// Writes go straight to the state, each with its undo: replayed backwards unless committed
+struct MemTx<'a> {
+    state: MutexGuard<'a, State>,
+    undo: Vec<Undo>,
+}
+impl Drop for MemTx<'_> {
+    fn drop(&mut self) {
+        while let Some(undo) = self.undo.pop() {
+            undo(&mut *self.state);
+        }
+    }
+}
+impl Tx for MemTx<'_> {
+    fn commit(mut self: Box<Self>) -> Result<(), ModelErr> {
+        self.undo.clear();
+        Ok(())
+    }
+
+    fn last_receipt(&mut self) -> Result<Option<SignedData>, ModelErr> {
+        Ok(self.state.receipts.iter().max_by_key(|r| r.serial).cloned())
+    }
+    fn receipt_by_data_hash(&mut self, hash_b64: &str) -> Result<Option<SignedData>, ModelErr> {
+        Ok(self
+            .state
+            .receipts
+            .iter()
+            .filter(|r| r.data_hash_b64 == hash_b64)
+            .min_by_key(|r| r.serial)
+            .cloned())
+    }
+    fn insert_receipt(&mut self, new: NewSignedData) -> Result<SignedData, ModelErr> {
+        if self.state.receipts.iter().any(|r| r.serial == new.serial) {
+            return Err(ModelErr::AlreadyExists(format!("serial {}", new.serial)));
+        }
+        let row = SignedData {
+            id: self.state.receipts.len() as i64 + 1,
+            created_at: new.created_at.unwrap_or_else(now),
+            data_hash_b64: new.data_hash_b64.to_string(),
+            receipt_hash_b64: new.receipt_hash_b64.map(str::to_string),
+            renewal_id: None,
+            serial: new.serial,
+            prev_receipt_hash_b64: new.prev_receipt_hash_b64.map(str::to_string),
+            subject_issuer: new.subject_issuer.map(str::to_string),
+            subject: new.subject.map(str::to_string),
+            receipt_json: new.receipt_json.map(str::to_string),
+        };
+        self.state.receipts.push(row.clone());
+        self.undo.push(Box::new(|state: &mut State| {
+            state.receipts.pop();
+        }));
+        Ok(row)
+    }
+    fn unrenewed_receipts(&mut self) -> Result<Vec<SignedData>, ModelErr> {
+        Ok(self
+            .state
+            .receipts
+            .iter()
+            .filter(|r| r.renewal_id.is_none() && r.receipt_hash_b64.is_some())
+            .cloned()
+            .collect())
+    }
+    fn receipts_renewed_by(&mut self, renewal_id: i64) -> Result<Vec<SignedData>, ModelErr> {
+        Ok(self
+            .state
+            .receipts
+            .iter()
+            .filter(|r| r.renewal_id == Some(renewal_id))
+            .cloned()
+            .collect())
+    }
+    fn set_renewal(&mut self, ids: &[i64], renewal_id: i64) -> Result<usize, ModelErr> {
+        let mut previous = vec![];
+        for receipt in self.state.receipts.iter_mut() {
+            if ids.contains(&receipt.id) {
+                previous.push((receipt.id, receipt.renewal_id));
+                receipt.renewal_id = Some(renewal_id);
+            }
+        }
+        let updated = previous.len();
+        self.undo.push(Box::new(move |state: &mut State| {
+            for (id, renewal_id) in previous {
+                state.receipts[id as usize - 1].renewal_id = renewal_id;
+            }
+        }));
+        Ok(updated)
+    }
+
+    fn latest_renewal(&mut self) -> Result<Option<EvidenceRenewal>, ModelErr> {
+        Ok(self.state.renewals.last().cloned())
+    }
+    fn previous_renewal(&mut self, id: i64) -> Result<Option<EvidenceRenewal>, ModelErr> {
+        Ok(self
+            .state
+            .renewals
+            .iter()
+            .rev()
+            .find(|r| r.id < id)
+            .cloned())
+    }
+    fn renewals_since(&mut self, id: i64) -> Result<Vec<EvidenceRenewal>, ModelErr> {
+        Ok(self
+            .state
+            .renewals
+            .iter()
+            .filter(|r| r.id >= id)
+            .cloned()
+            .collect())
+    }
+    fn insert_renewal(&mut self, new: NewEvidenceRenewal) -> Result<EvidenceRenewal, ModelErr> {
+        let row = EvidenceRenewal {
+            id: self.state.renewals.len() as i64 + 1,
+            created_at: new.created_at,
+            merkle_root_b64: new.merkle_root_b64.to_string(),
+            leaf_count: new.leaf_count,
+            algorithm: new.algorithm.to_string(),
+            signature_b64: new.signature_b64.to_string(),
+            signature_pq_b64: new.signature_pq_b64.map(str::to_string),
+            delegation_json: new.delegation_json.map(str::to_string),
+            previous_path_json: new.previous_path_json.map(str::to_string),
+        };
+        self.state.renewals.push(row.clone());
+        self.undo.push(Box::new(|state: &mut State| {
+            state.renewals.pop();
+        }));
+        Ok(row)
+    }
+
+    fn insert_account(&mut self, new: NewAccount) -> Result<Account, ModelErr> {
+        if self.state.accounts.iter().any(|a| a.name == new.name) {
+            return Err(ModelErr::AlreadyExists(format!("account {}", new.name)));
+        }
+        let row = Account {
+            id: self.state.accounts.len() as i64 + 1,
+            created_at: now(),
+            name: new.name.to_string(),
+            pow_mode: new.pow_mode.to_string(),
+            daily_quota: new.daily_quota,
+        };
+        self.state.accounts.push(row.clone());
+        self.undo.push(Box::new(|state: &mut State| {
+            state.accounts.pop();
+        }));
+        Ok(row)
+    }
+    fn account_by_name(&mut self, name: &str) -> Result<Option<Account>, ModelErr> {
+        Ok(self.state.accounts.iter().find(|a| a.name == name).cloned())
+    }
+    fn account_by_key_id(&mut self, key_id: &str) -> Result<Option<(ApiKey, Account)>, ModelErr> {
+        let state = &self.state;
+        Ok(state
+            .api_keys
+            .iter()
+            .find(|k| k.key_id == key_id && k.revoked_at.is_none())
+            .map(|key| {
+                let account = state.accounts[key.account_id as usize - 1].clone();
+                (key.clone(), account)
+            }))
+    }
+    fn count_account_usage(
+        &mut self,
+        account_id: i64,
+        day: NaiveDate,
+        requests: i64,
+    ) -> Result<i64, ModelErr> {
+        let key = (account_id, day);
+        let previous = self.state.account_usage.get(&key).copied();
+        let count = previous.unwrap_or(0) + requests;
+        self.state.account_usage.insert(key, count);
+        self.undo
+            .push(Box::new(move |state: &mut State| match previous {
+                Some(previous) => {
+                    state.account_usage.insert(key, previous);
+                }
+                None => {
+                    state.account_usage.remove(&key);
+                }
+            }));
+        Ok(count)
+    }
+    fn insert_api_key(&mut self, new: NewApiKey) -> Result<ApiKey, ModelErr> {
+        if self.state.api_keys.iter().any(|k| k.key_id == new.key_id) {
+            return Err(ModelErr::AlreadyExists(format!("API key {}", new.key_id)));
+        }
+        if self.state.accounts.len() < new.account_id as usize || new.account_id < 1 {
+            return Err(ModelErr::Corrupt(format!("no account {}", new.account_id)));
+        }
+        let row = ApiKey {
+            id: self.state.api_keys.len() as i64 + 1,
+            created_at: now(),
+            account_id: new.account_id,
+            key_id: new.key_id.to_string(),
+            secret_hash_b64: new.secret_hash_b64.to_string(),
+            revoked_at: None,
+        };
+        self.state.api_keys.push(row.clone());
+        self.undo.push(Box::new(|state: &mut State| {
+            state.api_keys.pop();
+        }));
+        Ok(row)
+    }
+    fn revoke_api_key(&mut self, key_id: &str, at: NaiveDateTime) -> Result<bool, ModelErr> {
+        let key = self
+            .state
+            .api_keys
+            .iter_mut()
+            .find(|k| k.key_id == key_id && k.revoked_at.is_none());
+        match key {
+            Some(key) => {
+                key.revoked_at = Some(at);
+                let id = key.id;
+                self.undo.push(Box::new(move |state: &mut State| {
+                    state.api_keys[id as usize - 1].revoked_at = None;
+                }));
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+    fn count_subject_usage(
+        &mut self,
+        issuer: &str,
+        subject: &str,
+        day: NaiveDate,
+        requests: i64,
+    ) -> Result<i64, ModelErr> {
+        let key = (issuer.to_string(), subject.to_string(), day);
+        let previous = self.state.subject_usage.get(&key).copied();
+        let count = previous.unwrap_or(0) + requests;
+        self.state.subject_usage.insert(key.clone(), count);
+        self.undo
+            .push(Box::new(move |state: &mut State| match previous {
+                Some(previous) => {
+                    state.subject_usage.insert(key, previous);
+                }
+                None => {
+                    state.subject_usage.remove(&key);
+                }
+            }));
+        Ok(count)
+    }
+
+    fn spend_token(&mut self, nonce_hash_b64: &str) -> Result<(), ModelErr> {
+        if !self.state.spent_tokens.insert(nonce_hash_b64.to_string()) {
+            return Err(ModelErr::AlreadyExists("token".to_string()));
+        }
+        let nonce_hash_b64 = nonce_hash_b64.to_string();
+        self.undo.push(Box::new(move |state: &mut State| {
+            state.spent_tokens.remove(&nonce_hash_b64);
+        }));
+        Ok(())
+    }
+}
+
+// as Postgres' `DEFAULT NOW()`, in the server's local time
+fn now() -> NaiveDateTime {
+    Local::now().naive_local()
+}
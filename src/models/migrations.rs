@@ -0,0 +1,37 @@
+use thiserror::Error;
+//
+use super::ModelErr;
+use crate::utils::db_conn::DbConnErr;
+
+/// Err unless `found`, the newest migration applied, is exactly `expected`.
+/// Versions are all 14 digits, as diesel records them: they compare as strings
+pub fn check_version(found: Option<String>, expected: &'static str) -> Result<(), SchemaErr> {
+    match found {
+        Some(v) if v == expected => Ok(()),
+        Some(v) if v.as_str() > expected => Err(SchemaErr::Ahead { found: v, expected }),
+        v => Err(SchemaErr::Behind {
+            found: v.unwrap_or_else(|| "none".to_string()),
+            expected,
+        }),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SchemaErr {
+    #[error("database schema at {found}, this build expects {expected}: run `migrate`, or set migrate_on_startup")]
+    Behind {
+        found: String,
+        expected: &'static str,
+    },
+    #[error("database schema at {found} is newer than this build expects ({expected}): deploy a matching build")]
+    Ahead {
+        found: String,
+        expected: &'static str,
+    },
+    #[error("migration failed: {0}")]
+    Migration(#[from] diesel_migrations::RunMigrationsError),
+    #[error("db conn err: {0}")]
+    DbConn(#[from] DbConnErr),
+    #[error(transparent)]
+    Model(#[from] ModelErr),
+}
@@ -1,17 +1,41 @@
 use chrono::NaiveDateTime;
-use diesel::prelude::*;
-use diesel::PgConnection;
 use thiserror::Error;
 
 mod __generated_schema;
-use __generated_schema::signed_data::{self, dsl::signed_data as signedDataTable};
+// macros for the SQL backends: declared before them
+#[macro_use]
+mod diesel_tx;
+mod account;
+mod evidence_renewal;
+pub mod kv;
+pub mod memory;
+mod migrations;
+pub mod postgres;
+pub mod sqlite;
+mod storage;
+use __generated_schema::signed_data;
+pub use account::{Account, ApiKey, NewAccount, NewApiKey};
+pub use evidence_renewal::{EvidenceRenewal, NewEvidenceRenewal};
+pub use migrations::SchemaErr;
+pub use storage::{connect, Lock, Storage, StorageBackend, Tx};
 
-#[derive(Queryable, Serialize, Deserialize)]
+#[derive(Queryable, Serialize, Deserialize, Clone)]
 pub struct SignedData {
     pub id: i64,
     pub created_at: NaiveDateTime, // Local::now().naive_local()
     //
     pub data_hash_b64: String,
+    // None for receipts issued before evidence records existed: they can't be renewed
+    pub receipt_hash_b64: Option<String>,
+    // The evidence renewal that first covered this receipt, None until the next renewal
+    pub renewal_id: Option<i64>,
+    pub serial: i64,
+    pub prev_receipt_hash_b64: Option<String>,
+    // The SSO user who requested the receipt, if any. Kept server-side, never signed
+    pub subject_issuer: Option<String>,
+    pub subject: Option<String>,
+    // The receipt as returned, to answer resubmissions. None for receipts issued before
+    pub receipt_json: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -19,29 +43,34 @@ pub struct SignedData {
 pub struct NewSignedData<'a> {
     pub data_hash_b64: &'a str,
     pub created_at: Option<NaiveDateTime>,
-}
-impl<'a> NewSignedData<'a> {
-    pub fn insert(self, db_conn: &PgConnection) -> Result<SignedData, ModelErr> {
-        let user: SignedData = diesel::insert_into(signedDataTable)
-            .values(&self)
-            .get_result(db_conn)?;
-        Ok(user.into())
-    }
+    pub receipt_hash_b64: Option<&'a str>,
+    pub serial: i64,
+    pub prev_receipt_hash_b64: Option<&'a str>,
+    pub subject_issuer: Option<&'a str>,
+    pub subject: Option<&'a str>,
+    pub receipt_json: Option<&'a str>,
 }
 
 #[derive(Error, Debug)]
 pub enum ModelErr {
     #[error("already exists: {0}")]
-    AlreadyExists(diesel::result::Error),
+    AlreadyExists(String),
     #[error(transparent)]
     OtherDieselErr(diesel::result::Error),
+    #[error("kv store err: {0}")]
+    Kv(#[from] sled::Error),
+    #[error("kv record err: {0}")]
+    KvRecord(#[from] serde_json::Error),
+    // Stored data that doesn't hold together, e.g an index to a missing row: any backend
+    #[error("corrupt storage: {0}")]
+    Corrupt(String),
 }
 impl From<diesel::result::Error> for ModelErr {
     fn from(e: diesel::result::Error) -> Self {
         use diesel::result::{DatabaseErrorKind, Error as DieselErr};
         match &e {
             DieselErr::DatabaseError(kind, _info) => match kind {
-                DatabaseErrorKind::UniqueViolation => ModelErr::AlreadyExists(e),
+                DatabaseErrorKind::UniqueViolation => ModelErr::AlreadyExists(e.to_string()),
                 _ => ModelErr::OtherDieselErr(e),
             },
             _ => ModelErr::OtherDieselErr(e),
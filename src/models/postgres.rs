@@ -0,0 +1,176 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::sql_types::{Bool, Nullable, Text};
+use std::time::Duration;
+//
+use super::__generated_schema::{
+    account_usage::{self, dsl::account_usage as accountUsageTable},
+    accounts::{self, dsl::accounts as accountsTable},
+    api_keys::{self, dsl::api_keys as apiKeysTable},
+    evidence_renewals::{self, dsl::evidence_renewals as evidenceRenewalsTable},
+    signed_data::{self, dsl::signed_data as signedDataTable},
+    spent_tokens::{self, dsl::spent_tokens as spentTokensTable},
+    subject_usage::{self, dsl::subject_usage as subjectUsageTable},
+};
+use super::migrations::{check_version, SchemaErr};
+use super::storage::{Lock, Storage, Tx};
+use super::{
+    Account, ApiKey, EvidenceRenewal, ModelErr, NewAccount, NewApiKey, NewEvidenceRenewal,
+    NewSignedData, SignedData,
+};
+use crate::utils::db_conn::DbConnErr;
+
+// `migrations/`, compiled in: see build.rs
+embed_migrations!("migrations");
+
+/// The newest migration this build expects, as diesel records versions
+pub const SCHEMA_VERSION: &str = "20201028120000";
+
+type Pool = r2d2::Pool<ConnectionManager<PgConnection>>;
+
+pub struct PgStorage {
+    pool: Pool,
+}
+impl PgStorage {
+    pub fn connect(dsn: &str) -> Result<Self, DbConnErr> {
+        let manager = ConnectionManager::<PgConnection>::new(dsn);
+        Ok(Self {
+            pool: r2d2::Pool::builder().build(manager)?,
+        })
+    }
+}
+impl Storage for PgStorage {
+    fn begin(&self, lock: Lock) -> Result<Box<dyn Tx + '_>, DbConnErr> {
+        let conn = self.pool.get()?;
+        conn.batch_execute("BEGIN").map_err(DbConnErr::Query)?;
+        // open from here: a LOCK that fails or times out is rolled back on drop
+        let tx = PgTx { conn, open: true };
+        // readers aren't blocked by either lock
+        let lock_table = match lock {
+            Lock::None => None,
+            Lock::Chain => Some("LOCK TABLE signed_data IN SHARE ROW EXCLUSIVE MODE"),
+            Lock::Renewals => Some("LOCK TABLE evidence_renewals IN EXCLUSIVE MODE"),
+        };
+        if let Some(lock_table) = lock_table {
+            tx.conn
+                .batch_execute(lock_table)
+                .map_err(DbConnErr::Query)?;
+        }
+        Ok(Box::new(tx))
+    }
+    fn migrate(&self) -> Result<bool, SchemaErr> {
+        let conn = self.pool.get().map_err(DbConnErr::from)?;
+        let before = schema_version(&conn)?;
+        embedded_migrations::run(&*conn)?;
+        check_version(schema_version(&conn)?, SCHEMA_VERSION)?;
+        Ok(before.as_deref() != Some(SCHEMA_VERSION))
+    }
+    fn check_schema(&self) -> Result<(), SchemaErr> {
+        let conn = self.pool.get().map_err(DbConnErr::from)?;
+        check_version(schema_version(&conn)?, SCHEMA_VERSION)
+    }
+    fn ping(&self, timeout: Duration) -> Result<(), DbConnErr> {
+        let conn = self.pool.get_timeout(timeout)?;
+        conn.batch_execute("SELECT 1").map_err(DbConnErr::Query)
+    }
+    fn pool_state(&self) -> Option<r2d2::State> {
+        Some(self.pool.state())
+    }
+}
+
+#[derive(QueryableByName)]
+struct TablePresent {
+    #[sql_type = "Bool"]
+    present: bool,
+}
+#[derive(QueryableByName)]
+struct MigrationVersion {
+    #[sql_type = "Nullable<Text>"]
+    version: Option<String>,
+}
+/// The newest migration applied to the database, None if none was
+fn schema_version(conn: &PgConnection) -> Result<Option<String>, ModelErr> {
+    // the table only exists once migrations ran
+    let present: TablePresent = diesel::sql_query(
+        "SELECT to_regclass('__diesel_schema_migrations') IS NOT NULL AS present",
+    )
+    .get_result(conn)?;
+    if !present.present {
+        return Ok(None);
+    }
+    let row: MigrationVersion =
+        diesel::sql_query("SELECT MAX(version) AS version FROM __diesel_schema_migrations")
+            .get_result(conn)?;
+    Ok(row.version)
+}
+
+diesel_tx!(PgTx, PgConnection);
+impl Tx for PgTx {
+    shared_queries!();
+
+    fn insert_receipt(&mut self, new: NewSignedData) -> Result<SignedData, ModelErr> {
+        Ok(diesel::insert_into(signedDataTable)
+            .values(&new)
+            .get_result(&*self.conn)?)
+    }
+    fn insert_renewal(&mut self, new: NewEvidenceRenewal) -> Result<EvidenceRenewal, ModelErr> {
+        Ok(diesel::insert_into(evidenceRenewalsTable)
+            .values(&new)
+            .get_result(&*self.conn)?)
+    }
+
+    fn insert_account(&mut self, new: NewAccount) -> Result<Account, ModelErr> {
+        Ok(diesel::insert_into(accountsTable)
+            .values(&new)
+            .get_result(&*self.conn)?)
+    }
+    fn count_account_usage(
+        &mut self,
+        account_id: i64,
+        day: NaiveDate,
+        requests: i64,
+    ) -> Result<i64, ModelErr> {
+        Ok(diesel::insert_into(accountUsageTable)
+            .values((
+                account_usage::account_id.eq(account_id),
+                account_usage::day.eq(day),
+                account_usage::count.eq(requests),
+            ))
+            .on_conflict((account_usage::account_id, account_usage::day))
+            .do_update()
+            .set(account_usage::count.eq(account_usage::count + requests))
+            .returning(account_usage::count)
+            .get_result(&*self.conn)?)
+    }
+    fn insert_api_key(&mut self, new: NewApiKey) -> Result<ApiKey, ModelErr> {
+        Ok(diesel::insert_into(apiKeysTable)
+            .values(&new)
+            .get_result(&*self.conn)?)
+    }
+    fn count_subject_usage(
+        &mut self,
+        issuer: &str,
+        subject: &str,
+        day: NaiveDate,
+        requests: i64,
+    ) -> Result<i64, ModelErr> {
+        Ok(diesel::insert_into(subjectUsageTable)
+            .values((
+                subject_usage::issuer.eq(issuer),
+                subject_usage::subject.eq(subject),
+                subject_usage::day.eq(day),
+                subject_usage::count.eq(requests),
+            ))
+            .on_conflict((
+                subject_usage::issuer,
+                subject_usage::subject,
+                subject_usage::day,
+            ))
+            .do_update()
+            .set(subject_usage::count.eq(subject_usage::count + requests))
+            .returning(subject_usage::count)
+            .get_result(&*self.conn)?)
+    }
+}
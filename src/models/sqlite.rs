@@ -0,0 +1,201 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::sql_types::{BigInt, Bool, Date, Nullable, Text};
+use std::path::Path;
+use std::time::Duration;
+//
+use super::__generated_schema::{
+    account_usage::{self, dsl::account_usage as accountUsageTable},
+    accounts::{self, dsl::accounts as accountsTable},
+    api_keys::{self, dsl::api_keys as apiKeysTable},
+    evidence_renewals::{self, dsl::evidence_renewals as evidenceRenewalsTable},
+    signed_data::{self, dsl::signed_data as signedDataTable},
+    spent_tokens::{self, dsl::spent_tokens as spentTokensTable},
+    subject_usage::{self, dsl::subject_usage as subjectUsageTable},
+};
+use super::migrations::{check_version, SchemaErr};
+use super::storage::{Lock, Storage, Tx};
+use super::{
+    Account, ApiKey, EvidenceRenewal, ModelErr, NewAccount, NewApiKey, NewEvidenceRenewal,
+    NewSignedData, SignedData,
+};
+use crate::utils::db_conn::DbConnErr;
+
+// `migrations_sqlite/`, compiled in: the same tables as `migrations/`, in SQLite's dialect
+embed_migrations!("migrations_sqlite");
+
+/// The newest migration this build expects, as diesel records versions
+pub const SCHEMA_VERSION: &str = "20201028120000";
+
+type Pool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
+
+pub struct SqliteStorage {
+    pool: Pool,
+}
+impl SqliteStorage {
+    pub fn open(path: &Path) -> Result<Self, DbConnErr> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let manager = ConnectionManager::<SqliteConnection>::new(path.to_string_lossy());
+        let pool = r2d2::Pool::builder()
+            .connection_customizer(Box::new(Pragmas))
+            .build(manager)?;
+        // persistent: readers don't block the writer
+        pool.get()?
+            .batch_execute("PRAGMA journal_mode = WAL")
+            .map_err(DbConnErr::Query)?;
+        Ok(Self { pool })
+    }
+}
+impl Storage for SqliteStorage {
+    fn begin(&self, lock: Lock) -> Result<Box<dyn Tx + '_>, DbConnErr> {
+        let conn = self.pool.get()?;
+        // one writer at a time: locks take the write lock upfront, instead of failing to upgrade
+        let begin = match lock {
+            Lock::None => "BEGIN",
+            Lock::Chain | Lock::Renewals => "BEGIN IMMEDIATE",
+        };
+        conn.batch_execute(begin).map_err(DbConnErr::Query)?;
+        Ok(Box::new(SqliteTx { conn, open: true }))
+    }
+    fn migrate(&self) -> Result<bool, SchemaErr> {
+        let conn = self.pool.get().map_err(DbConnErr::from)?;
+        let before = schema_version(&conn)?;
+        embedded_migrations::run(&*conn)?;
+        check_version(schema_version(&conn)?, SCHEMA_VERSION)?;
+        Ok(before.as_deref() != Some(SCHEMA_VERSION))
+    }
+    fn check_schema(&self) -> Result<(), SchemaErr> {
+        let conn = self.pool.get().map_err(DbConnErr::from)?;
+        check_version(schema_version(&conn)?, SCHEMA_VERSION)
+    }
+    fn ping(&self, timeout: Duration) -> Result<(), DbConnErr> {
+        let conn = self.pool.get_timeout(timeout)?;
+        conn.batch_execute("SELECT 1").map_err(DbConnErr::Query)
+    }
+    fn pool_state(&self) -> Option<r2d2::State> {
+        Some(self.pool.state())
+    }
+}
+
+// per connection: wait on the write lock rather than fail, and sync each commit to disk
+#[derive(Debug)]
+struct Pragmas;
+impl r2d2::CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for Pragmas {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        conn.batch_execute(
+            "PRAGMA busy_timeout = 5000; PRAGMA foreign_keys = ON; PRAGMA synchronous = FULL;",
+        )
+        .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+#[derive(QueryableByName)]
+struct TablePresent {
+    #[sql_type = "Bool"]
+    present: bool,
+}
+#[derive(QueryableByName)]
+struct MigrationVersion {
+    #[sql_type = "Nullable<Text>"]
+    version: Option<String>,
+}
+/// The newest migration applied to the database, None if none was
+fn schema_version(conn: &SqliteConnection) -> Result<Option<String>, ModelErr> {
+    // the table only exists once migrations ran
+    let present: TablePresent = diesel::sql_query(
+        "SELECT COUNT(*) > 0 AS present FROM sqlite_master
+            WHERE type = 'table' AND name = '__diesel_schema_migrations'",
+    )
+    .get_result(conn)?;
+    if !present.present {
+        return Ok(None);
+    }
+    let row: MigrationVersion =
+        diesel::sql_query("SELECT MAX(version) AS version FROM __diesel_schema_migrations")
+            .get_result(conn)?;
+    Ok(row.version)
+}
+
+diesel_tx!(SqliteTx, SqliteConnection);
+// without RETURNING, inserted rows are read back by the highest id, within the transaction
+impl Tx for SqliteTx {
+    shared_queries!();
+
+    fn insert_receipt(&mut self, new: NewSignedData) -> Result<SignedData, ModelErr> {
+        diesel::insert_into(signedDataTable)
+            .values(&new)
+            .execute(&*self.conn)?;
+        Ok(signedDataTable
+            .order(signed_data::id.desc())
+            .first(&*self.conn)?)
+    }
+    fn insert_renewal(&mut self, new: NewEvidenceRenewal) -> Result<EvidenceRenewal, ModelErr> {
+        diesel::insert_into(evidenceRenewalsTable)
+            .values(&new)
+            .execute(&*self.conn)?;
+        self.latest_renewal()?
+            .ok_or(ModelErr::OtherDieselErr(diesel::result::Error::NotFound))
+    }
+
+    fn insert_account(&mut self, new: NewAccount) -> Result<Account, ModelErr> {
+        diesel::insert_into(accountsTable)
+            .values(&new)
+            .execute(&*self.conn)?;
+        Ok(accountsTable
+            .order(accounts::id.desc())
+            .first(&*self.conn)?)
+    }
+    fn count_account_usage(
+        &mut self,
+        account_id: i64,
+        day: NaiveDate,
+        requests: i64,
+    ) -> Result<i64, ModelErr> {
+        diesel::sql_query(
+            "INSERT INTO account_usage (account_id, day, count) VALUES (?, ?, ?)
+                ON CONFLICT (account_id, day) DO UPDATE SET count = count + excluded.count",
+        )
+        .bind::<BigInt, _>(account_id)
+        .bind::<Date, _>(day)
+        .bind::<BigInt, _>(requests)
+        .execute(&*self.conn)?;
+        Ok(accountUsageTable
+            .filter(account_usage::account_id.eq(account_id))
+            .filter(account_usage::day.eq(day))
+            .select(account_usage::count)
+            .first(&*self.conn)?)
+    }
+    fn insert_api_key(&mut self, new: NewApiKey) -> Result<ApiKey, ModelErr> {
+        diesel::insert_into(apiKeysTable)
+            .values(&new)
+            .execute(&*self.conn)?;
+        Ok(apiKeysTable.order(api_keys::id.desc()).first(&*self.conn)?)
+    }
+    fn count_subject_usage(
+        &mut self,
+        issuer: &str,
+        subject: &str,
+        day: NaiveDate,
+        requests: i64,
+    ) -> Result<i64, ModelErr> {
+        diesel::sql_query(
+            "INSERT INTO subject_usage (issuer, subject, day, count) VALUES (?, ?, ?, ?)
+                ON CONFLICT (issuer, subject, day) DO UPDATE SET count = count + excluded.count",
+        )
+        .bind::<Text, _>(issuer)
+        .bind::<Text, _>(subject)
+        .bind::<Date, _>(day)
+        .bind::<BigInt, _>(requests)
+        .execute(&*self.conn)?;
+        Ok(subjectUsageTable
+            .filter(subject_usage::issuer.eq(issuer))
+            .filter(subject_usage::subject.eq(subject))
+            .filter(subject_usage::day.eq(day))
+            .select(subject_usage::count)
+            .first(&*self.conn)?)
+    }
+}
@@ -0,0 +1,121 @@
+//! Where receipts, renewals, accounts and spent tokens are kept, behind `Storage`.
+//! Each request works in one `Tx`, rolled back unless committed.
+use chrono::{NaiveDate, NaiveDateTime};
+use std::time::Duration;
+//
+use super::kv::KvStorage;
+use super::memory::MemStorage;
+use super::postgres::PgStorage;
+use super::sqlite::SqliteStorage;
+use super::{
+    Account, ApiKey, EvidenceRenewal, ModelErr, NewAccount, NewApiKey, NewEvidenceRenewal,
+    NewSignedData, SchemaErr, SignedData,
+};
+use crate::utils::db_conn::DbConnErr;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    Postgres,
+    // One file, for deployments without a database server
+    Sqlite,
+    // An embedded key-value store in one directory, for single-node appliances
+    Sled,
+    // Lost on exit: for tests and demos
+    Memory,
+}
+
+/// What a transaction serializes against
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lock {
+    // Reads, and single-statement writes
+    None,
+    // Signing: the receipt chain's tip is read, then extended
+    Chain,
+    // Evidence renewals
+    Renewals,
+}
+
+pub trait Storage: Send + Sync {
+    fn begin(&self, lock: Lock) -> Result<Box<dyn Tx + '_>, DbConnErr>;
+    /// Applies pending migrations, then checks the schema. Returns whether any was applied
+    fn migrate(&self) -> Result<bool, SchemaErr>;
+    /// Err unless the schema is exactly the one this build expects
+    fn check_schema(&self) -> Result<(), SchemaErr>;
+    /// Err unless the storage answers within `timeout`
+    fn ping(&self, timeout: Duration) -> Result<(), DbConnErr>;
+    /// None without a connection pool
+    fn pool_state(&self) -> Option<r2d2::State>;
+}
+impl dyn Storage {
+    /// Runs `f` in a transaction holding `lock`, committed if it returns Ok
+    pub fn transaction<T, E, F>(&self, lock: Lock, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut dyn Tx) -> Result<T, E>,
+        E: From<DbConnErr> + From<ModelErr>,
+    {
+        let mut tx = self.begin(lock)?;
+        let value = f(&mut *tx)?;
+        tx.commit()?;
+        Ok(value)
+    }
+}
+
+/// Reads and writes within one transaction
+pub trait Tx {
+    fn commit(self: Box<Self>) -> Result<(), ModelErr>;
+
+    /// The receipt with the highest serial
+    fn last_receipt(&mut self) -> Result<Option<SignedData>, ModelErr>;
+    /// The earliest receipt for the data, which may have been signed again since
+    fn receipt_by_data_hash(&mut self, hash_b64: &str) -> Result<Option<SignedData>, ModelErr>;
+    fn insert_receipt(&mut self, new: NewSignedData) -> Result<SignedData, ModelErr>;
+    /// Receipts not covered by any evidence renewal yet, oldest first
+    fn unrenewed_receipts(&mut self) -> Result<Vec<SignedData>, ModelErr>;
+    /// Receipts first covered by the renewal `renewal_id`, in their Merkle leaves order
+    fn receipts_renewed_by(&mut self, renewal_id: i64) -> Result<Vec<SignedData>, ModelErr>;
+    fn set_renewal(&mut self, ids: &[i64], renewal_id: i64) -> Result<usize, ModelErr>;
+
+    fn latest_renewal(&mut self) -> Result<Option<EvidenceRenewal>, ModelErr>;
+    /// The renewal chained as first leaf into renewal `id`
+    fn previous_renewal(&mut self, id: i64) -> Result<Option<EvidenceRenewal>, ModelErr>;
+    /// Renewal `id` and all later ones, oldest first
+    fn renewals_since(&mut self, id: i64) -> Result<Vec<EvidenceRenewal>, ModelErr>;
+    fn insert_renewal(&mut self, new: NewEvidenceRenewal) -> Result<EvidenceRenewal, ModelErr>;
+
+    fn insert_account(&mut self, new: NewAccount) -> Result<Account, ModelErr>;
+    fn account_by_name(&mut self, name: &str) -> Result<Option<Account>, ModelErr>;
+    /// The account owning the unrevoked key `key_id`, and the key
+    fn account_by_key_id(&mut self, key_id: &str) -> Result<Option<(ApiKey, Account)>, ModelErr>;
+    /// Counts `requests` more requests today, returning today's count so far
+    fn count_account_usage(
+        &mut self,
+        account_id: i64,
+        day: NaiveDate,
+        requests: i64,
+    ) -> Result<i64, ModelErr>;
+    fn insert_api_key(&mut self, new: NewApiKey) -> Result<ApiKey, ModelErr>;
+    /// Returns whether an unrevoked key was found
+    fn revoke_api_key(&mut self, key_id: &str, at: NaiveDateTime) -> Result<bool, ModelErr>;
+    /// Counts `requests` more requests today by an SSO user, returning today's count so far
+    fn count_subject_usage(
+        &mut self,
+        issuer: &str,
+        subject: &str,
+        day: NaiveDate,
+        requests: i64,
+    ) -> Result<i64, ModelErr>;
+
+    /// Marks a token as redeemed. Fails with `ModelErr::AlreadyExists` if it already was.
+    fn spend_token(&mut self, nonce_hash_b64: &str) -> Result<(), ModelErr>;
+}
+
+/// The configured backend. Postgres and SQLite connections are pooled
+pub fn connect() -> Result<Box<dyn Storage>, DbConnErr> {
+    Ok(match crate::config::storage_backend() {
+        StorageBackend::Postgres => Box::new(PgStorage::connect(crate::config::pg_dsn())?),
+        StorageBackend::Sqlite => Box::new(SqliteStorage::open(crate::config::sqlite_path())?),
+        StorageBackend::Sled => Box::new(KvStorage::open(crate::config::sled_dir())?),
+        StorageBackend::Memory => Box::new(MemStorage::default()),
+    })
+}
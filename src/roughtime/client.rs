@@ -0,0 +1,119 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use ed25519_dalek::PublicKey;
+use rand::RngCore;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration as StdDuration;
+//
+use super::{
+    leaf_hash, node_hash, Message, RoughtimeErr, CERT, DELE, DELEGATION_CONTEXT, HASH_LEN, INDX,
+    MAXT, MIDP, MINT, MIN_REQUEST_LEN, NONC, NONCE_LEN, PAD, PATH, PUBK, RADI, RESPONSE_CONTEXT,
+    ROOT, SIG, SREP,
+};
+use crate::utils::crypto_sign;
+
+const QUERY_TIMEOUT: StdDuration = StdDuration::from_secs(2);
+
+/// Authenticated time from a Roughtime server
+#[derive(Debug, Clone, Copy)]
+pub struct RoughTime {
+    pub midpoint: NaiveDateTime, // UTC
+    pub radius: Duration,
+    // local clock when the request was sent and the response received
+    pub sent_at: NaiveDateTime,
+    pub received_at: NaiveDateTime,
+}
+
+/// Queries the server at `addr`, verifying its answer against its long-term `pubkey`
+pub fn query(addr: &str, pubkey: &PublicKey) -> Result<RoughTime, RoughtimeErr> {
+    let target: SocketAddr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or(RoughtimeErr::Malformed)?;
+    let socket = match target {
+        SocketAddr::V4(_) => UdpSocket::bind("0.0.0.0:0")?,
+        SocketAddr::V6(_) => UdpSocket::bind("[::]:0")?,
+    };
+    socket.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    socket.connect(target)?;
+
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+    let request = request(&nonce);
+    let sent_at = Utc::now().naive_utc();
+    socket.send(&request)?;
+    let mut buf = [0u8; 2048];
+    let len = socket.recv(&mut buf)?;
+    let received_at = Utc::now().naive_utc();
+
+    let (midpoint, radius) = verify_response(&buf[..len], &nonce, pubkey)?;
+    Ok(RoughTime {
+        midpoint,
+        radius,
+        sent_at,
+        received_at,
+    })
+}
+
+pub fn request(nonce: &[u8; NONCE_LEN]) -> Vec<u8> {
+    let unpadded_len = Message::new()
+        .with(NONC, nonce.to_vec())
+        .with(PAD, vec![])
+        .encode()
+        .len();
+    Message::new()
+        .with(NONC, nonce.to_vec())
+        .with(PAD, vec![0u8; MIN_REQUEST_LEN - unpadded_len])
+        .encode()
+}
+
+/// The signed midpoint and radius, once the delegation, signature and Merkle path all check out
+pub fn verify_response(
+    response: &[u8],
+    nonce: &[u8; NONCE_LEN],
+    pubkey: &PublicKey,
+) -> Result<(NaiveDateTime, Duration), RoughtimeErr> {
+    let response = Message::decode(response)?;
+
+    let cert = Message::decode(response.get(CERT)?)?;
+    let dele_bytes = cert.get(DELE)?;
+    let dele_sig = [DELEGATION_CONTEXT, dele_bytes].concat();
+    if !crypto_sign::verify(pubkey, &dele_sig, cert.get(SIG)?) {
+        return Err(RoughtimeErr::BadSignature("delegation"));
+    }
+    let dele = Message::decode(dele_bytes)?;
+    let online_key =
+        PublicKey::from_bytes(dele.get(PUBK)?).map_err(|_| RoughtimeErr::BadLength(PUBK))?;
+
+    let srep_bytes = response.get(SREP)?;
+    let srep_sig = [RESPONSE_CONTEXT, srep_bytes].concat();
+    if !crypto_sign::verify(&online_key, &srep_sig, response.get(SIG)?) {
+        return Err(RoughtimeErr::BadSignature("response"));
+    }
+    let srep = Message::decode(srep_bytes)?;
+
+    // from the nonce's leaf up: the low bit of the index tells on which side the node is
+    let mut index = response.get_u32(INDX)?;
+    let mut node = leaf_hash(nonce);
+    for sibling in response.get(PATH)?.chunks(HASH_LEN) {
+        node = match index & 1 {
+            0 => node_hash(&node, sibling),
+            _ => node_hash(sibling, &node),
+        };
+        index >>= 1;
+    }
+    if &node[..] != srep.get(ROOT)? {
+        return Err(RoughtimeErr::BadPath);
+    }
+
+    let midpoint = srep.get_u64(MIDP)? as i64;
+    if midpoint < dele.get_u64(MINT)? as i64 || midpoint > dele.get_u64(MAXT)? as i64 {
+        return Err(RoughtimeErr::OutsideDelegation);
+    }
+    let midpoint = NaiveDateTime::from_timestamp_opt(
+        midpoint.div_euclid(1_000_000),
+        (midpoint.rem_euclid(1_000_000) * 1_000) as u32,
+    )
+    .ok_or(RoughtimeErr::BadLength(MIDP))?;
+    let radius = Duration::microseconds(srep.get_u32(RADI)? as i64);
+    Ok((midpoint, radius))
+}
@@ -0,0 +1,159 @@
+use sha2::{Digest, Sha512};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+//
+use crate::utils::crypto_sign::KpErr;
+
+pub mod client;
+pub mod server;
+
+// Roughtime, as first specified by Google: https://roughtime.googlesource.com/roughtime/+/HEAD/PROTOCOL.md
+// Messages map 4-byte tags to values whose lengths are multiples of 4, all integers little-endian.
+pub type Tag = [u8; 4];
+pub const SIG: Tag = *b"SIG\0";
+pub const NONC: Tag = *b"NONC";
+pub const DELE: Tag = *b"DELE";
+pub const PATH: Tag = *b"PATH";
+pub const RADI: Tag = *b"RADI";
+pub const PUBK: Tag = *b"PUBK";
+pub const MIDP: Tag = *b"MIDP";
+pub const SREP: Tag = *b"SREP";
+pub const MINT: Tag = *b"MINT";
+pub const ROOT: Tag = *b"ROOT";
+pub const CERT: Tag = *b"CERT";
+pub const MAXT: Tag = *b"MAXT";
+pub const INDX: Tag = *b"INDX";
+pub const PAD: Tag = *b"PAD\xff";
+
+const DELEGATION_CONTEXT: &[u8] = b"RoughTime v1 delegation signature--\0";
+const RESPONSE_CONTEXT: &[u8] = b"RoughTime v1 response signature\0";
+pub const DEFAULT_PORT: u16 = 2002;
+/// Requests are padded to this size at least, so responses are never larger than requests
+pub const MIN_REQUEST_LEN: usize = 1024;
+const NONCE_LEN: usize = 64;
+const HASH_LEN: usize = 64;
+
+/// A Roughtime message. Tags are kept sorted by their little-endian value, as on the wire.
+#[derive(Debug, Default, PartialEq)]
+pub struct Message(BTreeMap<u32, Vec<u8>>);
+impl Message {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with(mut self, tag: Tag, value: impl Into<Vec<u8>>) -> Self {
+        let value = value.into();
+        debug_assert!(value.len() % 4 == 0, "roughtime values are 4-byte aligned");
+        self.0.insert(u32::from_le_bytes(tag), value);
+        self
+    }
+    pub fn get(&self, tag: Tag) -> Result<&[u8], RoughtimeErr> {
+        self.0
+            .get(&u32::from_le_bytes(tag))
+            .map(Vec::as_slice)
+            .ok_or(RoughtimeErr::MissingTag(tag))
+    }
+    pub fn get_u32(&self, tag: Tag) -> Result<u32, RoughtimeErr> {
+        let value = self.get(tag)?;
+        Ok(u32::from_le_bytes(
+            value.try_into().map_err(|_| RoughtimeErr::BadLength(tag))?,
+        ))
+    }
+    pub fn get_u64(&self, tag: Tag) -> Result<u64, RoughtimeErr> {
+        let value = self.get(tag)?;
+        Ok(u64::from_le_bytes(
+            value.try_into().map_err(|_| RoughtimeErr::BadLength(tag))?,
+        ))
+    }
+
+    // header: number of tags, offsets of all values but the first, tags. Then values.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&(self.0.len() as u32).to_le_bytes());
+        let mut offset = 0;
+        for value in self.0.values().take(self.0.len().saturating_sub(1)) {
+            offset += value.len() as u32;
+            bytes.extend_from_slice(&offset.to_le_bytes());
+        }
+        for tag in self.0.keys() {
+            bytes.extend_from_slice(&tag.to_le_bytes());
+        }
+        for value in self.0.values() {
+            bytes.extend_from_slice(value);
+        }
+        bytes
+    }
+    pub fn decode(bytes: &[u8]) -> Result<Self, RoughtimeErr> {
+        let word = |i: usize| -> Result<u32, RoughtimeErr> {
+            let w = bytes.get(4 * i..4 * i + 4).ok_or(RoughtimeErr::Malformed)?;
+            Ok(u32::from_le_bytes(w.try_into().expect("4 bytes")))
+        };
+        if bytes.len() % 4 != 0 {
+            return Err(RoughtimeErr::Malformed);
+        }
+        let num_tags = word(0)? as usize;
+        if num_tags == 0 {
+            return Ok(Self::new());
+        }
+        let header_words = 2 * num_tags;
+        if header_words * 4 > bytes.len() {
+            return Err(RoughtimeErr::Malformed);
+        }
+        let values = &bytes[header_words * 4..];
+        let mut offsets = vec![0usize];
+        for i in 1..num_tags {
+            offsets.push(word(i)? as usize);
+        }
+        offsets.push(values.len());
+
+        let mut message = Self::new();
+        let mut prev_tag = None;
+        for i in 0..num_tags {
+            let tag = word(num_tags + i)?;
+            let (start, end) = (offsets[i], offsets[i + 1]);
+            if prev_tag.map_or(false, |prev| prev >= tag) || start > end || start % 4 != 0 {
+                return Err(RoughtimeErr::Malformed);
+            }
+            let value = values.get(start..end).ok_or(RoughtimeErr::Malformed)?;
+            message.0.insert(tag, value.to_vec());
+            prev_tag = Some(tag);
+        }
+        Ok(message)
+    }
+}
+
+// Merkle tree over request nonces, with SHA-512
+fn leaf_hash(nonce: &[u8]) -> [u8; HASH_LEN] {
+    hash(&[&[0x00], nonce])
+}
+fn node_hash(left: &[u8], right: &[u8]) -> [u8; HASH_LEN] {
+    hash(&[&[0x01], left, right])
+}
+fn hash(parts: &[&[u8]]) -> [u8; HASH_LEN] {
+    let mut hasher = Sha512::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    let mut out = [0u8; HASH_LEN];
+    out.copy_from_slice(&hasher.finalize());
+    out
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RoughtimeErr {
+    #[error("malformed message")]
+    Malformed,
+    #[error("missing tag {}", String::from_utf8_lossy(.0))]
+    MissingTag(Tag),
+    #[error("wrong length for tag {}", String::from_utf8_lossy(.0))]
+    BadLength(Tag),
+    #[error("signature doesn't verify: {0}")]
+    BadSignature(&'static str),
+    #[error("nonce isn't in the signed Merkle tree")]
+    BadPath,
+    #[error("midpoint outside the delegation's validity")]
+    OutsideDelegation,
+    #[error("IO err: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("key err: {0}")]
+    Kp(#[from] KpErr),
+}
@@ -0,0 +1,124 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use std::net::{ToSocketAddrs, UdpSocket};
+//
+use super::{
+    leaf_hash, Message, RoughtimeErr, CERT, DELE, DELEGATION_CONTEXT, INDX, MAXT, MIDP, MINT,
+    MIN_REQUEST_LEN, NONC, NONCE_LEN, PATH, PUBK, RADI, RESPONSE_CONTEXT, ROOT, SIG, SREP,
+};
+use crate::utils::crypto_sign::KeyPair;
+
+// online keys are short-lived, and replaced before they expire
+const DELEGATION_VALIDITY: i64 = 48; // hours
+const DELEGATION_RENEW_BEFORE: i64 = 1; // hours
+
+// when the clock isn't checked against reference sources
+const DEFAULT_RADIUS_MS: i64 = 1000;
+
+/// Answers Roughtime requests, signing with an online key certified by `longterm_key`
+pub struct Server {
+    longterm_key: &'static KeyPair,
+    online_key: KeyPair,
+    cert: Vec<u8>,
+    not_after: NaiveDateTime,
+}
+impl Server {
+    pub fn new(longterm_key: &'static KeyPair) -> Self {
+        let (online_key, cert, not_after) = delegate(longterm_key);
+        Self {
+            longterm_key,
+            online_key,
+            cert,
+            not_after,
+        }
+    }
+
+    /// The response to `request`, or None if it doesn't deserve one.
+    /// Nothing is answered while the local clock isn't trusted.
+    pub fn respond(&mut self, request: &[u8]) -> Option<Vec<u8>> {
+        // too short requests could amplify traffic towards a spoofed source
+        if request.len() < MIN_REQUEST_LEN {
+            return None;
+        }
+        let nonce = Message::decode(request).ok()?.get(NONC).ok()?.to_vec();
+        if nonce.len() != NONCE_LEN {
+            return None;
+        }
+        let radius = match crate::config::clock_guard().accuracy() {
+            Ok(accuracy) => accuracy.unwrap_or_else(|| Duration::milliseconds(DEFAULT_RADIUS_MS)),
+            Err(e) => {
+                warn!("roughtime: not answering, {}", e);
+                return None;
+            }
+        };
+        let now = Utc::now().naive_utc();
+        if now + Duration::hours(DELEGATION_RENEW_BEFORE) > self.not_after {
+            *self = Self::new(self.longterm_key);
+        }
+
+        // one request per tree: the root is the nonce's leaf, and the path is empty
+        let srep = Message::new()
+            .with(
+                RADI,
+                (radius.num_microseconds()? as u32).to_le_bytes().to_vec(),
+            )
+            .with(MIDP, (micros(now) as u64).to_le_bytes().to_vec())
+            .with(ROOT, leaf_hash(&nonce).to_vec())
+            .encode();
+        let signature = self
+            .online_key
+            .sign(&[RESPONSE_CONTEXT, &srep[..]].concat());
+        let response = Message::new()
+            .with(SIG, signature.to_vec())
+            .with(PATH, vec![])
+            .with(SREP, srep)
+            .with(CERT, self.cert.clone())
+            .with(INDX, 0u32.to_le_bytes().to_vec());
+        Some(response.encode())
+    }
+}
+
+// a fresh online key, and its certificate signed by the long-term key
+fn delegate(longterm_key: &KeyPair) -> (KeyPair, Vec<u8>, NaiveDateTime) {
+    let online_key = KeyPair::generate();
+    let not_before = Utc::now().naive_utc();
+    let not_after = not_before + Duration::hours(DELEGATION_VALIDITY);
+    let dele = Message::new()
+        .with(MINT, (micros(not_before) as u64).to_le_bytes().to_vec())
+        .with(MAXT, (micros(not_after) as u64).to_le_bytes().to_vec())
+        .with(PUBK, online_key.pubkey().as_bytes().to_vec())
+        .encode();
+    let signature = longterm_key.sign(&[DELEGATION_CONTEXT, &dele[..]].concat());
+    let cert = Message::new()
+        .with(SIG, signature.to_vec())
+        .with(DELE, dele)
+        .encode();
+    (online_key, cert, not_after)
+}
+fn micros(t: NaiveDateTime) -> i64 {
+    t.timestamp() * 1_000_000 + t.timestamp_subsec_micros() as i64
+}
+
+pub fn bind(addr: impl ToSocketAddrs) -> Result<UdpSocket, RoughtimeErr> {
+    Ok(UdpSocket::bind(addr)?)
+}
+
+/// Serves Roughtime on `socket`. Blocks forever.
+pub fn run(socket: UdpSocket, longterm_key: &'static KeyPair) -> Result<(), RoughtimeErr> {
+    info!("Roughtime listening on udp://{}", socket.local_addr()?);
+    let mut server = Server::new(longterm_key);
+    let mut buf = [0u8; 2048];
+    loop {
+        let (len, client) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("roughtime: failed receiving: {}", e);
+                continue;
+            }
+        };
+        if let Some(response) = server.respond(&buf[..len]) {
+            if let Err(e) = socket.send_to(&response, client) {
+                warn!("roughtime: failed answering {}: {}", client, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use schemars::JsonSchema;
+use warp::{reply, Rejection, Reply};
+//
+use super::middleware::auth::{ApiClient, AuthErr, PowMode};
+use super::middleware::pow_ratelimit;
+use super::sign_data::{self, SignDataErr};
+use crate::models::{Lock, ModelErr};
+use crate::utils::blind_sign::{BlindErr, EpochCert};
+use crate::utils::db_conn::DbConnErr;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BlindSignReq {
+    // The current epoch's, as from `GET /blind/key`
+    pub key_id: String,
+    // The blinded message: as many big-endian bytes as the key's modulus
+    pub blinded_base64: String,
+    // Over the blinded message's bytes. Optional for API clients exempt from PoW
+    #[serde(default)]
+    pub pow_proof_base64: Option<String>,
+}
+#[derive(Serialize, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+pub struct BlindSignResp {
+    pub blind_signature_base64: String,
+    pub epoch: EpochCert,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+pub struct BlindKeyResp {
+    pub key_id: String,
+    pub epoch: EpochCert,
+}
+
+pub async fn blind_key() -> Result<impl Reply, Rejection> {
+    let cert = epoch_cert().await?;
+    Ok(reply::json(&BlindKeyResp {
+        key_id: cert.key_id(),
+        epoch: cert,
+    }))
+}
+
+/// Signs a blinded message with the current epoch's key: the data's hash is never seen
+pub async fn blind_sign(
+    client: Option<ApiClient>,
+    bs_req: BlindSignReq,
+) -> Result<impl Reply, Rejection> {
+    let blinded = base64::decode(&bs_req.blinded_base64).map_err(BlindSignErr::B64)?;
+    // checked first: a late request mustn't cost a token or quota
+    let cert = epoch_cert().await?;
+    if cert.key_id() != bs_req.key_id {
+        return Err(BlindSignErr::EpochOver)?;
+    }
+
+    // rate-limit as sign_data, with the blinded message standing for the data
+    let pow_mode = client
+        .as_ref()
+        .map_or(Ok(PowMode::Full), ApiClient::pow_mode)?;
+    if !pow_ratelimit::verify_pow_mode(pow_mode, bs_req.pow_proof_base64.as_deref(), &blinded) {
+        return Err(BlindSignErr::PowRejected)?;
+    }
+    // nor should a message the key can't sign
+    cert.check_blinded(&blinded).map_err(BlindSignErr::from)?;
+    // spent along with the signature, below
+    if let Some(ApiClient::Token(token)) = &client {
+        token.check(blake3::hash(&blinded).as_bytes())?;
+    }
+
+    // charged in the transaction the signature is made in: a refused one costs nothing.
+    // Queries and signing (maybe through the signer socket) block: off the async workers
+    let key_id = bs_req.key_id;
+    let blind_signature = tokio::task::spawn_blocking(move || {
+        crate::config::storage().transaction::<_, BlindSignErr, _>(Lock::None, |tx| {
+            sign_data::charge(tx, client.as_ref())?;
+            Ok(crate::config::signer().blind_sign(&key_id, &blinded)?)
+        })
+    })
+    .await
+    .map_err(|e| BlindSignErr::Task(e.to_string()))??;
+
+    Ok(reply::json(&BlindSignResp {
+        blind_signature_base64: base64::encode(&blind_signature),
+        epoch: cert,
+    }))
+}
+
+// The current epoch's certificate, with our delegation. The signing conditions are checked
+// first: a revoked key or an untrusted clock mustn't certify a new epoch. Keys are generated
+// as epochs end, which takes a while
+async fn epoch_cert() -> Result<EpochCert, BlindSignErr> {
+    tokio::task::spawn_blocking(|| {
+        sign_data::signing_conditions()?;
+        let mut cert = crate::config::signer().blind_epoch()?;
+        cert.delegation = crate::config::delegation().cloned();
+        Ok(cert)
+    })
+    .await
+    .map_err(|e| BlindSignErr::Task(e.to_string()))?
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlindSignErr {
+    #[error("blind signing err: {0}")]
+    Blind(#[from] BlindErr),
+    #[error("base64 err: {0}")]
+    B64(base64::DecodeError),
+    #[error("PoW proof rejected")]
+    PowRejected,
+    #[error("the key's epoch is over")]
+    EpochOver,
+    #[error("task err: {0}")]
+    Task(String),
+    #[error(transparent)]
+    Conditions(#[from] SignDataErr),
+    #[error(transparent)]
+    Auth(#[from] AuthErr),
+    #[error("db conn err: {0}")]
+    DbConn(#[from] DbConnErr),
+    #[error("model err: {0}")]
+    Model(#[from] ModelErr),
+}
+
+impl warp::reject::Reject for BlindSignErr {}
+impl From<BlindSignErr> for Rejection {
+    fn from(e: BlindSignErr) -> Self {
+        warp::reject::custom(e)
+    }
+}
@@ -0,0 +1,24 @@
+use schemars::JsonSchema;
+use warp::{reply, Rejection, Reply};
+//
+use crate::models::Lock;
+use crate::utils::evidence::{self, EvidenceErr};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct EvidenceRecordReq {
+    // As in the receipt's `fields_signed`
+    pub data_hash_base64: String,
+}
+
+pub async fn evidence_record(er_req: EvidenceRecordReq) -> Result<impl Reply, Rejection> {
+    // queries block: off the async workers
+    let record = tokio::task::spawn_blocking(move || {
+        crate::config::storage().transaction(Lock::None, |tx| {
+            evidence::evidence_record(tx, &er_req.data_hash_base64)
+        })
+    })
+    .await
+    .map_err(|e| EvidenceErr::Task(e.to_string()))??;
+
+    Ok(reply::json(&record))
+}
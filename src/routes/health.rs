@@ -0,0 +1,134 @@
+use chrono::Local;
+use schemars::JsonSchema;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use warp::http::StatusCode;
+use warp::{reply, Rejection, Reply};
+//
+use crate::models::SchemaErr;
+
+// a probe shouldn't wait on the pool as long as requests do
+const DB_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize, Debug))]
+pub struct HealthResp {
+    pub status: HealthStatus,
+    // Empty for liveness
+    #[cfg_attr(test, serde(default))]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub components: BTreeMap<String, ComponentHealth>,
+}
+#[derive(Serialize, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize, Debug))]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Ok,
+    Fail,
+}
+
+/// The process is up and serving: restart it otherwise
+pub async fn health_live() -> Result<impl Reply, Rejection> {
+    Ok(reply::json(&HealthResp {
+        status: HealthStatus::Ok,
+        components: BTreeMap::new(),
+    }))
+}
+
+/// Whether receipts can be issued now, per component: `503` if any can't
+pub async fn health_ready() -> Result<impl Reply, Rejection> {
+    // the checks block on the DB, the signer and clock sources
+    let components = tokio::task::spawn_blocking(check_components)
+        .await
+        .unwrap_or_else(|e| {
+            let mut components = BTreeMap::new();
+            components.insert("checks".to_string(), fail(e.to_string()));
+            components
+        });
+    let status = match components.values().all(|c| c.status == HealthStatus::Ok) {
+        true => HealthStatus::Ok,
+        false => HealthStatus::Fail,
+    };
+    let code = match status {
+        HealthStatus::Ok => StatusCode::OK,
+        HealthStatus::Fail => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    Ok(reply::with_status(
+        reply::json(&HealthResp { status, components }),
+        code,
+    ))
+}
+
+fn check_components() -> BTreeMap<String, ComponentHealth> {
+    let mut components = BTreeMap::new();
+    let (database, migrations) = check_db();
+    components.insert("database".to_string(), database);
+    components.insert("migrations".to_string(), migrations);
+    components.insert("signer".to_string(), check_signer());
+    components.insert("clock".to_string(), check_clock());
+    components
+}
+
+fn check_db() -> (ComponentHealth, ComponentHealth) {
+    let unchecked = || fail("database unreachable".to_string());
+    let storage = crate::config::storage();
+    if let Err(e) = storage.ping(DB_TIMEOUT) {
+        return (fail(e.to_string()), unchecked());
+    }
+    // checked exactly on startup. Since, a newer build may have migrated during a rolling
+    // deploy: its migrations keep serving older builds, which stay ready until replaced
+    let migrations = match storage.check_schema() {
+        Ok(()) => ok(),
+        Err(e @ SchemaErr::Ahead { .. }) => ComponentHealth {
+            status: HealthStatus::Ok,
+            detail: Some(e.to_string()),
+        },
+        Err(e) => fail(e.to_string()),
+    };
+    (ok(), migrations)
+}
+
+// signs and verifies a probe: the key is loaded and, with a signer socket, the signer answers
+fn check_signer() -> ComponentHealth {
+    let signer = crate::config::signer();
+    let probe = blake3::hash(format!("health probe {}", Local::now()).as_bytes());
+    match signer.sign(probe.as_bytes()) {
+        Ok(sig) if signer.verify(probe.as_bytes(), &sig[..]) => ok(),
+        Ok(_) => fail("probe signature didn't verify".to_string()),
+        Err(e) => fail(e.to_string()),
+    }
+}
+
+fn check_clock() -> ComponentHealth {
+    match crate::config::clock_guard().accuracy() {
+        Ok(Some(accuracy)) => ComponentHealth {
+            status: HealthStatus::Ok,
+            detail: Some(format!("accurate to {}ms", accuracy.num_milliseconds())),
+        },
+        Ok(None) => ComponentHealth {
+            status: HealthStatus::Ok,
+            detail: Some("unchecked, no clock_sources".to_string()),
+        },
+        Err(e) => fail(e.to_string()),
+    }
+}
+
+fn ok() -> ComponentHealth {
+    ComponentHealth {
+        status: HealthStatus::Ok,
+        detail: None,
+    }
+}
+fn fail(detail: String) -> ComponentHealth {
+    ComponentHealth {
+        status: HealthStatus::Fail,
+        detail: Some(detail),
+    }
+}
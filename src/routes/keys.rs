@@ -0,0 +1,60 @@
+use schemars::JsonSchema;
+use warp::{reply, Rejection, Reply};
+//
+use crate::utils::crypto_sign;
+use crate::utils::revocation::{self, Revocation};
+
+#[derive(Serialize, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize, Debug))]
+pub struct KeyStatusResp {
+    pub key_id: String,
+    pub status: KeyStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revocation: Option<Revocation>,
+}
+#[derive(Serialize, PartialEq, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize, Debug))]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStatus {
+    Active,
+    Revoked,     // retired: its receipts before revocation stay valid
+    Compromised, // leaked: its receipts from `compromised_since` on are untrusted
+}
+
+pub async fn key_status(key_id: String) -> Result<impl Reply, Rejection> {
+    let (status, revocation) = match revocation::current().get(&key_id) {
+        Some(r) if r.fields_signed.compromised_since.is_some() => {
+            (KeyStatus::Compromised, Some(r.clone()))
+        }
+        Some(r) => (KeyStatus::Revoked, Some(r.clone())),
+        None if is_known(&key_id) => (KeyStatus::Active, None),
+        None => return Err(KeyStatusErr::UnknownKey)?,
+    };
+    let resp = KeyStatusResp {
+        key_id,
+        status,
+        revocation,
+    };
+
+    Ok(reply::json(&resp))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyStatusErr {
+    #[error("unknown key")]
+    UnknownKey,
+}
+impl warp::reject::Reject for KeyStatusErr {}
+impl From<KeyStatusErr> for Rejection {
+    fn from(e: KeyStatusErr) -> Self {
+        warp::reject::custom(e)
+    }
+}
+
+fn is_known(key_id: &str) -> bool {
+    let known = [
+        crate::config::signer().pubkey(),
+        crate::config::trust_anchor(),
+    ];
+    known.iter().any(|k| crypto_sign::key_id(k) == key_id)
+}
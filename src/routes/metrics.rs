@@ -0,0 +1,13 @@
+use warp::{reply, Rejection, Reply};
+//
+use crate::utils::metrics;
+
+/// Prometheus text exposition format
+pub async fn metrics() -> Result<impl Reply, Rejection> {
+    let body = metrics::render()?;
+    Ok(reply::with_header(
+        body,
+        "content-type",
+        "text/plain; version=0.0.4",
+    ))
+}
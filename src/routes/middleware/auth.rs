@@ -0,0 +1,264 @@
+use chrono::Utc;
+use rand::RngCore;
+use std::str::FromStr;
+use subtle::ConstantTimeEq;
+use warp::{Filter, Rejection};
+//
+use crate::models::{Account, Lock, ModelErr, NewApiKey, Tx};
+use crate::utils::db_conn::DbConnErr;
+use crate::utils::jwt::{JwtErr, SsoUser};
+
+const KEY_PREFIX: &str = "cts";
+
+/// How much proof of work an account's requests need
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PowMode {
+    /// Over the data, as for anonymous requests
+    Full,
+    /// Over the data's 32-byte hash: a small, constant cost whatever the data's size
+    Digest,
+    /// None, within the daily quota
+    Exempt,
+}
+impl PowMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PowMode::Full => "full",
+            PowMode::Digest => "digest",
+            PowMode::Exempt => "exempt",
+        }
+    }
+}
+impl FromStr for PowMode {
+    type Err = AuthErr;
+    fn from_str(s: &str) -> Result<Self, AuthErr> {
+        match s {
+            "full" => Ok(PowMode::Full),
+            "digest" => Ok(PowMode::Digest),
+            "exempt" => Ok(PowMode::Exempt),
+            _ => Err(AuthErr::BadPowMode(s.to_string())),
+        }
+    }
+}
+
+/// A client authenticated by its API key, by a bearer token from a configured SSO issuer,
+/// or anonymous but holding a privacy token
+#[derive(Debug, Clone)]
+pub enum ApiClient {
+    Account(Account),
+    Sso(SsoUser),
+    Token(PrivateToken),
+}
+impl ApiClient {
+    /// Fails on an account whose stored mode is unknown: it's never guessed
+    pub fn pow_mode(&self) -> Result<PowMode, AuthErr> {
+        match self {
+            ApiClient::Account(account) => account.pow_mode.parse(),
+            ApiClient::Sso(user) => Ok(user.limits.pow_mode),
+            ApiClient::Token(_) => Ok(PowMode::Exempt),
+        }
+    }
+    /// Counts `requests` against the account's, or SSO user's, daily quota (UTC days).
+    /// Tokens have no quota: they are redeemed instead, see `PrivateToken::redeem`.
+    pub fn consume_quota(&self, requests: i64) -> Result<(), AuthErr> {
+        crate::config::storage().transaction(Lock::None, |tx| self.consume_quota_in(tx, requests))
+    }
+    /// As `consume_quota`, in the caller's transaction: usage rolls back with it
+    pub fn consume_quota_in(&self, db: &mut dyn Tx, requests: i64) -> Result<(), AuthErr> {
+        let today = Utc::now().naive_utc().date();
+        let (count, quota) = match self {
+            ApiClient::Account(account) => (
+                db.count_account_usage(account.id, today, requests)?,
+                account.daily_quota,
+            ),
+            ApiClient::Sso(user) => (
+                db.count_subject_usage(&user.issuer, &user.subject, today, requests)?,
+                user.limits.daily_quota,
+            ),
+            ApiClient::Token(_) => return Err(AuthErr::UnsupportedScheme),
+        };
+        match count > quota {
+            true => Err(AuthErr::QuotaExceeded),
+            false => Ok(()),
+        }
+    }
+    /// The SSO issuer and subject, recorded with receipts
+    pub fn subject(&self) -> Option<(&str, &str)> {
+        match self {
+            ApiClient::Sso(user) => Some((&user.issuer, &user.subject)),
+            _ => None,
+        }
+    }
+}
+
+/// A privacy token presented for redemption (see `utils::tokens`)
+#[derive(Debug, Clone)]
+pub struct PrivateToken {
+    nonce: [u8; 32],
+    mac: [u8; 32],
+}
+impl PrivateToken {
+    /// Spends the token, which must be bound to `message` (for `sign_data`, the data's hash)
+    pub fn redeem(&self, message: &[u8]) -> Result<(), AuthErr> {
+        self.check(message)?;
+        crate::config::storage().transaction(Lock::None, |tx| self.spend_in(tx))
+    }
+    /// Whether the token is valid and bound to `message`, without spending it
+    pub fn check(&self, message: &[u8]) -> Result<(), AuthErr> {
+        let key = crate::config::token_key().ok_or(AuthErr::UnsupportedScheme)?;
+        match key.verify_redemption(&self.nonce, &self.mac, message) {
+            true => Ok(()),
+            false => Err(AuthErr::InvalidPrivateToken),
+        }
+    }
+    /// Spends the token in the caller's transaction: only if it commits
+    pub fn spend_in(&self, db: &mut dyn Tx) -> Result<(), AuthErr> {
+        let nonce_hash_b64 = base64::encode(blake3::hash(&self.nonce).as_bytes());
+        match db.spend_token(&nonce_hash_b64) {
+            Err(ModelErr::AlreadyExists(_)) => Err(AuthErr::TokenSpent),
+            res => Ok(res?),
+        }
+    }
+    // `<nonce_base64>.<mac_base64>`
+    fn parse(s: &str) -> Option<Self> {
+        let decode = |s: &str| {
+            let bytes = base64::decode(s).ok()?;
+            let mut array = [0u8; 32];
+            match bytes.len() {
+                32 => array.copy_from_slice(&bytes),
+                _ => return None,
+            }
+            Some(array)
+        };
+        match s.split('.').collect::<Vec<&str>>()[..] {
+            [nonce, mac] => Some(Self {
+                nonce: decode(nonce)?,
+                mac: decode(mac)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The client from the `Authorization: ApiKey <key>`, `Authorization: Bearer <jwt>`
+/// or `Authorization: PrivateToken <token>` header, None without the header. Invalid credentials are rejected, never downgraded to anonymous requests.
+pub fn client() -> impl Filter<Extract = (Option<ApiClient>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization").and_then(
+        |header: Option<String>| async move {
+            match header {
+                None => Ok(None),
+                // key lookups and JWKS fetches block: off the async workers
+                Some(header) => tokio::task::spawn_blocking(move || authenticate(&header))
+                    .await
+                    .map_err(|e| AuthErr::Task(e.to_string()))
+                    .and_then(|res| res)
+                    .map(Some)
+                    .map_err(Rejection::from),
+            }
+        },
+    )
+}
+fn authenticate(header: &str) -> Result<ApiClient, AuthErr> {
+    match header.trim().splitn(2, ' ').collect::<Vec<&str>>()[..] {
+        [scheme, key] if scheme.eq_ignore_ascii_case("ApiKey") => authenticate_key(key.trim()),
+        [scheme, token] if scheme.eq_ignore_ascii_case("Bearer") => {
+            authenticate_token(token.trim())
+        }
+        [scheme, token] if scheme.eq_ignore_ascii_case("PrivateToken") => {
+            crate::config::token_key().ok_or(AuthErr::UnsupportedScheme)?;
+            PrivateToken::parse(token.trim())
+                .map(ApiClient::Token)
+                .ok_or(AuthErr::InvalidPrivateToken)
+        }
+        _ => Err(AuthErr::UnsupportedScheme),
+    }
+}
+fn authenticate_key(key: &str) -> Result<ApiClient, AuthErr> {
+    let (key_id, secret) = parse_key(key).ok_or(AuthErr::InvalidKey)?;
+    let (api_key, account) = crate::config::storage()
+        .begin(Lock::None)?
+        .account_by_key_id(key_id)?
+        .ok_or(AuthErr::InvalidKey)?;
+    // constant time: timing mustn't tell how much of the hash a guess got right
+    let secret_ok = api_key
+        .secret_hash_b64
+        .as_bytes()
+        .ct_eq(hash_secret(secret).as_bytes());
+    if !bool::from(secret_ok) {
+        return Err(AuthErr::InvalidKey);
+    }
+    Ok(ApiClient::Account(account))
+}
+fn authenticate_token(token: &str) -> Result<ApiClient, AuthErr> {
+    let issuers = crate::config::jwt_issuers().ok_or(AuthErr::UnsupportedScheme)?;
+    match issuers.verify(token) {
+        Ok(user) => Ok(ApiClient::Sso(user)),
+        Err(JwtErr::NoTier) => Err(AuthErr::NoTier),
+        Err(e) => {
+            debug!("bearer token refused: {}", e);
+            Err(AuthErr::InvalidToken)
+        }
+    }
+}
+
+/// Mints a key for `account`, returned in full only here: only its secret's hash is stored.
+/// Keys look like `cts_<key id, 16 hex chars>_<secret, 64 hex chars>`.
+pub fn create_api_key(db: &mut dyn Tx, account: &Account) -> Result<String, AuthErr> {
+    let mut bytes = [0u8; 40];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    let (key_id, secret) = (hex(&bytes[..8]), hex(&bytes[8..]));
+    db.insert_api_key(NewApiKey {
+        account_id: account.id,
+        key_id: &key_id,
+        secret_hash_b64: &hash_secret(&secret),
+    })?;
+    Ok(format!("{}_{}_{}", KEY_PREFIX, key_id, secret))
+}
+fn parse_key(key: &str) -> Option<(&str, &str)> {
+    match key.split('_').collect::<Vec<&str>>()[..] {
+        [KEY_PREFIX, key_id, secret] if key_id.len() == 16 && secret.len() == 64 => {
+            Some((key_id, secret))
+        }
+        _ => None,
+    }
+}
+fn hash_secret(secret: &str) -> String {
+    base64::encode(blake3::hash(secret.as_bytes()).as_bytes())
+}
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AuthErr {
+    #[error("db conn err: {0}")]
+    DbConn(#[from] DbConnErr),
+    #[error("model err: {0}")]
+    Model(#[from] ModelErr),
+    #[error("unsupported authorization scheme")]
+    UnsupportedScheme,
+    #[error("invalid API key")]
+    InvalidKey,
+    #[error("invalid bearer token")]
+    InvalidToken,
+    #[error("bearer token's claims match no tier")]
+    NoTier,
+    #[error("invalid privacy token")]
+    InvalidPrivateToken,
+    #[error("privacy token already spent")]
+    TokenSpent,
+    #[error("daily quota exceeded")]
+    QuotaExceeded,
+    #[error("unknown pow mode {0:?}, expected full, digest or exempt")]
+    BadPowMode(String),
+    #[error("task err: {0}")]
+    Task(String),
+}
+
+impl warp::reject::Reject for AuthErr {}
+impl From<AuthErr> for Rejection {
+    fn from(e: AuthErr) -> Self {
+        warp::reject::custom(e)
+    }
+}
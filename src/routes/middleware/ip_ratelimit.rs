@@ -0,0 +1,243 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use warp::{Filter, Rejection};
+
+// buckets back to full are dropped, at most this often
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+// past this many buckets, the oldest is dropped for each new one: memory stays bounded under floods
+const MAX_BUCKETS: usize = 100_000;
+const IPV4_SUBNET_PREFIX: u8 = 24;
+const IPV6_SUBNET_PREFIX: u8 = 48;
+
+/// Rejects clients over their IP's or subnet's request rate, before anything else is parsed:
+/// PoW doesn't cost anything to requests failing before it's verified.
+/// Requests without a remote address (e.g. in tests) aren't limited.
+pub fn limit() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::addr::remote()
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .and_then(
+            |remote: Option<SocketAddr>, forwarded_for: Option<String>| async move {
+                let limiter = crate::config::rate_limiter();
+                match remote {
+                    Some(remote) => {
+                        let ip = limiter.client_ip(remote.ip(), forwarded_for.as_deref());
+                        limiter.check(ip, Instant::now()).map_err(Rejection::from)
+                    }
+                    None => Ok(()),
+                }
+            },
+        )
+        .untuple_one()
+}
+
+/// Per-IP and per-subnet token buckets
+pub struct RateLimiter {
+    per_ip: Buckets<IpAddr>,
+    per_subnet: Buckets<IpAddr>,
+    trusted_proxies: Vec<Cidr>,
+}
+impl RateLimiter {
+    /// Limits are in requests per minute, also the burst size. 0 disables a limit.
+    pub fn new(per_ip: u32, per_subnet: u32, trusted_proxies: Vec<Cidr>) -> Self {
+        Self {
+            per_ip: Buckets::new(per_ip),
+            per_subnet: Buckets::new(per_subnet),
+            trusted_proxies,
+        }
+    }
+
+    /// `X-Forwarded-For` is only believed when sent by a trusted proxy: the client is the
+    /// rightmost address not itself a trusted proxy, as clients can prepend anything
+    pub fn client_ip(&self, remote: IpAddr, forwarded_for: Option<&str>) -> IpAddr {
+        if !self.is_trusted(remote) {
+            return remote;
+        }
+        let mut client = remote;
+        for hop in forwarded_for.unwrap_or("").rsplit(',') {
+            match IpAddr::from_str(hop.trim()) {
+                Ok(ip) if self.is_trusted(ip) => client = ip,
+                Ok(ip) => return ip,
+                Err(_) => return client,
+            }
+        }
+        client
+    }
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|proxy| proxy.contains(ip))
+    }
+
+    /// Takes a token from both of the client's buckets, or from neither:
+    /// requests refused for their subnet don't drain their IP's bucket
+    pub fn check(&self, ip: IpAddr, now: Instant) -> Result<(), RateLimitErr> {
+        self.per_ip.take(ip, now)?;
+        self.per_subnet.take(subnet(ip), now).map_err(|e| {
+            self.per_ip.refund(ip);
+            e
+        })
+    }
+}
+
+struct Buckets<K> {
+    capacity: f64,
+    per_sec: f64,
+    state: Mutex<BucketsState<K>>,
+}
+struct BucketsState<K> {
+    buckets: HashMap<K, Bucket>,
+    // the same keys, oldest bucket first
+    created: VecDeque<K>,
+    swept_at: Instant,
+}
+#[derive(Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+impl<K: Hash + Eq + Copy> Buckets<K> {
+    fn new(per_min: u32) -> Self {
+        Self {
+            capacity: per_min as f64,
+            per_sec: per_min as f64 / 60.0,
+            state: Mutex::new(BucketsState {
+                buckets: HashMap::new(),
+                created: VecDeque::new(),
+                swept_at: Instant::now(),
+            }),
+        }
+    }
+    fn take(&self, key: K, now: Instant) -> Result<(), RateLimitErr> {
+        if self.capacity == 0.0 {
+            return Ok(());
+        }
+        let mut state = self.state.lock().map_err(|_| RateLimitErr::Poisoned)?;
+        let state = &mut *state;
+        if now.saturating_duration_since(state.swept_at) >= SWEEP_INTERVAL {
+            state
+                .buckets
+                .retain(|_, bucket| self.refilled(bucket, now) < self.capacity);
+            let buckets = &state.buckets;
+            state.created.retain(|key| buckets.contains_key(key));
+            state.swept_at = now;
+        }
+        if !state.buckets.contains_key(&key) {
+            if state.buckets.len() >= MAX_BUCKETS {
+                if let Some(oldest) = state.created.pop_front() {
+                    state.buckets.remove(&oldest);
+                }
+            }
+            state.created.push_back(key);
+        }
+        let bucket = state.buckets.entry(key).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            let wait_secs = (1.0 - bucket.tokens) / self.per_sec;
+            return Err(RateLimitErr::TooManyRequests {
+                retry_after_secs: wait_secs.ceil() as u64,
+            });
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+    // gives back a token just taken
+    fn refund(&self, key: K) {
+        if let Ok(mut state) = self.state.lock() {
+            if let Some(bucket) = state.buckets.get_mut(&key) {
+                bucket.tokens = (bucket.tokens + 1.0).min(self.capacity);
+            }
+        }
+    }
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated);
+        (bucket.tokens + elapsed.as_secs_f64() * self.per_sec).min(self.capacity)
+    }
+}
+
+fn subnet(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask_u32(IPV4_SUBNET_PREFIX))),
+        IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(
+            u128::from(ip) & mask_u128(IPV6_SUBNET_PREFIX),
+        )),
+    }
+}
+fn mask_u32(prefix: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0)
+}
+fn mask_u128(prefix: u8) -> u128 {
+    u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0)
+}
+
+/// An address range, like `10.0.0.0/8` or a single address
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = mask_u32(self.prefix);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = mask_u128(self.prefix);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+    /// Comma-separated ranges, empty for none
+    pub fn parse_list(s: &str) -> Result<Vec<Self>, RateLimitErr> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(Self::from_str)
+            .collect()
+    }
+}
+impl FromStr for Cidr {
+    type Err = RateLimitErr;
+    fn from_str(s: &str) -> Result<Self, RateLimitErr> {
+        let bad = || RateLimitErr::BadCidr(s.to_string());
+        let (addr, prefix) = match s.splitn(2, '/').collect::<Vec<&str>>()[..] {
+            [addr] => (addr, None),
+            [addr, prefix] => (addr, Some(prefix.parse::<u8>().map_err(|_| bad())?)),
+            _ => return Err(bad()),
+        };
+        let addr = IpAddr::from_str(addr).map_err(|_| bad())?;
+        let max_prefix = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        match prefix.unwrap_or(max_prefix) {
+            prefix if prefix <= max_prefix => Ok(Self { addr, prefix }),
+            _ => Err(bad()),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RateLimitErr {
+    #[error("too many requests, retry after {retry_after_secs}s")]
+    TooManyRequests { retry_after_secs: u64 },
+    #[error("invalid address range {0:?}, expected like 10.0.0.0/8")]
+    BadCidr(String),
+    #[error("poisoned lock")]
+    Poisoned,
+}
+
+impl warp::reject::Reject for RateLimitErr {}
+impl From<RateLimitErr> for Rejection {
+    fn from(e: RateLimitErr) -> Self {
+        warp::reject::custom(e)
+    }
+}
@@ -1,6 +1,10 @@
 use byteorder::{BigEndian, ByteOrder};
 use cuckoo::Cuckoo;
 use std::panic;
+use std::time::Instant;
+//
+use super::auth::PowMode;
+use crate::utils::{logging, metrics};
 
 fn newC(data_bytes_len: usize) -> Cuckoo {
     Cuckoo::new(16, 8 * data_bytes_len, 6) // params:(N,M,cycleLen).  difficulty depends on M/N ratio. See Cuckoo paper
@@ -8,14 +12,35 @@ fn newC(data_bytes_len: usize) -> Cuckoo {
 
 pub fn verify_pow(data_base64: &str, pow_proof_base64: &str) -> Result<bool, PowVerifErr> {
     let data_bytes = base64::decode(&data_base64).map_err(PowVerifErr::B64DecodeBody)?;
+    verify_pow_bytes(&data_bytes, pow_proof_base64)
+}
+pub fn verify_pow_bytes(data_bytes: &[u8], pow_proof_base64: &str) -> Result<bool, PowVerifErr> {
     let pow_proof_bytes =
         base64::decode(&pow_proof_base64).map_err(PowVerifErr::B64DecodePowProof)?;
     let pow_proof_vec32 = vec8tovec32(&pow_proof_bytes)?;
 
+    let start = Instant::now();
     let verif_ok = newC(data_bytes.len()).verify(&data_bytes, &pow_proof_vec32);
+    metrics::pow_verified(verif_ok, start.elapsed());
+    logging::record_ms("pow_ms", start.elapsed());
     Ok(verif_ok)
 }
 
+/// Verifies the proof `mode` asks for over `bytes`: over them, over their hash, or none.
+/// Only the proof is decoded: a malformed one is rejected like a wrong one
+pub fn verify_pow_mode(mode: PowMode, pow_proof_base64: Option<&str>, bytes: &[u8]) -> bool {
+    let pow_proof_base64 = match (mode, pow_proof_base64) {
+        (PowMode::Exempt, _) => return true,
+        (_, None) => return false,
+        (_, Some(pow_proof_base64)) => pow_proof_base64,
+    };
+    let res = match mode {
+        PowMode::Digest => verify_pow_bytes(blake3::hash(bytes).as_bytes(), pow_proof_base64),
+        _ => verify_pow_bytes(bytes, pow_proof_base64),
+    };
+    res.unwrap_or(false)
+}
+
 #[cfg(test)] // unwrap is okay for tests
 pub fn solve_pow_proof_b64(data_bytes: &[u8]) -> String {
     let pow_proof_vec32 = newC(data_bytes.len()).solve(&data_bytes).unwrap();
@@ -0,0 +1,35 @@
+use std::convert::Infallible;
+use warp::http::HeaderMap;
+use warp::reply::{self, WithHeader};
+use warp::{Filter, Reply};
+
+pub const HEADER: &str = "x-request-id";
+const MAX_LEN: usize = 128;
+
+/// The client's request id, or a new one if it has none or a malformed one.
+/// Recorded on the request's span, and sent back with the response
+pub fn request_id() -> impl Filter<Extract = (String,), Error = Infallible> + Clone {
+    warp::header::headers_cloned().map(|headers: HeaderMap| {
+        let id = headers
+            .get(HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| is_valid(id))
+            .map(String::from)
+            .unwrap_or_else(|| format!("{:032x}", rand::random::<u128>()));
+        tracing::Span::current().record("request_id", &id.as_str());
+        id
+    })
+}
+
+pub fn with_header<R: Reply>(id: String, reply: R) -> WithHeader<R> {
+    reply::with_header(reply, HEADER, id)
+}
+
+// ids end up in logs: no control characters, spaces or quotes
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+}
@@ -1,9 +1,28 @@
+pub mod blind;
+pub mod evidence;
+pub mod health;
+pub mod keys;
+pub mod metrics;
+pub mod openapi;
 pub mod pubkey;
 pub mod sign_data;
+pub mod sign_data_batch;
+pub mod tokens;
+pub use blind::{blind_key, blind_sign, BlindSignErr, BlindSignReq, BlindSignResp};
+pub use evidence::{evidence_record, EvidenceRecordReq};
+pub use health::{health_live, health_ready, HealthResp};
+pub use keys::{key_status, KeyStatusErr, KeyStatusResp};
+pub use metrics::metrics;
+pub use openapi::{docs, openapi};
 pub use pubkey::{pubkey, PubkeyResp};
 pub use sign_data::{sign_data, SignDataErr, SignDataReq, SignDataResp};
+pub use sign_data_batch::{sign_data_batch, SignBatchErr, SignBatchReq, SignBatchResp};
+pub use tokens::{issue_tokens, token_pubkey, IssueTokensErr, IssueTokensReq, IssueTokensResp};
 pub mod middleware {
+    pub mod auth;
+    pub mod ip_ratelimit;
     pub mod pow_ratelimit;
+    pub mod request_id;
 }
 
 pub async fn getRoot() -> Result<impl warp::Reply, warp::Rejection> {
@@ -0,0 +1,200 @@
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+use warp::{reply, Rejection, Reply};
+//
+use super::blind::{BlindKeyResp, BlindSignReq, BlindSignResp};
+use super::evidence::EvidenceRecordReq;
+use super::health::HealthResp;
+use super::keys::KeyStatusResp;
+use super::pubkey::PubkeyResp;
+use super::sign_data::{SignDataReq, SignDataResp};
+use super::sign_data_batch::{SignBatchReq, SignBatchResp};
+use super::tokens::{IssueTokensReq, IssueTokensResp, TokenPubkeyResp};
+use crate::errors::ErrResp;
+use crate::utils::evidence::EvidenceRecord;
+
+const DOCS_HTML: &str = include_str!("openapi_docs.html");
+
+lazy_static::lazy_static! {
+    static ref SPEC: Value = spec();
+}
+
+pub async fn openapi() -> Result<impl Reply, Rejection> {
+    Ok(reply::json(&*SPEC))
+}
+
+/// Interactive docs for `/openapi.json`
+pub async fn docs() -> Result<impl Reply, Rejection> {
+    Ok(reply::html(DOCS_HTML))
+}
+
+/// The OpenAPI 3 document: schemas come from the request and response types
+pub fn spec() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let gen = &mut gen;
+    // the Authorization schemes accepted where API clients may authenticate
+    let clients = json!([{}, {"apiKey": []}, {"bearer": []}, {"privateToken": []}]);
+
+    let paths = json!({
+        "/pubkey": {
+            "get": op("Get the public key to pin", None, ok::<PubkeyResp>(gen), errors(gen, &[])),
+        },
+        "/sign_data": {
+            "post": with_security(op(
+                "Timestamp and sign data",
+                Some(body::<SignDataReq>(gen)),
+                ok::<SignDataResp>(gen),
+                errors(gen, &[400, 401, 403, 409, 429, 503]),
+            ), &clients),
+        },
+        "/sign_data/batch": {
+            "post": with_security(op(
+                "Timestamp and sign many items, for one PoW",
+                Some(body::<SignBatchReq>(gen)),
+                ok::<SignBatchResp>(gen),
+                errors(gen, &[400, 401, 403, 411, 413, 429, 503]),
+            ), &json!([{}, {"apiKey": []}, {"bearer": []}])),
+        },
+        "/keys/{key_id}/status": {
+            "get": with_params(op(
+                "Get a signing key's status",
+                None,
+                ok::<KeyStatusResp>(gen),
+                errors(gen, &[404]),
+            ), json!([{
+                "name": "key_id",
+                "in": "path",
+                "required": true,
+                "description": "Lowercase hex of the first 16 bytes of the public key's Blake3 hash",
+                "schema": {"type": "string"},
+            }])),
+        },
+        "/evidence_record": {
+            "post": op(
+                "Get a receipt's evidence record",
+                Some(body::<EvidenceRecordReq>(gen)),
+                ok::<EvidenceRecord>(gen),
+                errors(gen, &[400, 404, 429]),
+            ),
+        },
+        "/tokens/issue": {
+            "post": with_security(op(
+                "Get privacy tokens",
+                Some(body::<IssueTokensReq>(gen)),
+                ok::<IssueTokensResp>(gen),
+                errors(gen, &[400, 401, 403, 429]),
+            ), &json!([{}, {"apiKey": []}, {"bearer": []}])),
+        },
+        "/tokens/pubkey": {
+            "get": op("Get the token issuance key", None, ok::<TokenPubkeyResp>(gen), errors(gen, &[])),
+        },
+        "/blind/key": {
+            "get": op("Get the current epoch's blind signing key", None, ok::<BlindKeyResp>(gen), errors(gen, &[])),
+        },
+        "/blind/sign": {
+            "post": with_security(op(
+                "Blindly sign a hash",
+                Some(body::<BlindSignReq>(gen)),
+                ok::<BlindSignResp>(gen),
+                errors(gen, &[400, 401, 403, 409, 429]),
+            ), &clients),
+        },
+        "/health/live": {
+            "get": op("Liveness", None, ok::<HealthResp>(gen), errors(gen, &[])),
+        },
+        "/health/ready": {
+            "get": op("Readiness, per component", None, ok::<HealthResp>(gen), {
+                let mut responses = errors(gen, &[]);
+                responses.insert("503".to_string(), json!({
+                    "description": "Not ready",
+                    "content": {"application/json": {"schema": gen.subschema_for::<HealthResp>()}},
+                }));
+                responses
+            }),
+        },
+        "/metrics": {
+            "get": {
+                "summary": "Prometheus metrics",
+                "responses": {"200": {"description": "OK", "content": {"text/plain": {"schema": {"type": "string"}}}}},
+            },
+        },
+    });
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": gen.definitions(),
+            "securitySchemes": {
+                "apiKey": {
+                    "type": "apiKey",
+                    "in": "header",
+                    "name": "Authorization",
+                    "description": "`ApiKey <key>`",
+                },
+                "bearer": {"type": "http", "scheme": "bearer", "bearerFormat": "JWT"},
+                "privateToken": {
+                    "type": "apiKey",
+                    "in": "header",
+                    "name": "Authorization",
+                    "description": "`PrivateToken <nonce_base64>.<mac_base64>`, redeemed for the data's hash",
+                },
+            },
+        },
+    })
+}
+
+fn op(summary: &str, body: Option<Value>, ok: Value, mut responses: Map<String, Value>) -> Value {
+    responses.insert("200".to_string(), ok);
+    let mut op = json!({ "summary": summary, "responses": responses });
+    if let Some(body) = body {
+        op["requestBody"] = body;
+    }
+    op
+}
+fn with_security(mut op: Value, security: &Value) -> Value {
+    op["security"] = security.clone();
+    op
+}
+fn with_params(mut op: Value, params: Value) -> Value {
+    op["parameters"] = params;
+    op
+}
+fn body<T: JsonSchema>(gen: &mut SchemaGenerator) -> Value {
+    json!({
+        "required": true,
+        "content": {"application/json": {"schema": gen.subschema_for::<T>()}},
+    })
+}
+fn ok<T: JsonSchema>(gen: &mut SchemaGenerator) -> Value {
+    json!({
+        "description": "OK",
+        "content": {"application/json": {"schema": gen.subschema_for::<T>()}},
+    })
+}
+// every route may fail with 500, and answers errors as `ErrResp`
+fn errors(gen: &mut SchemaGenerator, codes: &[u16]) -> Map<String, Value> {
+    let schema = gen.subschema_for::<ErrResp>();
+    codes
+        .iter()
+        .chain(&[500])
+        .map(|code| {
+            let description = warp::http::StatusCode::from_u16(*code)
+                .ok()
+                .and_then(|c| c.canonical_reason())
+                .unwrap_or("Error");
+            (
+                code.to_string(),
+                json!({
+                    "description": description,
+                    "content": {"application/json": {"schema": schema}},
+                }),
+            )
+        })
+        .collect()
+}
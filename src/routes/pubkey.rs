@@ -1,15 +1,35 @@
 use ed25519_dalek::PublicKey;
+use schemars::JsonSchema;
 use warp::{reply, Rejection, Reply};
+//
+use crate::utils::crypto_sign_pq::PQ_SCHEME;
+use crate::utils::delegation::Delegation;
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 #[cfg_attr(test, derive(Deserialize, Debug))]
 pub struct PubkeyResp {
-    pub pubkey: PublicKey,
+    #[schemars(with = "Vec<u8>")]
+    pub pubkey: PublicKey, // The root key when signing is delegated: the one to pin
+    // Post-quantum key, to pin as well when hybrid signatures are enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pubkey_pq_base64: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pq_scheme: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delegation: Option<Delegation>,
 }
 
 pub async fn pubkey() -> Result<impl Reply, Rejection> {
-    let pubkey = crate::config::keypair().pubkey();
-    let resp = PubkeyResp { pubkey };
+    let pubkey = crate::config::trust_anchor();
+    let pubkey_pq_base64 = crate::config::signer().pq_pubkey().map(base64::encode);
+    let pq_scheme = pubkey_pq_base64.as_ref().map(|_| PQ_SCHEME.to_string());
+    let delegation = crate::config::delegation().cloned();
+    let resp = PubkeyResp {
+        pubkey,
+        pubkey_pq_base64,
+        pq_scheme,
+        delegation,
+    };
 
     Ok(reply::json(&resp))
 }
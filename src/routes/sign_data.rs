@@ -1,15 +1,30 @@
-use chrono::{Local, NaiveDateTime};
+use chrono::{Local, NaiveDateTime, TimeZone, Utc};
+use ed25519_dalek::PublicKey;
+use schemars::JsonSchema;
+use std::time::Instant;
 use warp::{reply, Rejection, Reply};
 //
+use super::middleware::auth::{ApiClient, AuthErr, PowMode};
 use super::middleware::pow_ratelimit;
-use crate::models::{ModelErr, NewSignedData};
-use crate::utils::db_conn::{self, DbConnErr};
+use crate::models::{Lock, ModelErr, NewSignedData, SignedData, Tx};
+use crate::signer::SignerErr;
+use crate::utils::clock::ClockErr;
+use crate::utils::crypto_sign;
+use crate::utils::crypto_sign_pq;
+use crate::utils::db_conn::DbConnErr;
+use crate::utils::delegation::Delegation;
+use crate::utils::evidence;
+use crate::utils::logging;
+use crate::utils::metrics::METRICS;
+use crate::utils::revocation::{self, RevocationList};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct SignDataReq {
     // String since HTTP is text only, and we want the server to accept any bytes as data, only encoded as base64
     pub data_base64: String,
-    pub pow_proof_base64: String,
+    // Optional for API clients exempt from PoW
+    #[serde(default)]
+    pub pow_proof_base64: Option<String>,
 }
 impl SignDataReq {
     pub fn data_bytes(&self) -> Result<Vec<u8>, SignDataErr> {
@@ -18,72 +33,437 @@ impl SignDataReq {
     pub fn hash_data(&self) -> Result<blake3::Hash, SignDataErr> {
         Ok(blake3::hash(&self.data_bytes()?))
     }
-    pub fn verify_pow(&self) -> Result<bool, SignDataErr> {
-        pow_ratelimit::verify_pow(&self.data_base64, &self.pow_proof_base64)
-            .map_err(SignDataErr::from)
+    pub fn verify_pow(&self, mode: PowMode, data_hash: &blake3::Hash) -> Result<bool, SignDataErr> {
+        let pow_proof_base64 = match (mode, &self.pow_proof_base64) {
+            (PowMode::Exempt, _) => return Ok(true),
+            (_, None) => return Ok(false),
+            (_, Some(pow_proof_base64)) => pow_proof_base64,
+        };
+        match mode {
+            PowMode::Digest => {
+                pow_ratelimit::verify_pow_bytes(data_hash.as_bytes(), pow_proof_base64)
+            }
+            _ => pow_ratelimit::verify_pow(&self.data_base64, pow_proof_base64),
+        }
+        .map_err(SignDataErr::from)
     }
 }
 
-#[derive(Serialize)]
-#[cfg_attr(test, derive(Deserialize))]
+/// What signing data already signed does
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    // Refused with 409
+    Strict,
+    // The original receipt is returned again
+    Idempotent,
+    // A new receipt is issued, along with the earliest one
+    AllowMultiple,
+}
+impl DuplicatePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DuplicatePolicy::Strict => "strict",
+            DuplicatePolicy::Idempotent => "idempotent",
+            DuplicatePolicy::AllowMultiple => "allow_multiple",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct SignDataResp {
     pub fields_signed: FieldsSigned,
     // Why base64 ? FieldsSigned is part of the server response, must be text for HTTP, and we want the field name to be self-documenting for clients
     pub signature_base64: String, // Signature over both data and timestamp
+    // Post-quantum signature over the same fields, when hybrid signatures are enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature_pq_base64: Option<String>,
+    // Chains the signing key to the root key clients pin, when signing is delegated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delegation: Option<Delegation>,
+    // The data's first receipt, when it was signed before (allow_multiple duplicate policy)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub earliest: Option<Box<SignDataResp>>,
 }
-#[derive(Serialize, Clone)]
-#[cfg_attr(test, derive(Deserialize))]
+impl SignDataResp {
+    /// What the receipt is aggregated as in evidence renewals: covers the signed fields and the signature
+    pub fn hash(&self) -> Result<[u8; 32], SignDataErr> {
+        let signature = base64::decode(&self.signature_base64)?;
+        Ok(evidence::chain_hash(
+            &self.fields_signed.hash()?,
+            &signature,
+        ))
+    }
+    /// Whether nothing before this receipt is linked: the first receipt ever, or the first after
+    /// receipts from before the chain, whose hashes weren't kept. Its `serial` still continues theirs
+    pub fn is_chain_start(&self) -> bool {
+        self.fields_signed.prev_receipt_hash_base64.is_none()
+    }
+    /// Whether this receipt directly follows `prev` in the chain. Ordering is proven by the links,
+    /// so a timestamp earlier than `prev`'s reveals a backdated receipt or a clock gone wrong.
+    pub fn follows(&self, prev: &SignDataResp) -> bool {
+        let prev_hash = match prev.hash() {
+            Ok(hash) => base64::encode(&hash),
+            Err(_) => return false,
+        };
+        self.fields_signed.serial == prev.fields_signed.serial + 1
+            && self.fields_signed.prev_receipt_hash_base64.as_ref() == Some(&prev_hash)
+            && self.fields_signed.timestamp >= prev.fields_signed.timestamp
+    }
+    /// Client-side verification of a receipt against the pinned keys and known revocations.
+    /// With a pinned post-quantum key, both signatures must verify: for delegated receipts,
+    /// the post-quantum one against the key the delegation certifies.
+    pub fn verify(
+        &self,
+        trust_anchor: &PublicKey,
+        pq_pubkey: Option<&[u8]>,
+        revocations: &RevocationList,
+    ) -> Verdict {
+        let timestamp = self.fields_signed.timestamp;
+        let (signing_key, pq_pubkey) = match &self.delegation {
+            None => (*trust_anchor, pq_pubkey.map(<[u8]>::to_vec)),
+            Some(d) => match (
+                d.verify(trust_anchor),
+                d.online_pubkey(),
+                d.online_pq_pubkey(),
+            ) {
+                (Ok(()), Ok(online), Ok(online_pq)) if d.covers_local(timestamp) => {
+                    match (pq_pubkey, online_pq) {
+                        (None, _) => (online, None),
+                        (Some(_), Some(online_pq)) => (online, Some(online_pq)),
+                        (Some(_), None) => return Verdict::BadDelegation,
+                    }
+                }
+                _ => return Verdict::BadDelegation,
+            },
+        };
+        let sig_ok = match (
+            self.fields_signed.hash(),
+            base64::decode(&self.signature_base64),
+        ) {
+            (Ok(hash), Ok(sig)) => crypto_sign::verify(&signing_key, &hash, &sig),
+            _ => false,
+        };
+        let sig_pq_ok = match (pq_pubkey, &self.signature_pq_base64) {
+            (None, _) => true,
+            (Some(pq_pubkey), Some(sig_pq_b64)) => {
+                match (self.fields_signed.hash(), base64::decode(sig_pq_b64)) {
+                    (Ok(hash), Ok(sig_pq)) => crypto_sign_pq::verify_pq(&pq_pubkey, &hash, &sig_pq),
+                    _ => false,
+                }
+            }
+            (Some(_), None) => false,
+        };
+        if !sig_ok || !sig_pq_ok {
+            return Verdict::BadSignature;
+        }
+        for key in &[signing_key, *trust_anchor] {
+            let key_id = crypto_sign::key_id(key);
+            if let Some(r) = revocations.get(&key_id) {
+                if !r.trusts(timestamp) {
+                    return Verdict::Untrusted { key_id };
+                }
+            }
+        }
+        Verdict::Trusted
+    }
+}
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Trusted,
+    BadSignature,
+    BadDelegation,
+    // Signed by a key revoked, or compromised, before the receipt's timestamp
+    Untrusted { key_id: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
 pub struct FieldsSigned {
     // Why base64 ? FieldsSigned is part of the server response, must be text for HTTP, and we want the field name to be self-documenting for clients
     pub data_hash_base64: String,
     pub timestamp: NaiveDateTime,
+    // How far off `timestamp` may be, as checked against reference clocks. Null if unchecked.
+    pub accuracy_ms: Option<u64>,
+    // Strictly increasing, without gaps: orders receipts regardless of the server clock
+    pub serial: i64,
+    // Hash of the receipt with the previous serial (see `SignDataResp::hash`), null for the first one
+    pub prev_receipt_hash_base64: Option<String>,
 }
 impl FieldsSigned {
-    fn hash(&self) -> Result<[u8; 32], SignDataErr> {
+    pub(crate) fn hash(&self) -> Result<[u8; 32], SignDataErr> {
         let json_bytes: Vec<u8> =
             serde_json::to_vec(&self).map_err(SignDataErr::SerializeFieldsSigned)?;
         Ok(*blake3::hash(&json_bytes).as_bytes())
     }
     fn sign(&self) -> Result<[u8; 64], SignDataErr> {
-        let sig = crate::config::keypair().sign(&self.hash()?);
+        let sig = crate::config::signer()
+            .sign(&self.hash()?)
+            .map_err(SignDataErr::Signer)?;
         Ok(sig)
     }
+    fn sign_pq(&self) -> Result<Option<Vec<u8>>, SignDataErr> {
+        crate::config::signer()
+            .sign_pq(&self.hash()?)
+            .map_err(SignDataErr::Signer)
+    }
 }
 
-pub async fn sign_data(sd_req: SignDataReq) -> Result<impl Reply, Rejection> {
-    // TODO middleware rate-limit with PoW
-    let pow_ok = sd_req.verify_pow()?;
-    if !pow_ok {
-        return Err(SignDataErr::PowRejected)?;
+pub async fn sign_data(
+    client: Option<ApiClient>,
+    sd_req: SignDataReq,
+) -> Result<impl Reply, Rejection> {
+    sign_one(client, sd_req).await.map_err(count_error)
+}
+
+/// Counts a signing request's failure by its error: once, as the route rejects it
+pub(crate) fn count_error(r: Rejection) -> Rejection {
+    if let Some(e) = r.find::<SignDataErr>() {
+        METRICS
+            .sign_data_errors
+            .with_label_values(&[e.variant()])
+            .inc();
     }
+    r
+}
 
+async fn sign_one(client: Option<ApiClient>, sd_req: SignDataReq) -> Result<impl Reply, Rejection> {
     // hash data
     let data_hash = sd_req.hash_data()?;
     let data_hash_base64 = base64::encode(&data_hash.as_bytes());
+    let policy = crate::config::duplicate_policy();
+
+    // rate-limit with PoW, discounted for API clients and SSO users within their quota,
+    // or waived for privacy tokens
+    let pow_mode = client
+        .as_ref()
+        .map_or(Ok(PowMode::Full), ApiClient::pow_mode)?;
+    let pow_ok = sd_req.verify_pow(pow_mode, &data_hash)?;
+    if !pow_ok {
+        return Err(SignDataErr::PowRejected)?;
+    }
+    // a retry gets its receipt back before the client is charged again
+    if policy == DuplicatePolicy::Idempotent {
+        let original = crate::config::storage()
+            .begin(Lock::None)?
+            .receipt_by_data_hash(&data_hash_base64)?;
+        if let Some(original) = original {
+            if let Some(receipt) = stored_receipt(&original)? {
+                duplicate_seen(policy);
+                return Ok(reply::json(&receipt));
+            }
+        }
+    }
+    // spent along with the receipt, below
+    if let Some(ApiClient::Token(token)) = &client {
+        token.check(data_hash.as_bytes())?;
+    }
+    let subject = client
+        .as_ref()
+        .and_then(ApiClient::subject)
+        .map(|(issuer, subject)| (issuer.to_string(), subject.to_string()));
+
+    let accuracy = signing_conditions()?;
+
+    // one receipt at a time: each takes the next serial and links to the last receipt.
+    // Queries and signing (maybe through the signer socket) block: off the async workers
+    let start = Instant::now();
+    let signed = tokio::task::spawn_blocking(move || {
+        let subject = subject.as_ref().map(|(i, s)| (i.as_str(), s.as_str()));
+        crate::config::storage().transaction(Lock::Chain, |tx| {
+            let signed = sign_next(tx, data_hash_base64, policy, accuracy, subject)?;
+            if let Signed::New(_) = signed {
+                charge(tx, client.as_ref())?;
+            }
+            Ok(signed)
+        })
+    })
+    .await
+    .map_err(|e| SignDataErr::Task(e.to_string()))?;
+    logging::record_ms("db_ms", start.elapsed());
+    let signed = signed?;
+    let resp = match signed {
+        Signed::New(resp) => {
+            METRICS.receipts_issued.inc();
+            resp
+        }
+        Signed::Original(resp) => resp,
+    };
+
+    Ok(reply::json(&resp))
+}
+
+/// Spends the client's token for a new receipt, or counts it against their quota. In the signing
+/// transaction: a receipt that isn't issued, e.g on a 409 or a rollback, costs nothing
+pub(crate) fn charge(db: &mut dyn Tx, client: Option<&ApiClient>) -> Result<(), AuthErr> {
+    match client {
+        Some(ApiClient::Token(token)) => token.spend_in(db),
+        Some(client) => client.consume_quota_in(db, 1),
+        None => Ok(()),
+    }
+}
+
+/// Checks the keys may sign now, and returns the clock's accuracy. Once per request
+pub(crate) fn signing_conditions() -> Result<Option<chrono::Duration>, SignDataErr> {
+    let revocations = revocation::current();
+    if revocations.is_revoked(&crate::config::signer().pubkey())
+        || revocations.is_revoked(&crate::config::trust_anchor())
+    {
+        return Err(SignDataErr::KeyRevoked);
+    }
+    crate::config::clock_guard()
+        .accuracy()
+        .map_err(SignDataErr::Clock)
+}
+
+pub(crate) enum Signed {
+    New(SignDataResp),
+    // The data's receipt from before, as the idempotent duplicate policy returns it
+    Original(SignDataResp),
+}
+
+/// The chain's last receipt, as the next one links to it
+pub(crate) struct ChainTip {
+    serial: i64,
+    receipt_hash_b64: Option<String>,
+    created_at: NaiveDateTime,
+}
+impl From<SignedData> for ChainTip {
+    fn from(last: SignedData) -> Self {
+        ChainTip {
+            serial: last.serial,
+            receipt_hash_b64: last.receipt_hash_b64,
+            created_at: last.created_at,
+        }
+    }
+}
+impl ChainTip {
+    /// The chain's current tip, None while the chain is empty
+    pub(crate) fn read(db: &mut dyn Tx) -> Result<Option<ChainTip>, SignDataErr> {
+        Ok(db.last_receipt()?.map(ChainTip::from))
+    }
+}
+
+/// Signs `data_hash_base64` as the next receipt of the chain, as `policy` allows for data
+/// already signed. To call in a transaction holding `Lock::Chain`
+pub(crate) fn sign_next(
+    db: &mut dyn Tx,
+    data_hash_base64: String,
+    policy: DuplicatePolicy,
+    accuracy: Option<chrono::Duration>,
+    subject: Option<(&str, &str)>,
+) -> Result<Signed, SignDataErr> {
+    let mut tip = ChainTip::read(db)?;
+    sign_after(db, &mut tip, data_hash_base64, policy, accuracy, subject)
+}
+
+/// As `sign_next`, after the chain's `tip` as the caller read it in the same transaction.
+/// Moves `tip` to the new receipt, so consecutive calls don't query it again
+pub(crate) fn sign_after(
+    db: &mut dyn Tx,
+    tip: &mut Option<ChainTip>,
+    data_hash_base64: String,
+    policy: DuplicatePolicy,
+    accuracy: Option<chrono::Duration>,
+    subject: Option<(&str, &str)>,
+) -> Result<Signed, SignDataErr> {
+    let last = tip.as_ref();
+
+    // checked under the lock: the same data may have been signed concurrently
+    let earliest = match db.receipt_by_data_hash(&data_hash_base64)? {
+        None => None,
+        Some(original) => {
+            duplicate_seen(policy);
+            match (policy, stored_receipt(&original)?) {
+                (DuplicatePolicy::Idempotent, Some(receipt)) => {
+                    return Ok(Signed::Original(receipt))
+                }
+                // receipts issued before they were stored can't be returned again
+                (DuplicatePolicy::Strict, _) | (DuplicatePolicy::Idempotent, None) => {
+                    return Err(SignDataErr::AlreadySigned)
+                }
+                (DuplicatePolicy::AllowMultiple, receipt) => receipt.map(Box::new),
+            }
+        }
+    };
 
-    // serialize {data,timestamp} to bytes, sign serialized bytes
+    let now = Utc::now();
+    let timestamp = now.with_timezone(&Local).naive_local();
+    if let Some(last) = last {
+        // compared in UTC, as local times repeat when DST ends: a repeated time reads as its earliest
+        let last_at = Local.from_local_datetime(&last.created_at).earliest();
+        if last_at.map_or(false, |last_at| now < last_at.with_timezone(&Utc)) {
+            return Err(ClockErr::WentBackwards {
+                last: last.created_at,
+            })?;
+        }
+    }
+
+    // serialize {data,timestamp,chain} to bytes, sign serialized bytes
     let fields_signed = FieldsSigned {
         data_hash_base64,
-        timestamp: Local::now().naive_local(),
+        timestamp,
+        accuracy_ms: accuracy.map(|a| a.num_milliseconds() as u64),
+        serial: last.map_or(1, |last| last.serial + 1),
+        // None after receipts from before the chain, which have no hash: the chain starts over
+        prev_receipt_hash_base64: last.and_then(|last| last.receipt_hash_b64.clone()),
     };
+    let delegation = crate::config::delegation().cloned();
+    if let Some(delegation) = &delegation {
+        if !delegation.covers_local(fields_signed.timestamp) {
+            return Err(SignDataErr::DelegationExpired);
+        }
+    }
     let signature = fields_signed.sign()?;
     let signature_base64 = base64::encode(&signature);
+    let signature_pq_base64 = fields_signed.sign_pq()?.map(base64::encode);
 
     // create response
-    let resp = SignDataResp {
-        fields_signed: fields_signed.clone(),
+    let mut resp = SignDataResp {
+        fields_signed,
         signature_base64,
+        signature_pq_base64,
+        delegation,
+        earliest: None,
     };
 
-    // insert data_hash into db (to disallow signing the same data a second time)
-    let db = db_conn::get().map_err(SignDataErr::DbConn)?;
+    // insert data_hash into db (to detect signing the same data a second time),
+    // the receipt's hash for the next receipt and evidence renewal, and the receipt itself
+    let receipt_hash_base64 = base64::encode(&resp.hash()?);
+    let receipt_json = serde_json::to_string(&resp).map_err(SignDataErr::StoredReceipt)?;
     let new_signed_data = NewSignedData {
-        created_at: Some(fields_signed.timestamp),
-        data_hash_b64: &fields_signed.data_hash_base64,
+        created_at: Some(resp.fields_signed.timestamp),
+        data_hash_b64: &resp.fields_signed.data_hash_base64,
+        receipt_hash_b64: Some(&receipt_hash_base64),
+        serial: resp.fields_signed.serial,
+        prev_receipt_hash_b64: resp.fields_signed.prev_receipt_hash_base64.as_deref(),
+        subject_issuer: subject.map(|(issuer, _)| issuer),
+        subject: subject.map(|(_, subject)| subject),
+        receipt_json: Some(&receipt_json),
     };
-    let _signed_data = new_signed_data.insert(&db).map_err(SignDataErr::Model)?;
+    let _signed_data = db.insert_receipt(new_signed_data)?;
+    *tip = Some(ChainTip {
+        serial: resp.fields_signed.serial,
+        receipt_hash_b64: Some(receipt_hash_base64),
+        created_at: resp.fields_signed.timestamp,
+    });
+    resp.earliest = earliest;
+    Ok(Signed::New(resp))
+}
 
-    Ok(reply::json(&resp))
+fn duplicate_seen(policy: DuplicatePolicy) {
+    METRICS
+        .duplicates
+        .with_label_values(&[policy.as_str()])
+        .inc();
+}
+
+fn stored_receipt(signed_data: &SignedData) -> Result<Option<SignDataResp>, SignDataErr> {
+    signed_data
+        .receipt_json
+        .as_deref()
+        .map(serde_json::from_str)
+        .transpose()
+        .map_err(SignDataErr::StoredReceipt)
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -98,6 +478,42 @@ pub enum SignDataErr {
     B64DecodeBody(#[from] base64::DecodeError),
     #[error("PoW proof rejected")]
     PowRejected,
+    #[error("data already signed")]
+    AlreadySigned,
+    #[error("stored receipt err: {0}")]
+    StoredReceipt(serde_json::Error),
+    #[error("signer err: {0}")]
+    Signer(SignerErr),
+    #[error("signing key delegation isn't valid now")]
+    DelegationExpired,
+    #[error("signing key is revoked")]
+    KeyRevoked,
+    #[error("clock err: {0}")]
+    Clock(#[from] ClockErr),
+    #[error("task err: {0}")]
+    Task(String),
+    #[error(transparent)]
+    Auth(#[from] AuthErr),
+}
+impl SignDataErr {
+    /// For metrics: the variant, without details
+    pub fn variant(&self) -> &'static str {
+        match self {
+            SignDataErr::DbConn(_) => "db_conn",
+            SignDataErr::Model(_) => "model",
+            SignDataErr::SerializeFieldsSigned(_) => "serialize_fields_signed",
+            SignDataErr::B64DecodeBody(_) => "b64_decode_body",
+            SignDataErr::PowRejected => "pow_rejected",
+            SignDataErr::AlreadySigned => "already_signed",
+            SignDataErr::StoredReceipt(_) => "stored_receipt",
+            SignDataErr::Signer(_) => "signer",
+            SignDataErr::DelegationExpired => "delegation_expired",
+            SignDataErr::KeyRevoked => "key_revoked",
+            SignDataErr::Clock(_) => "clock",
+            SignDataErr::Task(_) => "task",
+            SignDataErr::Auth(_) => "auth",
+        }
+    }
 }
 use pow_ratelimit::PowVerifErr;
 impl From<PowVerifErr> for SignDataErr {
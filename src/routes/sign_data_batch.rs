@@ -0,0 +1,220 @@
+use schemars::JsonSchema;
+use std::time::Instant;
+use warp::{reply, Rejection, Reply};
+//
+use super::middleware::auth::{ApiClient, AuthErr, PowMode};
+use super::middleware::pow_ratelimit;
+use super::sign_data::{self, ChainTip, SignDataErr, SignDataResp, Signed};
+use crate::models::Lock;
+use crate::utils::logging;
+use crate::utils::metrics::METRICS;
+
+// a batch holds the chain's lock while it signs: kept short for single receipts waiting on it
+pub const MAX_ITEMS: usize = 500;
+// checked from Content-Length, before the body is read
+pub const MAX_BODY_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SignBatchReq {
+    pub items: Vec<BatchItem>,
+    // Over all items' bytes, concatenated in order. Optional for API clients exempt from PoW
+    #[serde(default)]
+    pub pow_proof_base64: Option<String>,
+}
+/// Either the data, or its 32-byte Blake3 hash
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BatchItem {
+    #[serde(default)]
+    pub data_base64: Option<String>,
+    #[serde(default)]
+    pub data_hash_base64: Option<String>,
+}
+impl BatchItem {
+    // the bytes submitted, and the data's hash
+    fn decode(&self, index: usize) -> Result<(Vec<u8>, blake3::Hash), SignBatchErr> {
+        let bad_item = |reason: &str| SignBatchErr::BadItem {
+            index,
+            reason: reason.to_string(),
+        };
+        match (&self.data_base64, &self.data_hash_base64) {
+            (Some(data_base64), None) => {
+                let data = base64::decode(data_base64).map_err(|e| bad_item(&e.to_string()))?;
+                let hash = blake3::hash(&data);
+                Ok((data, hash))
+            }
+            (None, Some(data_hash_base64)) => {
+                let bytes =
+                    base64::decode(data_hash_base64).map_err(|e| bad_item(&e.to_string()))?;
+                let mut hash = [0u8; 32];
+                match bytes.len() {
+                    32 => hash.copy_from_slice(&bytes),
+                    _ => return Err(bad_item("a data hash is 32 bytes")),
+                }
+                Ok((bytes, blake3::Hash::from(hash)))
+            }
+            _ => Err(bad_item(
+                "expected exactly one of data_base64 and data_hash_base64",
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+pub struct SignBatchResp {
+    // In the request's order
+    pub results: Vec<BatchResult>,
+}
+#[derive(Serialize, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+pub struct BatchResult {
+    pub status: BatchStatus,
+    // None for data already signed, unless the duplicate policy returns its receipt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<SignDataResp>,
+}
+#[derive(Serialize, Debug, PartialEq, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    Signed,
+    AlreadySigned,
+}
+
+/// Signs many items for one PoW over all of them, in one transaction: the receipts are consecutive
+pub async fn sign_data_batch(
+    client: Option<ApiClient>,
+    sb_req: SignBatchReq,
+) -> Result<impl Reply, Rejection> {
+    sign_batch(client, sb_req)
+        .await
+        .map_err(sign_data::count_error)
+}
+
+async fn sign_batch(
+    client: Option<ApiClient>,
+    sb_req: SignBatchReq,
+) -> Result<impl Reply, Rejection> {
+    let count = sb_req.items.len();
+    if count == 0 || count > MAX_ITEMS {
+        return Err(SignBatchErr::BatchSize)?;
+    }
+    let decoded = sb_req
+        .items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| item.decode(index))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // a token pays for a single receipt
+    if let Some(ApiClient::Token(_)) = &client {
+        return Err(AuthErr::UnsupportedScheme)?;
+    }
+    // the PoW grows with the batch: over all data, or all hashes for discounted clients
+    let pow_mode = client
+        .as_ref()
+        .map_or(Ok(PowMode::Full), ApiClient::pow_mode)?;
+    let pow_ok = match (pow_mode, &sb_req.pow_proof_base64) {
+        (PowMode::Exempt, _) => true,
+        (_, None) => false,
+        (mode, Some(pow_proof_base64)) => {
+            let batch: Vec<u8> = match mode {
+                PowMode::Digest => decoded
+                    .iter()
+                    .flat_map(|(_, hash)| hash.as_bytes().iter().copied())
+                    .collect(),
+                _ => decoded
+                    .iter()
+                    .flat_map(|(bytes, _)| bytes.iter().copied())
+                    .collect(),
+            };
+            // a malformed proof is rejected like a wrong one
+            pow_ratelimit::verify_pow_bytes(&batch, pow_proof_base64).unwrap_or(false)
+        }
+    };
+    if !pow_ok {
+        return Err(SignDataErr::PowRejected)?;
+    }
+    let subject = client
+        .as_ref()
+        .and_then(ApiClient::subject)
+        .map(|(issuer, subject)| (issuer.to_string(), subject.to_string()));
+    let policy = crate::config::duplicate_policy();
+    let accuracy = sign_data::signing_conditions()?;
+    let hashes: Vec<String> = decoded
+        .iter()
+        .map(|(_, hash)| base64::encode(hash.as_bytes()))
+        .collect();
+
+    // the chain is locked for the whole batch; any error other than a duplicate rolls it back.
+    // Queries and signing block: off the async workers
+    let start = Instant::now();
+    let results = tokio::task::spawn_blocking(move || {
+        let subject = subject.as_ref().map(|(i, s)| (i.as_str(), s.as_str()));
+        crate::config::storage().transaction(Lock::Chain, |tx| {
+            // read once, then moved along by each new receipt
+            let mut tip = ChainTip::read(tx)?;
+            let results = hashes
+                .into_iter()
+                .map(|data_hash_base64| {
+                    let signed = sign_data::sign_after(
+                        tx,
+                        &mut tip,
+                        data_hash_base64,
+                        policy,
+                        accuracy,
+                        subject,
+                    );
+                    match signed {
+                        Ok(Signed::New(receipt)) => Ok(BatchResult {
+                            status: BatchStatus::Signed,
+                            receipt: Some(receipt),
+                        }),
+                        Ok(Signed::Original(receipt)) => Ok(BatchResult {
+                            status: BatchStatus::AlreadySigned,
+                            receipt: Some(receipt),
+                        }),
+                        Err(SignDataErr::AlreadySigned) => Ok(BatchResult {
+                            status: BatchStatus::AlreadySigned,
+                            receipt: None,
+                        }),
+                        Err(e) => Err(e),
+                    }
+                })
+                .collect::<Result<Vec<_>, SignDataErr>>()?;
+            // duplicates cost nothing; over quota rolls the whole batch back
+            let signed = results
+                .iter()
+                .filter(|r| r.status == BatchStatus::Signed)
+                .count();
+            if let (Some(client), true) = (&client, signed > 0) {
+                client.consume_quota_in(tx, signed as i64)?;
+            }
+            Ok(results)
+        })
+    })
+    .await
+    .map_err(|e| SignDataErr::Task(e.to_string()))?;
+    logging::record_ms("db_ms", start.elapsed());
+    let results = results?;
+
+    for _ in results.iter().filter(|r| r.status == BatchStatus::Signed) {
+        METRICS.receipts_issued.inc();
+    }
+    Ok(reply::json(&SignBatchResp { results }))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignBatchErr {
+    #[error("expected 1 to {} items", MAX_ITEMS)]
+    BatchSize,
+    #[error("item {index}: {reason}")]
+    BadItem { index: usize, reason: String },
+}
+
+impl warp::reject::Reject for SignBatchErr {}
+impl From<SignBatchErr> for Rejection {
+    fn from(e: SignBatchErr) -> Self {
+        warp::reject::custom(e)
+    }
+}
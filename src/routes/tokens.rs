@@ -0,0 +1,127 @@
+use schemars::JsonSchema;
+use warp::{reply, Rejection, Reply};
+//
+use super::middleware::auth::{ApiClient, AuthErr, PowMode};
+use super::middleware::pow_ratelimit;
+use crate::models::{Lock, ModelErr};
+use crate::utils::db_conn::DbConnErr;
+use crate::utils::tokens::{self, TokenErr, MAX_BATCH};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct IssueTokensReq {
+    // Blinded token nonces: compressed ristretto255 points
+    pub blinded_base64: Vec<String>,
+    // Over the concatenated blinded elements' bytes. Optional for API clients exempt from PoW
+    #[serde(default)]
+    pub pow_proof_base64: Option<String>,
+}
+#[derive(Serialize, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+pub struct IssueTokensResp {
+    // In the request's order
+    pub evaluated_base64: Vec<String>,
+    // One proof for the whole batch, to check against the pinned token key
+    pub proof: DleqProofResp,
+    pub pubkey_base64: String,
+}
+#[derive(Serialize, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+pub struct DleqProofResp {
+    pub challenge_base64: String,
+    pub response_base64: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+pub struct TokenPubkeyResp {
+    pub pubkey_base64: String,
+}
+
+/// Evaluates a batch of blinded tokens for one PoW, or against an API client's quota
+pub async fn issue_tokens(
+    client: Option<ApiClient>,
+    it_req: IssueTokensReq,
+) -> Result<impl Reply, Rejection> {
+    let key = crate::config::token_key().ok_or(IssueTokensErr::Disabled)?;
+    let count = it_req.blinded_base64.len();
+    if count == 0 || count > MAX_BATCH {
+        return Err(IssueTokensErr::BatchSize)?;
+    }
+    let blinded = it_req
+        .blinded_base64
+        .iter()
+        .map(|b| tokens::point_from_b64(b))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(IssueTokensErr::from)?;
+    let batch: Vec<u8> = blinded
+        .iter()
+        .flat_map(|p| p.compress().to_bytes().to_vec())
+        .collect();
+
+    // tokens can't buy more tokens
+    if let Some(ApiClient::Token(_)) = &client {
+        return Err(AuthErr::UnsupportedScheme)?;
+    }
+    let pow_mode = client
+        .as_ref()
+        .map_or(Ok(PowMode::Full), ApiClient::pow_mode)?;
+    if !pow_ratelimit::verify_pow_mode(pow_mode, it_req.pow_proof_base64.as_deref(), &batch) {
+        return Err(IssueTokensErr::PowRejected)?;
+    }
+
+    // charged in the transaction the tokens are issued in: a failed issuance costs nothing.
+    // Queries and evaluation block: off the async workers
+    let (evaluated, proof) = tokio::task::spawn_blocking(move || {
+        crate::config::storage().transaction::<_, IssueTokensErr, _>(Lock::None, |tx| {
+            if let Some(client) = &client {
+                client.consume_quota_in(tx, count as i64)?;
+            }
+            Ok(key.issue(&blinded))
+        })
+    })
+    .await
+    .map_err(|e| IssueTokensErr::Task(e.to_string()))??;
+    Ok(reply::json(&IssueTokensResp {
+        evaluated_base64: evaluated.iter().map(tokens::point_to_b64).collect(),
+        proof: DleqProofResp {
+            challenge_base64: base64::encode(proof.challenge.as_bytes()),
+            response_base64: base64::encode(proof.response.as_bytes()),
+        },
+        pubkey_base64: tokens::point_to_b64(&key.pubkey()),
+    }))
+}
+
+/// The key tokens are issued with, for clients to pin: a key per client would tag their tokens
+pub async fn token_pubkey() -> Result<impl Reply, Rejection> {
+    let key = crate::config::token_key().ok_or(IssueTokensErr::Disabled)?;
+    Ok(reply::json(&TokenPubkeyResp {
+        pubkey_base64: tokens::point_to_b64(&key.pubkey()),
+    }))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IssueTokensErr {
+    #[error("expected 1 to {} blinded tokens", MAX_BATCH)]
+    BatchSize,
+    #[error("invalid blinded token: {0}")]
+    BadElement(#[from] TokenErr),
+    #[error("PoW proof rejected")]
+    PowRejected,
+    #[error("privacy tokens aren't enabled")]
+    Disabled,
+    #[error("task err: {0}")]
+    Task(String),
+    #[error(transparent)]
+    Auth(#[from] AuthErr),
+    #[error("db conn err: {0}")]
+    DbConn(#[from] DbConnErr),
+    #[error("model err: {0}")]
+    Model(#[from] ModelErr),
+}
+
+impl warp::reject::Reject for IssueTokensErr {}
+impl From<IssueTokensErr> for Rejection {
+    fn from(e: IssueTokensErr) -> Self {
+        warp::reject::custom(e)
+    }
+}
@@ -0,0 +1,134 @@
+use ed25519_dalek::PublicKey;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+//
+use super::{
+    SignerErr, DIGEST_LEN, KEY_ID_LEN, MAX_BLINDED_LEN, OP_BLIND_EPOCH, OP_BLIND_SIGN,
+    OP_PQ_PUBKEY, OP_PQ_SIGN_DIGEST, OP_PUBKEY, OP_SIGN_DIGEST, PUBKEY_LEN, SIG_LEN,
+    STATUS_BAD_BLINDED, STATUS_EPOCH_OVER, STATUS_OK,
+};
+use crate::utils::blind_sign::{BlindErr, EpochCert};
+use crate::utils::crypto_sign::{self, KpErr};
+use crate::utils::crypto_sign_pq;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Client side of the signer daemon. Holds only the public keys.
+pub struct SignerClient {
+    socket_path: PathBuf,
+    pubkey: PublicKey,
+    pq_pubkey: Option<Vec<u8>>,
+}
+impl SignerClient {
+    /// Asks the daemon for its public key, failing early if it isn't reachable
+    pub fn connect(socket_path: &Path) -> Result<Self, SignerErr> {
+        let pubkey_bytes = request(socket_path, &[OP_PUBKEY], PUBKEY_LEN)?;
+        let pubkey = PublicKey::from_bytes(&pubkey_bytes).map_err(KpErr::from)?;
+        // a daemon without post-quantum key refuses: hybrid signatures are off
+        let pq_pubkey = match request_var(socket_path, &[OP_PQ_PUBKEY]) {
+            Ok(pq_pubkey) => Some(pq_pubkey),
+            Err(SignerErr::Refused) => None,
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            socket_path: socket_path.to_path_buf(),
+            pubkey,
+            pq_pubkey,
+        })
+    }
+    pub fn pubkey(&self) -> PublicKey {
+        self.pubkey
+    }
+    pub fn pq_pubkey(&self) -> Option<&[u8]> {
+        self.pq_pubkey.as_deref()
+    }
+    pub fn sign_digest(&self, digest: &[u8; DIGEST_LEN]) -> Result<[u8; SIG_LEN], SignerErr> {
+        let mut req = [0u8; 1 + DIGEST_LEN];
+        req[0] = OP_SIGN_DIGEST;
+        req[1..].copy_from_slice(digest);
+        let sig_bytes = request(&self.socket_path, &req, SIG_LEN)?;
+
+        // don't hand out anything the published pubkey can't verify
+        if !crypto_sign::verify(&self.pubkey, digest, &sig_bytes) {
+            return Err(SignerErr::BadSignature);
+        }
+        let mut sig = [0u8; SIG_LEN];
+        sig.copy_from_slice(&sig_bytes);
+        Ok(sig)
+    }
+    pub fn sign_digest_pq(&self, digest: &[u8; DIGEST_LEN]) -> Result<Option<Vec<u8>>, SignerErr> {
+        let pq_pubkey = match &self.pq_pubkey {
+            Some(pq_pubkey) => pq_pubkey,
+            None => return Ok(None),
+        };
+        let mut req = [0u8; 1 + DIGEST_LEN];
+        req[0] = OP_PQ_SIGN_DIGEST;
+        req[1..].copy_from_slice(digest);
+        let sig = request_var(&self.socket_path, &req)?;
+
+        if !crypto_sign_pq::verify_pq(pq_pubkey, digest, &sig) {
+            return Err(SignerErr::BadSignature);
+        }
+        Ok(Some(sig))
+    }
+    /// The daemon's current epoch, generated on demand there
+    pub fn blind_epoch(&self) -> Result<EpochCert, BlindErr> {
+        let cert_json = request_var(&self.socket_path, &[OP_BLIND_EPOCH])?;
+        let cert: EpochCert = serde_json::from_slice(&cert_json)?;
+        // certified by the signing key itself: a delegation is attached by the caller
+        if cert.delegation.is_some() || !cert.verify(&self.pubkey) {
+            return Err(SignerErr::BadSignature)?;
+        }
+        Ok(cert)
+    }
+    pub fn blind_sign(&self, key_id: &str, blinded: &[u8]) -> Result<Vec<u8>, BlindErr> {
+        if key_id.len() != KEY_ID_LEN {
+            return Err(BlindErr::EpochOver);
+        }
+        if blinded.len() > MAX_BLINDED_LEN {
+            return Err(BlindErr::BadBlindedMessage);
+        }
+        let mut req = vec![OP_BLIND_SIGN];
+        req.extend_from_slice(key_id.as_bytes());
+        req.extend_from_slice(&(blinded.len() as u32).to_be_bytes());
+        req.extend_from_slice(blinded);
+        request_var(&self.socket_path, &req).map_err(|e| match e {
+            SignerErr::EpochOver => BlindErr::EpochOver,
+            SignerErr::BadBlindedMessage => BlindErr::BadBlindedMessage,
+            e => BlindErr::Signer(e),
+        })
+    }
+}
+
+fn request(socket_path: &Path, req: &[u8], resp_len: usize) -> Result<Vec<u8>, SignerErr> {
+    let mut stream = send(socket_path, req)?;
+    let mut resp = vec![0u8; resp_len];
+    stream.read_exact(&mut resp)?;
+    Ok(resp)
+}
+// for responses prefixed by their length
+fn request_var(socket_path: &Path, req: &[u8]) -> Result<Vec<u8>, SignerErr> {
+    let mut stream = send(socket_path, req)?;
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let mut resp = vec![0u8; u32::from_be_bytes(len) as usize];
+    stream.read_exact(&mut resp)?;
+    Ok(resp)
+}
+fn send(socket_path: &Path, req: &[u8]) -> Result<UnixStream, SignerErr> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    stream.write_all(req)?;
+
+    let mut status = [0u8; 1];
+    stream.read_exact(&mut status)?;
+    match status[0] {
+        STATUS_OK => Ok(stream),
+        STATUS_EPOCH_OVER => Err(SignerErr::EpochOver),
+        STATUS_BAD_BLINDED => Err(SignerErr::BadBlindedMessage),
+        _ => Err(SignerErr::Refused),
+    }
+}
@@ -0,0 +1,155 @@
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+//
+use super::{
+    SignerErr, DIGEST_LEN, KEY_ID_LEN, MAX_BLINDED_LEN, OP_BLIND_EPOCH, OP_BLIND_SIGN,
+    OP_PQ_PUBKEY, OP_PQ_SIGN_DIGEST, OP_PUBKEY, OP_SIGN_DIGEST, STATUS_BAD_BLINDED,
+    STATUS_EPOCH_OVER, STATUS_ERR, STATUS_OK,
+};
+use crate::utils::blind_sign::{BlindErr, EpochKeys};
+use crate::utils::crypto_sign::KeyPair;
+use crate::utils::crypto_sign_pq::PqKeyPair;
+
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Serves the signer protocol on a Unix socket. Blocks forever.
+/// The only operations exposed are "give me your pubkey" and "sign this 32-byte digest",
+/// for the Ed25519 key and, if hybrid signatures are enabled, the post-quantum key,
+/// and "give me the current blind epoch" and "blind-sign this with its key": epoch keys are
+/// generated, certified and kept here, in `epoch_keys`.
+pub fn run(
+    socket_path: &Path,
+    keypair: &'static KeyPair,
+    pq_keypair: Option<&'static PqKeyPair>,
+    epoch_keys: &'static EpochKeys,
+) -> Result<(), SignerErr> {
+    let listener = bind(socket_path)?;
+    info!("Signer listening on {}", socket_path.display());
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || {
+                    if let Err(e) = serve_conn(stream, keypair, pq_keypair, epoch_keys) {
+                        warn!("signer connection closed: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!("signer failed accepting connection: {}", e),
+        }
+    }
+    Ok(())
+}
+
+pub fn bind(socket_path: &Path) -> Result<UnixListener, SignerErr> {
+    // remove a stale socket left by a previous run, but never any other kind of file
+    if let Ok(meta) = fs::symlink_metadata(socket_path) {
+        if !meta.file_type().is_socket() {
+            return Err(SignerErr::Io(std::io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", socket_path.display()),
+            )));
+        }
+        fs::remove_file(socket_path)?;
+    }
+    if let Some(dir) = socket_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let listener = UnixListener::bind(socket_path)?;
+    fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+pub(crate) fn serve_conn(
+    mut stream: UnixStream,
+    keypair: &KeyPair,
+    pq_keypair: Option<&PqKeyPair>,
+    epoch_keys: &EpochKeys,
+) -> Result<(), SignerErr> {
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    loop {
+        let mut op = [0u8; 1];
+        match stream.read_exact(&mut op) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        match (op[0], pq_keypair) {
+            (OP_PUBKEY, _) => {
+                stream.write_all(&[STATUS_OK])?;
+                stream.write_all(keypair.pubkey().as_bytes())?;
+            }
+            (OP_SIGN_DIGEST, _) => {
+                let mut digest = [0u8; DIGEST_LEN];
+                stream.read_exact(&mut digest)?;
+                stream.write_all(&[STATUS_OK])?;
+                stream.write_all(&keypair.sign(&digest))?;
+            }
+            (OP_PQ_PUBKEY, Some(pq_keypair)) => {
+                write_var(&mut stream, pq_keypair.pubkey_bytes())?;
+            }
+            (OP_PQ_SIGN_DIGEST, Some(pq_keypair)) => {
+                let mut digest = [0u8; DIGEST_LEN];
+                stream.read_exact(&mut digest)?;
+                write_var(&mut stream, &pq_keypair.sign(&digest))?;
+            }
+            (OP_BLIND_EPOCH, _) => {
+                let cert = epoch_keys
+                    .current(keypair)
+                    .and_then(|key| Ok(serde_json::to_vec(&key.cert)?));
+                match cert {
+                    Ok(cert) => write_var(&mut stream, &cert)?,
+                    Err(e) => return refuse(&mut stream, STATUS_ERR, e),
+                }
+            }
+            (OP_BLIND_SIGN, _) => {
+                let mut key_id = [0u8; KEY_ID_LEN];
+                stream.read_exact(&mut key_id)?;
+                let mut len = [0u8; 4];
+                stream.read_exact(&mut len)?;
+                let len = u32::from_be_bytes(len) as usize;
+                if len > MAX_BLINDED_LEN {
+                    return refuse(&mut stream, STATUS_BAD_BLINDED, BlindErr::BadBlindedMessage);
+                }
+                let mut blinded = vec![0u8; len];
+                stream.read_exact(&mut blinded)?;
+                // never generates a key: only the current epoch's, as the HTTP server got it
+                let signed = epoch_keys
+                    .get(&String::from_utf8_lossy(&key_id))
+                    .and_then(|key| key.sign_blinded(&blinded));
+                match signed {
+                    Ok(sig) => write_var(&mut stream, &sig)?,
+                    Err(e @ BlindErr::EpochOver) => {
+                        return refuse(&mut stream, STATUS_EPOCH_OVER, e)
+                    }
+                    Err(e @ BlindErr::BadBlindedMessage) => {
+                        return refuse(&mut stream, STATUS_BAD_BLINDED, e)
+                    }
+                    Err(e) => return refuse(&mut stream, STATUS_ERR, e),
+                }
+            }
+            _ => {
+                stream.write_all(&[STATUS_ERR])?;
+                return Err(SignerErr::Refused);
+            }
+        }
+    }
+}
+
+// answers `status`, then closes the connection
+fn refuse(stream: &mut UnixStream, status: u8, e: BlindErr) -> Result<(), SignerErr> {
+    stream.write_all(&[status])?;
+    Err(SignerErr::Blind(e.to_string()))
+}
+
+fn write_var(stream: &mut UnixStream, payload: &[u8]) -> Result<(), SignerErr> {
+    stream.write_all(&[STATUS_OK])?;
+    stream.write_all(&(payload.len() as u32).to_be_bytes())?;
+    stream.write_all(payload)?;
+    Ok(())
+}
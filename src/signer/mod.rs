@@ -0,0 +1,105 @@
+use ed25519_dalek::PublicKey;
+//
+use crate::utils::blind_sign::{self, BlindErr, EpochCert};
+use crate::utils::crypto_sign::{self, KeyPair, KpErr};
+use crate::utils::crypto_sign_pq::{self, PqKeyPair};
+
+pub mod client;
+pub mod daemon;
+use client::SignerClient;
+
+// Wire protocol between the HTTP server and the signer daemon, over a Unix socket.
+// Request: 1-byte opcode [+ 32-byte digest | + 32-byte epoch key id + blinded message].
+// Response: 1-byte status [+ payload].
+// Post-quantum payloads, epoch certificates (JSON), blinded messages and blind signatures are
+// variable-length, prefixed by their u32 big-endian length.
+const OP_PUBKEY: u8 = 0x01;
+const OP_SIGN_DIGEST: u8 = 0x02;
+const OP_PQ_PUBKEY: u8 = 0x03;
+const OP_PQ_SIGN_DIGEST: u8 = 0x04;
+const OP_BLIND_EPOCH: u8 = 0x05;
+const OP_BLIND_SIGN: u8 = 0x06;
+const STATUS_OK: u8 = 0x00;
+const STATUS_EPOCH_OVER: u8 = 0x01;
+const STATUS_BAD_BLINDED: u8 = 0x02;
+const STATUS_ERR: u8 = 0xff;
+const DIGEST_LEN: usize = 32;
+const PUBKEY_LEN: usize = 32;
+const SIG_LEN: usize = 64;
+// hex, as `EpochCert::key_id`
+const KEY_ID_LEN: usize = 32;
+// as long as the modulus
+const MAX_BLINDED_LEN: usize = blind_sign::KEY_BITS / 8;
+
+/// Signs digests with the service's key, wherever that key lives
+pub enum Signer {
+    /// keys loaded in this process. The post-quantum one only if hybrid signatures are enabled
+    Local(&'static KeyPair, Option<&'static PqKeyPair>),
+    /// key owned by a separate `signer` process, so the HTTP server holds no secret material
+    Remote(SignerClient),
+}
+impl Signer {
+    pub fn pubkey(&self) -> PublicKey {
+        match self {
+            Signer::Local(kp, _) => kp.pubkey(),
+            Signer::Remote(client) => client.pubkey(),
+        }
+    }
+    pub fn sign(&self, digest: &[u8; DIGEST_LEN]) -> Result<[u8; SIG_LEN], SignerErr> {
+        match self {
+            Signer::Local(kp, _) => Ok(kp.sign(digest)),
+            Signer::Remote(client) => client.sign_digest(digest),
+        }
+    }
+    pub fn pq_pubkey(&self) -> Option<&[u8]> {
+        match self {
+            Signer::Local(_, pq_kp) => pq_kp.map(|pq_kp| pq_kp.pubkey_bytes()),
+            Signer::Remote(client) => client.pq_pubkey(),
+        }
+    }
+    /// Post-quantum signature of the digest, `None` if hybrid signatures are disabled
+    pub fn sign_pq(&self, digest: &[u8; DIGEST_LEN]) -> Result<Option<Vec<u8>>, SignerErr> {
+        match self {
+            Signer::Local(_, pq_kp) => Ok(pq_kp.map(|pq_kp| pq_kp.sign(digest))),
+            Signer::Remote(client) => client.sign_digest_pq(digest),
+        }
+    }
+    /// The current blind signing epoch's certificate, without delegation. Its key is generated,
+    /// certified and kept where the signing key lives
+    pub fn blind_epoch(&self) -> Result<EpochCert, BlindErr> {
+        match self {
+            Signer::Local(kp, _) => Ok(crate::config::epoch_keys().current(kp)?.cert.clone()),
+            Signer::Remote(client) => client.blind_epoch(),
+        }
+    }
+    /// Signs a blinded message with the epoch key `key_id`, refused once its epoch is over
+    pub fn blind_sign(&self, key_id: &str, blinded: &[u8]) -> Result<Vec<u8>, BlindErr> {
+        match self {
+            Signer::Local(..) => crate::config::epoch_keys()
+                .get(key_id)?
+                .sign_blinded(blinded),
+            Signer::Remote(client) => client.blind_sign(key_id, blinded),
+        }
+    }
+    pub fn verify(&self, message: &[u8], sig: impl AsRef<[u8]>) -> bool {
+        crypto_sign::verify(&self.pubkey(), message, sig)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SignerErr {
+    #[error("signer socket IO err: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("signer refused the request")]
+    Refused,
+    #[error("signer returned a signature that doesn't verify")]
+    BadSignature,
+    #[error("the blind signing key's epoch is over")]
+    EpochOver,
+    #[error("signer refused the blinded message")]
+    BadBlindedMessage,
+    #[error("blind signing err: {0}")]
+    Blind(String),
+    #[error(transparent)]
+    Kp(#[from] KpErr),
+}
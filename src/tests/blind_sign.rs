@@ -0,0 +1,64 @@
+use chrono::Duration;
+//
+use crate::utils::blind_sign::{BlindErr, Blinded, EpochKeys};
+use crate::utils::crypto_sign::KeyPair;
+
+// Happy path: the unblinded receipt verifies, for the data only
+#[test]
+fn test__blind_sign__OK() -> Result<(), anyhow::Error> {
+    let key = EpochKeys::new(Duration::hours(1)).current(crate::config::keypair())?;
+    let anchor = crate::config::trust_anchor();
+    assert!(key.cert.verify(&anchor));
+
+    let data = b"data the server never sees";
+    let blinded = Blinded::new(&key.cert, data)?;
+    let blind_sig = key.sign_blinded(&blinded.blinded)?;
+    let receipt = blinded.finalize(&blind_sig)?;
+
+    assert!(receipt.verify(data, &anchor));
+    assert!(!receipt.verify(b"other data", &anchor));
+    assert!(!receipt.verify(data, &KeyPair::generate().pubkey()));
+
+    // the signature isn't the blind one the server saw
+    assert_ne!(base64::decode(&receipt.signature_base64)?, blind_sig);
+    Ok(())
+}
+
+// The same data blinds differently each time: the server can't recognize it
+#[test]
+fn test__blind_sign__Unlinkable() -> Result<(), anyhow::Error> {
+    let key = EpochKeys::new(Duration::hours(1)).current(crate::config::keypair())?;
+    let data = b"same data";
+    let a = Blinded::new(&key.cert, data)?;
+    let b = Blinded::new(&key.cert, data)?;
+    assert_ne!(a.blinded, b.blinded);
+    Ok(())
+}
+
+// Blinded messages must be as long as the modulus
+#[test]
+fn test__blind_sign__BadBlindedMessage() -> Result<(), anyhow::Error> {
+    let key = EpochKeys::new(Duration::hours(1)).current(crate::config::keypair())?;
+    let err = key.sign_blinded(&[1u8; 32]).unwrap_err();
+    assert!(matches!(err, BlindErr::BadBlindedMessage), "got: {}", err);
+    let err = key.sign_blinded(&[0xffu8; 256]).unwrap_err();
+    assert!(matches!(err, BlindErr::BadBlindedMessage), "got: {}", err);
+    Ok(())
+}
+
+// Only the current epoch's key is found for signing, by its id
+#[test]
+fn test__blind_sign__EpochKeys() -> Result<(), anyhow::Error> {
+    let keys = EpochKeys::new(Duration::hours(1));
+    assert!(matches!(
+        keys.get("0123456789abcdef"),
+        Err(BlindErr::EpochOver)
+    ));
+    let key = keys.current(crate::config::keypair())?;
+    assert!(keys.get(&key.cert.key_id()).is_ok());
+    assert!(matches!(
+        keys.get("0123456789abcdef"),
+        Err(BlindErr::EpochOver)
+    ));
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use chrono::{Duration, Utc};
+use std::net::UdpSocket;
+use std::time::Duration as StdDuration;
+//
+use crate::utils::clock::{self, ClockErr, ClockGuard, ClockSource};
+
+// Local stand-in for an NTP server, answering one query with its clock `skew` ahead of ours
+fn ntp_stand_in(skew: Duration) -> Result<ClockSource, anyhow::Error> {
+    let socket = UdpSocket::bind("127.0.0.1:0")?;
+    let addr = socket.local_addr()?;
+    std::thread::spawn(move || -> Result<(), std::io::Error> {
+        let mut req = [0u8; 48];
+        let (_, client) = socket.recv_from(&mut req)?;
+        let now = clock::to_ntp_timestamp(Utc::now().naive_utc() + skew);
+        let mut resp = [0u8; 48];
+        resp[0] = 0x24; // version 4, server mode
+        resp[1] = 1; // stratum
+        resp[24..32].copy_from_slice(&req[40..48]);
+        resp[32..40].copy_from_slice(&now);
+        resp[40..48].copy_from_slice(&now);
+        socket.send_to(&resp, client)?;
+        Ok(())
+    });
+    Ok(ClockSource::parse(&format!("ntp:{}", addr))?)
+}
+// checked once, as the background refresh would
+fn guard(sources: Vec<ClockSource>) -> ClockGuard {
+    let guard = ClockGuard::new(sources, Duration::seconds(1), StdDuration::from_secs(60));
+    guard.refresh();
+    guard
+}
+
+// Happy path: reference clocks agree with ours
+#[test]
+fn test__clock__OK() -> Result<(), anyhow::Error> {
+    let sources = vec![
+        ntp_stand_in(Duration::milliseconds(100))?,
+        ntp_stand_in(Duration::milliseconds(-50))?,
+        ntp_stand_in(Duration::milliseconds(20))?,
+    ];
+    let accuracy = guard(sources).accuracy()?.unwrap();
+
+    assert!(accuracy >= Duration::milliseconds(20), "got {}", accuracy);
+    assert!(accuracy < Duration::milliseconds(500), "got {}", accuracy);
+    Ok(())
+}
+
+// Our clock is off by more than the bound
+#[test]
+fn test__clock__Skewed() -> Result<(), anyhow::Error> {
+    let sources = vec![ntp_stand_in(Duration::minutes(5))?];
+    let err = guard(sources).accuracy().unwrap_err();
+
+    assert!(matches!(err, ClockErr::Skewed { .. }), "got: {}", err);
+    Ok(())
+}
+
+// One lying source is outvoted by the median
+#[test]
+fn test__clock__Outvoted() -> Result<(), anyhow::Error> {
+    let sources = vec![
+        ntp_stand_in(Duration::hours(-3))?,
+        ntp_stand_in(Duration::zero())?,
+        ntp_stand_in(Duration::milliseconds(10))?,
+    ];
+    assert!(guard(sources).accuracy().is_ok());
+    Ok(())
+}
+
+// No source answers: fail closed
+#[test]
+fn test__clock__NoSource() -> Result<(), anyhow::Error> {
+    let closed_port = UdpSocket::bind("127.0.0.1:0")?.local_addr()?; // dropped right away
+    let sources = vec![ClockSource::parse(&format!("ntp:{}", closed_port))?];
+    let err = guard(sources).accuracy().unwrap_err();
+
+    assert!(matches!(err, ClockErr::NoSource), "got: {}", err);
+    Ok(())
+}
+
+// Before any check, or once checks stop, the clock isn't vouched for
+#[test]
+fn test__clock__Stale() -> Result<(), anyhow::Error> {
+    let sources = vec![ntp_stand_in(Duration::zero())?];
+    let guard = ClockGuard::new(sources, Duration::seconds(1), StdDuration::from_millis(10));
+    let err = guard.accuracy().unwrap_err();
+    assert!(matches!(err, ClockErr::Stale), "got: {}", err);
+
+    guard.refresh();
+    assert!(guard.accuracy()?.is_some());
+    std::thread::sleep(StdDuration::from_millis(50));
+    let err = guard.accuracy().unwrap_err();
+    assert!(matches!(err, ClockErr::Stale), "got: {}", err);
+    Ok(())
+}
+
+// Without sources, nothing is claimed about accuracy
+#[test]
+fn test__clock__Unchecked() -> Result<(), anyhow::Error> {
+    assert!(guard(vec![]).accuracy()?.is_none());
+    assert!(ClockSource::parse_list("")?.is_empty());
+    assert_eq!(
+        ClockSource::parse_list("ntp:pool.ntp.org, ntp:[::1]")?,
+        vec![
+            ClockSource::Ntp("pool.ntp.org:123".into()),
+            ClockSource::Ntp("[::1]:123".into())
+        ]
+    );
+    assert!(ClockSource::parse("pool.ntp.org").is_err());
+    Ok(())
+}
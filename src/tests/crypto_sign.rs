@@ -0,0 +1,151 @@
+use crate::utils::crypto_sign::{AsBytes, KeyMode, KeyPair, KpErr, B64};
+
+// every way the secret could plausibly end up rendered in a log line
+fn secret_renderings(kp: &KeyPair) -> Vec<String> {
+    let bytes = kp.to_bytes();
+    let secret = &bytes[..32];
+    vec![
+        kp.to_str(),
+        base64::encode(secret),
+        format!("{:?}", secret),
+        secret.iter().map(|b| format!("{:02x}", b)).collect(),
+    ]
+}
+
+// Debug output only shows the public key
+#[test]
+fn test__keypair__Debug_hides_secret() {
+    let kp = KeyPair::generate();
+    let debug_str = format!("{:?}", kp);
+
+    assert!(debug_str.contains(&base64::encode(kp.pubkey().as_bytes())));
+    for secret_str in secret_renderings(&kp) {
+        assert!(
+            !debug_str.contains(&secret_str),
+            "Debug output leaks secret key"
+        );
+    }
+}
+
+// Malformed key material never shows up in errors
+#[test]
+fn test__KpErr__hides_key_material() {
+    let kp = KeyPair::generate();
+    let key_b64 = kp.to_str();
+
+    let invalid_b64 = format!("{}#{}", &key_b64[..40], &key_b64[40..]);
+    let wrong_length = base64::encode(&kp.to_bytes()[..63]);
+    for malformed in &[invalid_b64, wrong_length] {
+        let err = KeyPair::from_str(malformed).unwrap_err();
+        for err_str in &[format!("{}", err), format!("{:?}", err)] {
+            assert!(
+                !err_str.contains(&key_b64[..16]),
+                "error leaks key: {}",
+                err_str
+            );
+            assert!(
+                !err_str.contains("Invalid byte"),
+                "error leaks key: {}",
+                err_str
+            );
+            for secret_str in secret_renderings(&kp) {
+                assert!(
+                    !err_str.contains(&secret_str),
+                    "error leaks key: {}",
+                    err_str
+                );
+            }
+        }
+    }
+}
+
+// Secret survives encoding roundtrip
+#[test]
+fn test__keypair__B64_roundtrip() -> Result<(), anyhow::Error> {
+    let kp = KeyPair::generate();
+    let kp2 = KeyPair::from_str(&kp.to_str())?;
+
+    assert_eq!(kp.pubkey().to_bytes(), kp2.pubkey().to_bytes());
+    let sig = kp2.sign(b"hello dog this is data");
+    assert_eq!(kp.verify(b"hello dog this is data", &sig[..]), true);
+    Ok(())
+}
+
+fn temp_keyfile(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir()
+        .join(format!("crypto_sign-test-{}", std::process::id()))
+        .join(name);
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+// Strict mode: a missing key is an error, not a new key
+#[test]
+fn test__keypair__load_Strict_missing() {
+    let keyfile = temp_keyfile("strict_missing");
+    let err = KeyPair::load(&keyfile, KeyMode::Strict).unwrap_err();
+
+    assert!(matches!(err, KpErr::Missing(_)), "got: {}", err);
+    assert!(!keyfile.exists(), "strict mode shouldn't create a key");
+}
+
+// GenerateIfMissing: creates the key once, then keeps loading the same one
+#[test]
+fn test__keypair__load_GenerateIfMissing() -> Result<(), anyhow::Error> {
+    let keyfile = temp_keyfile("generate_if_missing");
+    let kp = KeyPair::load(&keyfile, KeyMode::GenerateIfMissing)?;
+    let kp2 = KeyPair::load(&keyfile, KeyMode::Strict)?;
+
+    assert_eq!(kp.pubkey().to_bytes(), kp2.pubkey().to_bytes());
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(&keyfile)?.permissions().mode();
+    assert_eq!(
+        mode & 0o777,
+        0o600,
+        "key file should only be readable by owner"
+    );
+    Ok(())
+}
+
+// Corrupted key files are reported and left untouched, even when generating is allowed
+#[test]
+fn test__keypair__load_corrupted() -> Result<(), anyhow::Error> {
+    let kp = KeyPair::generate();
+    let malformed = temp_keyfile("malformed");
+    let wrong_length = temp_keyfile("wrong_length");
+    std::fs::create_dir_all(malformed.parent().unwrap())?;
+    std::fs::write(&malformed, "not a key !")?;
+    std::fs::write(&wrong_length, base64::encode(&kp.to_bytes()[..32]))?;
+
+    let err = KeyPair::load(&malformed, KeyMode::GenerateIfMissing).unwrap_err();
+    assert!(matches!(err, KpErr::Malformed { .. }), "got: {}", err);
+    assert_eq!(std::fs::read_to_string(&malformed)?, "not a key !");
+
+    let err = KeyPair::load(&wrong_length, KeyMode::GenerateIfMissing).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            KpErr::WrongLength {
+                expected: 64,
+                got: 32,
+                ..
+            }
+        ),
+        "got: {}",
+        err
+    );
+    Ok(())
+}
+
+// Explicit generation never overwrites
+#[test]
+fn test__keypair__generate_to_file_AlreadyExists() -> Result<(), anyhow::Error> {
+    let keyfile = temp_keyfile("already_exists");
+    let kp = KeyPair::generate_to_file(&keyfile)?;
+    let err = KeyPair::generate_to_file(&keyfile).unwrap_err();
+
+    assert!(matches!(err, KpErr::AlreadyExists(_)), "got: {}", err);
+    let kp2 = KeyPair::load(&keyfile, KeyMode::Strict)?;
+    assert_eq!(kp.pubkey().to_bytes(), kp2.pubkey().to_bytes());
+    Ok(())
+}
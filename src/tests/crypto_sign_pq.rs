@@ -0,0 +1,148 @@
+use chrono::{Duration, Local};
+//
+use crate::routes::sign_data::{FieldsSigned, SignDataResp, Verdict};
+use crate::utils::crypto_sign::{KeyMode, KeyPair, KpErr};
+use crate::utils::crypto_sign_pq::{verify_pq, PqKeyPair};
+use crate::utils::delegation::Delegation;
+use crate::utils::revocation::RevocationList;
+
+fn hybrid_receipt(kp: &KeyPair, pq_kp: &PqKeyPair) -> Result<SignDataResp, anyhow::Error> {
+    let fields_signed = FieldsSigned {
+        data_hash_base64: base64::encode(blake3::hash(b"hello dog this is data").as_bytes()),
+        timestamp: Local::now().naive_local(),
+        accuracy_ms: None,
+        serial: 1,
+        prev_receipt_hash_base64: None,
+    };
+    let hash = fields_signed.hash()?;
+    Ok(SignDataResp {
+        signature_base64: base64::encode(&kp.sign(&hash)[..]),
+        signature_pq_base64: Some(base64::encode(&pq_kp.sign(&hash))),
+        fields_signed,
+        delegation: None,
+        earliest: None,
+    })
+}
+
+// Happy path: PQ keys survive a file roundtrip and sign verifiably
+#[test]
+fn test__pq_keypair__OK() -> Result<(), anyhow::Error> {
+    let keyfile = std::env::temp_dir()
+        .join(format!("crypto_sign_pq-test-{}", std::process::id()))
+        .join("keypair_sign_pq");
+    let _ = std::fs::remove_file(&keyfile);
+    let pq_kp = PqKeyPair::load(&keyfile, KeyMode::GenerateIfMissing)?;
+    let pq_kp2 = PqKeyPair::load(&keyfile, KeyMode::Strict)?;
+    assert_eq!(pq_kp.pubkey_bytes(), pq_kp2.pubkey_bytes());
+
+    let sig = pq_kp2.sign(b"hello dog this is data");
+    assert!(verify_pq(
+        pq_kp.pubkey_bytes(),
+        b"hello dog this is data",
+        &sig
+    ));
+    assert!(!verify_pq(
+        pq_kp.pubkey_bytes(),
+        b"hello dog this is other data",
+        &sig
+    ));
+    Ok(())
+}
+
+// Hybrid receipts need both signatures to verify
+#[test]
+fn test__pq_keypair__hybrid_receipt() -> Result<(), anyhow::Error> {
+    let (kp, pq_kp) = (KeyPair::generate(), PqKeyPair::generate());
+    let revocations = RevocationList::default();
+    let mut receipt = hybrid_receipt(&kp, &pq_kp)?;
+    let pinned_pq = Some(pq_kp.pubkey_bytes());
+
+    assert_eq!(
+        receipt.verify(&kp.pubkey(), pinned_pq, &revocations),
+        Verdict::Trusted
+    );
+    let other_pq_kp = PqKeyPair::generate();
+    assert_eq!(
+        receipt.verify(&kp.pubkey(), Some(other_pq_kp.pubkey_bytes()), &revocations),
+        Verdict::BadSignature
+    );
+    receipt.signature_pq_base64 = None;
+    assert_eq!(
+        receipt.verify(&kp.pubkey(), pinned_pq, &revocations),
+        Verdict::BadSignature
+    );
+    Ok(())
+}
+
+// A key file whose pubkey isn't the secret's is refused on load, not discovered by clients
+#[test]
+fn test__pq_keypair__Mismatched() -> Result<(), anyhow::Error> {
+    let keyfile = std::env::temp_dir()
+        .join(format!(
+            "crypto_sign_pq-test-mismatched-{}",
+            std::process::id()
+        ))
+        .join("keypair_sign_pq");
+    let _ = std::fs::remove_file(&keyfile);
+    PqKeyPair::generate_to_file(&keyfile)?;
+    let content = std::fs::read_to_string(&keyfile)?;
+    let secret_b64 = content.splitn(2, '.').nth(1).unwrap();
+    let other_pubkey_b64 = base64::encode(PqKeyPair::generate().pubkey_bytes());
+    std::fs::write(&keyfile, format!("{}.{}", other_pubkey_b64, secret_b64))?;
+
+    let err = PqKeyPair::load(&keyfile, KeyMode::Strict).unwrap_err();
+    assert!(matches!(err, KpErr::Malformed { .. }), "got: {}", err);
+    std::fs::remove_file(&keyfile)?;
+    Ok(())
+}
+
+// Delegated hybrid receipts: the PQ signature is checked against the key the delegation certifies
+#[test]
+fn test__pq_keypair__delegated_receipt() -> Result<(), anyhow::Error> {
+    let (root, kp, pq_kp) = (
+        KeyPair::generate(),
+        KeyPair::generate(),
+        PqKeyPair::generate(),
+    );
+    let revocations = RevocationList::default();
+    let mut receipt = hybrid_receipt(&kp, &pq_kp)?;
+    let require_pq = Some(&[][..]);
+
+    receipt.delegation = Some(Delegation::issue(
+        &root,
+        &kp.pubkey(),
+        Some(pq_kp.pubkey_bytes()),
+        Duration::days(1),
+    )?);
+    assert_eq!(
+        receipt.verify(&root.pubkey(), require_pq, &revocations),
+        Verdict::Trusted
+    );
+
+    receipt.delegation = Some(Delegation::issue(
+        &root,
+        &kp.pubkey(),
+        Some(PqKeyPair::generate().pubkey_bytes()),
+        Duration::days(1),
+    )?);
+    assert_eq!(
+        receipt.verify(&root.pubkey(), require_pq, &revocations),
+        Verdict::BadSignature
+    );
+
+    receipt.delegation = Some(Delegation::issue(
+        &root,
+        &kp.pubkey(),
+        None,
+        Duration::days(1),
+    )?);
+    assert_eq!(
+        receipt.verify(&root.pubkey(), require_pq, &revocations),
+        Verdict::BadDelegation
+    );
+    assert_eq!(
+        receipt.verify(&root.pubkey(), None, &revocations),
+        Verdict::Trusted
+    );
+    Ok(())
+}
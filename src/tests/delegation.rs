@@ -0,0 +1,106 @@
+use chrono::{Duration, Local, Utc};
+//
+use crate::utils::crypto_sign::KeyPair;
+use crate::utils::delegation::{Delegation, DelegationErr};
+use crate::utils::evidence::{Renewal, RenewalFields};
+
+// Happy path: the root vouches for the online key, for a limited time
+#[test]
+fn test__delegation__OK() -> Result<(), anyhow::Error> {
+    let root = KeyPair::generate();
+    let online = KeyPair::generate();
+    let delegation = Delegation::issue(&root, &online.pubkey(), None, Duration::days(7))?;
+
+    delegation.verify(&root.pubkey())?;
+    assert_eq!(delegation.root_pubkey()?, root.pubkey());
+    assert_eq!(delegation.online_pubkey()?, online.pubkey());
+    let now = Utc::now();
+    assert!(delegation.covers(now + Duration::days(1)));
+    assert!(!delegation.covers(now + Duration::days(8)));
+    assert!(!delegation.covers(now - Duration::days(1)));
+    Ok(())
+}
+
+// Tampering with the delegated key or validity breaks the root's signature
+#[test]
+fn test__delegation__Tampered() -> Result<(), anyhow::Error> {
+    let root = KeyPair::generate();
+    let mut delegation = Delegation::issue(
+        &root,
+        &KeyPair::generate().pubkey(),
+        None,
+        Duration::days(7),
+    )?;
+    delegation.fields_signed.online_pubkey_base64 =
+        base64::encode(KeyPair::generate().pubkey().as_bytes());
+
+    let err = delegation.verify(&root.pubkey()).unwrap_err();
+    assert!(matches!(err, DelegationErr::BadSignature), "got: {}", err);
+
+    let mut delegation = Delegation::issue(
+        &root,
+        &KeyPair::generate().pubkey(),
+        None,
+        Duration::days(7),
+    )?;
+    delegation.fields_signed.not_after = delegation.fields_signed.not_after + Duration::days(365);
+    assert!(delegation.verify(&root.pubkey()).is_err());
+    Ok(())
+}
+
+// A delegation signed by any other root than the pinned one is rejected, though it verifies on its own
+#[test]
+fn test__delegation__OtherRoot() -> Result<(), anyhow::Error> {
+    let pinned = KeyPair::generate();
+    let other = KeyPair::generate();
+    let delegation = Delegation::issue(
+        &other,
+        &KeyPair::generate().pubkey(),
+        None,
+        Duration::days(7),
+    )?;
+
+    delegation.verify(&other.pubkey())?;
+    let err = delegation.verify(&pinned.pubkey()).unwrap_err();
+    assert!(matches!(err, DelegationErr::OtherRoot), "got: {}", err);
+
+    // claiming the pinned root in the fields doesn't help
+    let mut delegation = delegation;
+    delegation.fields_signed.root_pubkey_base64 = base64::encode(pinned.pubkey().as_bytes());
+    let err = delegation.verify(&pinned.pubkey()).unwrap_err();
+    assert!(matches!(err, DelegationErr::BadSignature), "got: {}", err);
+    Ok(())
+}
+
+// A renewal by the online key verifies against the root through its delegation, in its window only
+#[test]
+fn test__delegation__Renewal() -> Result<(), anyhow::Error> {
+    let root = KeyPair::generate();
+    let online = KeyPair::generate();
+    let delegation = Delegation::issue(&root, &online.pubkey(), None, Duration::days(7))?;
+    let renewal = |timestamp| -> Result<Renewal, anyhow::Error> {
+        let fields_signed = RenewalFields {
+            merkle_root_base64: base64::encode(&[0u8; 32]),
+            leaf_count: 1,
+            algorithm: "blake3-merkle+ed25519".to_string(),
+            timestamp,
+        };
+        let signature = online.sign(&fields_signed.hash()?);
+        Ok(Renewal {
+            fields_signed,
+            signature_base64: base64::encode(&signature[..]),
+            signature_pq_base64: None,
+            delegation: Some(delegation.clone()),
+        })
+    };
+
+    let now = Local::now().naive_local();
+    let mut current = renewal(now)?;
+    assert!(current.verify(&root.pubkey()));
+    assert!(!current.verify(&online.pubkey()));
+    assert!(!renewal(now + Duration::days(8))?.verify(&root.pubkey()));
+    // the online key alone isn't trusted
+    current.delegation = None;
+    assert!(!current.verify(&root.pubkey()));
+    Ok(())
+}
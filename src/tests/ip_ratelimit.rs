@@ -0,0 +1,133 @@
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+//
+use crate::routes::middleware::ip_ratelimit::{Cidr, RateLimitErr, RateLimiter};
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+// Happy path: bursts up to the limit, then refills over time
+#[test]
+fn test__ip_ratelimit__OK() -> Result<(), anyhow::Error> {
+    let limiter = RateLimiter::new(3, 100, vec![]);
+    let now = Instant::now();
+    for _ in 0..3 {
+        limiter.check(ip("192.0.2.1"), now)?;
+    }
+    match limiter.check(ip("192.0.2.1"), now) {
+        Err(RateLimitErr::TooManyRequests { retry_after_secs }) => {
+            assert_eq!(retry_after_secs, 20)
+        }
+        res => panic!("expected TooManyRequests, got: {:?}", res),
+    }
+    // other addresses have their own bucket
+    limiter.check(ip("192.0.2.2"), now)?;
+    // one token back every 20s at 3 per minute
+    limiter.check(ip("192.0.2.1"), now + Duration::from_secs(20))?;
+    Ok(())
+}
+
+// Addresses of a subnet share its bucket
+#[test]
+fn test__ip_ratelimit__Subnet() -> Result<(), anyhow::Error> {
+    let limiter = RateLimiter::new(100, 2, vec![]);
+    let now = Instant::now();
+    limiter.check(ip("198.51.100.1"), now)?;
+    limiter.check(ip("198.51.100.2"), now)?;
+    assert!(limiter.check(ip("198.51.100.3"), now).is_err());
+    limiter.check(ip("198.51.101.1"), now)?;
+
+    limiter.check(ip("2001:db8:1:1::1"), now)?;
+    limiter.check(ip("2001:db8:1:2::1"), now)?;
+    assert!(limiter.check(ip("2001:db8:1:3::1"), now).is_err());
+    Ok(())
+}
+
+// Requests refused for their subnet don't cost their IP a token
+#[test]
+fn test__ip_ratelimit__SubnetRefunds() -> Result<(), anyhow::Error> {
+    let limiter = RateLimiter::new(2, 4, vec![]);
+    let now = Instant::now();
+    for client in &["203.0.113.1", "203.0.113.1", "203.0.113.2", "203.0.113.2"] {
+        limiter.check(ip(client), now)?;
+    }
+    assert!(limiter.check(ip("203.0.113.3"), now).is_err());
+    assert!(limiter.check(ip("203.0.113.3"), now).is_err());
+    // one subnet token back every 15s at 4 per minute: the IP's bucket is still full
+    limiter.check(ip("203.0.113.3"), now + Duration::from_secs(15))?;
+    Ok(())
+}
+
+// X-Forwarded-For is only believed from trusted proxies, and only up to the first untrusted hop
+#[test]
+fn test__ip_ratelimit__ForwardedFor() -> Result<(), anyhow::Error> {
+    let limiter = RateLimiter::new(60, 600, Cidr::parse_list("10.0.0.0/8, 192.0.2.7")?);
+
+    let xff = Some("203.0.113.9");
+    assert_eq!(
+        limiter.client_ip(ip("198.51.100.1"), xff),
+        ip("198.51.100.1")
+    );
+    assert_eq!(limiter.client_ip(ip("10.1.2.3"), xff), ip("203.0.113.9"));
+    // the client can prepend anything, proxies append
+    let xff = Some("1.2.3.4, 203.0.113.9, 192.0.2.7");
+    assert_eq!(limiter.client_ip(ip("10.1.2.3"), xff), ip("203.0.113.9"));
+    assert_eq!(limiter.client_ip(ip("10.1.2.3"), None), ip("10.1.2.3"));
+    assert_eq!(
+        limiter.client_ip(ip("10.1.2.3"), Some("garbage")),
+        ip("10.1.2.3")
+    );
+
+    assert!(Cidr::parse_list("10.0.0.0/33").is_err());
+    assert!(Cidr::parse_list("not-an-ip").is_err());
+    Ok(())
+}
+
+// Malformed requests are limited too, with a 429 telling when to retry
+#[tokio::test]
+async fn test__ip_ratelimit__TooManyRequests() {
+    let remote = format!(
+        "10.{}.{}.1:4000",
+        rand::random::<u8>(),
+        rand::random::<u8>()
+    );
+    let router = crate::router();
+    for _ in 0..1000 {
+        let res = warp::test::request()
+            .method("POST")
+            .path("/sign_data")
+            .remote_addr(remote.parse().unwrap())
+            .body("not json")
+            .reply(&router)
+            .await;
+        if res.status() == 400 {
+            continue;
+        }
+        assert_eq!(res.status(), 429, "Should return 429 Too Many Requests");
+        assert!(res.headers().contains_key("retry-after"));
+        assert_eq!(
+            res.body(),
+            r#"{"code":429,"message":"Too many requests","status":"error"}"#
+        );
+        return;
+    }
+    panic!("never rate-limited");
+}
+
+// A flood of distinct addresses doesn't grow memory without bound: the oldest buckets go first
+#[test]
+fn test__ip_ratelimit__MaxBuckets() -> Result<(), anyhow::Error> {
+    let limiter = RateLimiter::new(1, 0, vec![]);
+    let now = Instant::now();
+    limiter.check(ip("2001:db8::1"), now)?;
+    assert!(limiter.check(ip("2001:db8::1"), now).is_err());
+
+    for i in 0..100_000u128 {
+        let flood = IpAddr::from(std::net::Ipv6Addr::from(0x2001_0db8_ffff_u128 << 80 | i));
+        limiter.check(flood, now)?;
+    }
+    // evicted: back with a full bucket
+    limiter.check(ip("2001:db8::1"), now)?;
+    Ok(())
+}
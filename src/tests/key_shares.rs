@@ -0,0 +1,74 @@
+use crate::utils::crypto_sign::KeyPair;
+use crate::utils::key_shares::{combine, split, SharesErr};
+
+// Happy path: any k of n shares restore the key
+#[test]
+fn test__key_shares__OK() -> Result<(), anyhow::Error> {
+    let kp = KeyPair::generate();
+    let shares = split(&kp, 3, 5)?;
+    assert_eq!(shares.len(), 5);
+
+    for picked in &[[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+        let subset: Vec<String> = picked.iter().map(|&i| shares[i].clone()).collect();
+        let restored = combine(&subset)?;
+        assert_eq!(restored.pubkey().to_bytes(), kp.pubkey().to_bytes());
+    }
+    Ok(())
+}
+
+// Printable: QR alphanumeric charset only
+#[test]
+fn test__key_shares__QR_friendly() -> Result<(), anyhow::Error> {
+    let shares = split(&KeyPair::generate(), 2, 3)?;
+    for share in shares {
+        assert!(share
+            .chars()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase() || c == '-'));
+    }
+    Ok(())
+}
+
+// Fewer than k shares
+#[test]
+fn test__key_shares__NotEnoughShares() -> Result<(), anyhow::Error> {
+    let shares = split(&KeyPair::generate(), 3, 5)?;
+    let err = combine(&[shares[0].clone(), shares[1].clone(), shares[1].clone()]).unwrap_err();
+
+    assert!(
+        matches!(err, SharesErr::NotEnoughShares { needed: 3, got: 2 }),
+        "got: {}",
+        err
+    );
+    Ok(())
+}
+
+// A mistyped share is caught before any reconstruction
+#[test]
+fn test__key_shares__BadChecksum() -> Result<(), anyhow::Error> {
+    let mut shares = split(&KeyPair::generate(), 2, 3)?;
+    let typo = if shares[1].as_bytes()[20] == b'0' {
+        "1"
+    } else {
+        "0"
+    };
+    shares[1].replace_range(20..21, typo);
+    let err = combine(&shares[..2]).unwrap_err();
+
+    assert!(
+        matches!(err, SharesErr::BadChecksum { share: 2 }),
+        "got: {}",
+        err
+    );
+    Ok(())
+}
+
+// Shares of different keys can't be mixed
+#[test]
+fn test__key_shares__MixedKeys() -> Result<(), anyhow::Error> {
+    let shares = split(&KeyPair::generate(), 2, 3)?;
+    let other_shares = split(&KeyPair::generate(), 2, 3)?;
+    let err = combine(&[shares[0].clone(), other_shares[1].clone()]).unwrap_err();
+
+    assert!(matches!(err, SharesErr::MixedKeys), "got: {}", err);
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use crate::utils::merkle;
+
+fn leaves(n: u8) -> Vec<[u8; 32]> {
+    (0..n).map(|i| *blake3::hash(&[i]).as_bytes()).collect()
+}
+
+// Every leaf's path leads to the root, whatever the tree's shape
+#[test]
+fn test__merkle__Paths() {
+    for n in 1..=9 {
+        let leaves = leaves(n);
+        let root = merkle::root(&leaves).unwrap();
+        for (i, leaf) in leaves.iter().enumerate() {
+            let path = merkle::path(&leaves, i).unwrap();
+            assert_eq!(
+                merkle::root_from_path(leaf, &path),
+                Some(root),
+                "leaf {} of {}",
+                i,
+                n
+            );
+        }
+        assert!(merkle::path(&leaves, n as usize).is_none());
+    }
+    assert!(merkle::root(&[]).is_none());
+}
+
+// A path doesn't prove another leaf, nor a leaf on the other side
+#[test]
+fn test__merkle__WrongLeaf() {
+    let leaves = leaves(5);
+    let root = merkle::root(&leaves).unwrap();
+    let mut path = merkle::path(&leaves, 2).unwrap();
+
+    assert_ne!(merkle::root_from_path(&leaves[3], &path), Some(root));
+    path[0].sibling_is_left = !path[0].sibling_is_left;
+    assert_ne!(merkle::root_from_path(&leaves[2], &path), Some(root));
+}
+
+// A single leaf is still hashed: the root never equals a leaf
+#[test]
+fn test__merkle__SingleLeaf() {
+    let leaves = leaves(1);
+    assert_ne!(merkle::root(&leaves), Some(leaves[0]));
+}
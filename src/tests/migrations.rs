@@ -0,0 +1,61 @@
+use crate::models::{self, kv::KvStorage, sqlite::SqliteStorage, Storage};
+
+// the 14 digits of the newest migration's name, as diesel records its version
+fn newest_migration(dir: &str) -> Result<String, anyhow::Error> {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(dir);
+    let newest = std::fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<String>, std::io::Error>>()?
+        .into_iter()
+        .max()
+        .unwrap();
+    Ok(newest
+        .split('_')
+        .next()
+        .unwrap()
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .collect())
+}
+
+// Each backend's SCHEMA_VERSION is bumped with each of its new migrations
+#[test]
+fn test__migrations__SchemaVersion() -> Result<(), anyhow::Error> {
+    assert_eq!(
+        newest_migration("migrations")?,
+        models::postgres::SCHEMA_VERSION
+    );
+    assert_eq!(
+        newest_migration("migrations_sqlite")?,
+        models::sqlite::SCHEMA_VERSION
+    );
+    Ok(())
+}
+
+// A fresh SQLite file: rejected until migrated, then nothing is pending
+#[test]
+fn test__migrations__Migrate() -> Result<(), anyhow::Error> {
+    let path = std::env::temp_dir().join(format!("test-{}.sqlite", rand::random::<u64>()));
+    let storage = SqliteStorage::open(&path)?;
+    assert!(storage.check_schema().is_err());
+    assert!(storage.migrate()?);
+    assert!(!storage.migrate()?);
+    storage.check_schema()?;
+    drop(storage);
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+// A fresh sled directory: rejected until its version is recorded
+#[test]
+fn test__migrations__MigrateSled() -> Result<(), anyhow::Error> {
+    let dir = std::env::temp_dir().join(format!("test-{}.sled", rand::random::<u64>()));
+    let storage = KvStorage::open(&dir)?;
+    assert!(storage.check_schema().is_err());
+    assert!(storage.migrate()?);
+    assert!(!storage.migrate()?);
+    storage.check_schema()?;
+    drop(storage);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use chrono::{Duration, Local};
+//
+use crate::routes::sign_data::{FieldsSigned, SignDataResp, Verdict};
+use crate::utils::crypto_sign::{self, KeyPair};
+use crate::utils::revocation::{Revocation, RevocationErr, RevocationList};
+
+fn receipt(kp: &KeyPair, timestamp: chrono::NaiveDateTime) -> Result<SignDataResp, anyhow::Error> {
+    let fields_signed = FieldsSigned {
+        data_hash_base64: base64::encode(blake3::hash(b"hello dog this is data").as_bytes()),
+        timestamp,
+        accuracy_ms: None,
+        serial: 1,
+        prev_receipt_hash_base64: None,
+    };
+    Ok(SignDataResp {
+        signature_base64: base64::encode(&kp.sign(&fields_signed.hash()?)[..]),
+        signature_pq_base64: None,
+        fields_signed,
+        delegation: None,
+        earliest: None,
+    })
+}
+fn revocation_list(
+    dir_name: &str,
+    revocation: &Revocation,
+) -> Result<RevocationList, anyhow::Error> {
+    let dir = std::env::temp_dir()
+        .join(format!("revocation-test-{}", std::process::id()))
+        .join(dir_name);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.json", revocation.fields_signed.key_id));
+    std::fs::write(path, serde_json::to_vec(revocation)?)?;
+    Ok(RevocationList::load(&dir, &KeyPair::generate().pubkey())?)
+}
+
+// Compromised key: receipts before the compromise stay valid, later ones are flagged
+#[test]
+fn test__revocation__Compromised() -> Result<(), anyhow::Error> {
+    let kp = KeyPair::generate();
+    let now = Local::now().naive_local();
+    let compromised_since = now - Duration::days(10);
+    let revocation = Revocation::issue(&kp, &kp.pubkey(), Some(compromised_since), "leaked")?;
+    let revocations = revocation_list("compromised", &revocation)?;
+
+    let before = receipt(&kp, now - Duration::days(11))?;
+    let after = receipt(&kp, now - Duration::days(9))?;
+    assert_eq!(
+        before.verify(&kp.pubkey(), None, &revocations),
+        Verdict::Trusted
+    );
+    assert_eq!(
+        after.verify(&kp.pubkey(), None, &revocations),
+        Verdict::Untrusted {
+            key_id: crypto_sign::key_id(&kp.pubkey())
+        }
+    );
+    Ok(())
+}
+
+// An unreadable entry is skipped, like an invalid one: the others still load
+#[test]
+fn test__revocation__UnreadableSkipped() -> Result<(), anyhow::Error> {
+    let kp = KeyPair::generate();
+    let revocation = Revocation::issue(&kp, &kp.pubkey(), None, "rotated")?;
+    let dir = std::env::temp_dir()
+        .join(format!("revocation-test-{}", std::process::id()))
+        .join("unreadable");
+    std::fs::create_dir_all(dir.join("unreadable.json"))?;
+    let revocations = revocation_list("unreadable", &revocation)?;
+
+    assert!(revocations.is_revoked(&kp.pubkey()));
+    Ok(())
+}
+
+// Revocations can only be issued by the revoked key or the root key
+#[test]
+fn test__revocation__UnauthorizedIssuer() -> Result<(), anyhow::Error> {
+    let kp = KeyPair::generate();
+    let root = KeyPair::generate();
+    let revocation = Revocation::issue(&KeyPair::generate(), &kp.pubkey(), None, "prank")?;
+
+    let err = revocation.verify(&root.pubkey()).unwrap_err();
+    assert!(
+        matches!(err, RevocationErr::UnauthorizedIssuer),
+        "got: {}",
+        err
+    );
+    let revocation = Revocation::issue(&root, &kp.pubkey(), None, "rotated")?;
+    assert!(revocation.verify(&root.pubkey()).is_ok());
+    Ok(())
+}
+
+// Untampered receipts of an unrevoked key are trusted, tampered ones aren't
+#[test]
+fn test__revocation__verify_receipt() -> Result<(), anyhow::Error> {
+    let kp = KeyPair::generate();
+    let mut receipt = receipt(&kp, Local::now().naive_local())?;
+    let revocations = RevocationList::default();
+
+    assert_eq!(
+        receipt.verify(&kp.pubkey(), None, &revocations),
+        Verdict::Trusted
+    );
+    receipt.fields_signed.timestamp = receipt.fields_signed.timestamp - Duration::days(1);
+    assert_eq!(
+        receipt.verify(&kp.pubkey(), None, &revocations),
+        Verdict::BadSignature
+    );
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use chrono::{Duration, Utc};
+//
+use crate::roughtime::{client, server, Message, RoughtimeErr, NONC, PAD, SREP};
+use crate::utils::clock::{ClockGuard, ClockSource};
+use crate::utils::crypto_sign::KeyPair;
+
+fn longterm_key() -> &'static KeyPair {
+    Box::leak(Box::new(KeyPair::generate()))
+}
+
+// Messages survive the wire, and tags come out sorted
+#[test]
+fn test__roughtime__Message() -> Result<(), anyhow::Error> {
+    let message = Message::new()
+        .with(PAD, vec![0u8; 8])
+        .with(NONC, vec![7u8; 64])
+        .with(SREP, vec![]);
+    let bytes = message.encode();
+
+    assert_eq!(Message::decode(&bytes)?, message);
+    assert_eq!(&bytes[12..16], b"NONC");
+    assert!(Message::decode(&bytes[..8]).is_err());
+    assert!(Message::decode(&[1, 0, 0]).is_err());
+    Ok(())
+}
+
+// Happy path: authenticated time over UDP, usable as a clock source
+#[test]
+fn test__roughtime__OK() -> Result<(), anyhow::Error> {
+    let key = longterm_key();
+    let socket = server::bind("127.0.0.1:0")?;
+    let addr = socket.local_addr()?.to_string();
+    std::thread::spawn(move || server::run(socket, key));
+
+    let time = client::query(&addr, &key.pubkey())?;
+    let now = Utc::now().naive_utc();
+    assert!(time.midpoint > now - Duration::seconds(5) && time.midpoint <= now);
+    assert!(time.radius > Duration::zero());
+
+    let source = ClockSource::parse(&format!(
+        "roughtime:{}@{}",
+        base64::encode(key.pubkey().as_bytes()),
+        addr
+    ))?;
+    let guard = ClockGuard::new(
+        vec![source],
+        Duration::seconds(2),
+        std::time::Duration::from_secs(60),
+    );
+    guard.refresh();
+    assert!(guard.accuracy()?.is_some());
+    Ok(())
+}
+
+// Responses only verify against the server's long-term key, and untampered
+#[test]
+fn test__roughtime__BadSignature() -> Result<(), anyhow::Error> {
+    let key = longterm_key();
+    let mut server = server::Server::new(key);
+    let nonce = [3u8; 64];
+    let response = server.respond(&client::request(&nonce)).unwrap();
+
+    assert!(client::verify_response(&response, &nonce, &key.pubkey()).is_ok());
+    let err =
+        client::verify_response(&response, &nonce, &KeyPair::generate().pubkey()).unwrap_err();
+    assert!(matches!(err, RoughtimeErr::BadSignature(_)), "got: {}", err);
+    let err = client::verify_response(&response, &[4u8; 64], &key.pubkey()).unwrap_err();
+    assert!(matches!(err, RoughtimeErr::BadPath), "got: {}", err);
+
+    let mut tampered = Message::decode(&response)?;
+    let mut srep = Message::decode(tampered.get(SREP)?)?.encode();
+    let last = srep.len() - 1;
+    srep[last] ^= 1;
+    tampered = tampered.with(SREP, srep);
+    assert!(client::verify_response(&tampered.encode(), &nonce, &key.pubkey()).is_err());
+    Ok(())
+}
+
+// Short requests aren't answered: responses must never be larger than requests
+#[test]
+fn test__roughtime__ShortRequest() {
+    let mut server = server::Server::new(longterm_key());
+    let request = Message::new().with(NONC, vec![0u8; 64]).encode();
+
+    assert!(server.respond(&request).is_none());
+    assert!(server.respond(&client::request(&[0u8; 64])).is_some());
+}
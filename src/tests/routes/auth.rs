@@ -0,0 +1,216 @@
+use crate::models::{Account, Lock, NewAccount};
+use crate::routes::middleware::auth::{self, PowMode};
+use crate::routes::middleware::pow_ratelimit::solve_pow_proof_b64;
+
+// an account with a fresh key, unique per run
+fn api_key(pow_mode: PowMode, daily_quota: i64) -> Result<(Account, String), anyhow::Error> {
+    let mut tx = crate::config::storage().begin(Lock::None)?;
+    let account = tx.insert_account(NewAccount {
+        name: &format!("test-{}", rand::random::<u64>()),
+        pow_mode: pow_mode.as_str(),
+        daily_quota,
+    })?;
+    let key = auth::create_api_key(&mut *tx, &account)?;
+    tx.commit()?;
+    Ok((account, key))
+}
+async fn sign(key: &str, body: String) -> (u16, String) {
+    sign_as(&format!("ApiKey {}", key), body).await
+}
+async fn sign_as(authorization: &str, body: String) -> (u16, String) {
+    let res = warp::test::request()
+        .method("POST")
+        .path("/sign_data")
+        .header("authorization", authorization)
+        .body(body)
+        .reply(&crate::router()) // Server routes to respond with
+        .await;
+    (
+        res.status().as_u16(),
+        String::from_utf8_lossy(res.body()).into_owned(),
+    )
+}
+// from the test issuer, configured in tests (see tests/fixtures), for a subject unique per run
+fn bearer(groups: &[&str]) -> Result<String, anyhow::Error> {
+    let mut claims = crate::tests::jwt::claims(groups);
+    claims["sub"] = format!("test-{}", rand::random::<u64>()).into();
+    Ok(format!("Bearer {}", crate::tests::jwt::token(claims)?))
+}
+fn unique_data() -> Vec<u8> {
+    format!("api key data {}", rand::random::<u64>()).into_bytes()
+}
+
+// Happy path: exempt clients skip PoW, within their quota
+#[tokio::test]
+async fn test__auth__Exempt() -> Result<(), anyhow::Error> {
+    let (_, key) = api_key(PowMode::Exempt, 1)?;
+
+    let (status, _) = sign(
+        &key,
+        format!(r#"{{"data_base64":"{}"}}"#, base64::encode(&unique_data())),
+    )
+    .await;
+    assert_eq!(status, 200, "Should return 200 OK");
+
+    let (status, body) = sign(
+        &key,
+        format!(r#"{{"data_base64":"{}"}}"#, base64::encode(&unique_data())),
+    )
+    .await;
+    assert_eq!(status, 429, "Should return 429 Too Many Requests");
+    assert_eq!(
+        body,
+        r#"{"code":429,"message":"Daily quota exceeded","status":"error"}"#
+    );
+    Ok(())
+}
+
+// Digest clients prove work over the data's hash only
+#[tokio::test]
+async fn test__auth__Digest() -> Result<(), anyhow::Error> {
+    let (_, key) = api_key(PowMode::Digest, 10)?;
+    let data = unique_data();
+    let data_b64 = base64::encode(&data);
+
+    let (status, _) = sign(&key, format!(r#"{{"data_base64":"{}"}}"#, data_b64)).await;
+    assert_eq!(status, 400, "Should return 400 Bad Request");
+
+    let proof = solve_pow_proof_b64(blake3::hash(&data).as_bytes());
+    let (status, _) = sign(
+        &key,
+        format!(
+            r#"{{"data_base64":"{}","pow_proof_base64":"{}"}}"#,
+            data_b64, proof
+        ),
+    )
+    .await;
+    assert_eq!(status, 200, "Should return 200 OK");
+    Ok(())
+}
+
+// Unknown, malformed and revoked keys are refused, not treated as anonymous
+#[tokio::test]
+async fn test__auth__InvalidKey() -> Result<(), anyhow::Error> {
+    let (_, key) = api_key(PowMode::Exempt, 10)?;
+    let new_body = || format!(r#"{{"data_base64":"{}"}}"#, base64::encode(&unique_data()));
+
+    let (status, body) = sign("cts_not_a_key", new_body()).await;
+    assert_eq!(status, 401, "Should return 401 Unauthorized");
+    assert_eq!(
+        body,
+        r#"{"code":401,"message":"Invalid API key","status":"error"}"#
+    );
+
+    let key_id = key.split('_').nth(1).unwrap();
+    let now = chrono::Utc::now().naive_utc();
+    let mut tx = crate::config::storage().begin(Lock::None)?;
+    assert!(tx.revoke_api_key(key_id, now)?);
+    tx.commit()?;
+    let (status, _) = sign(&key, new_body()).await;
+    assert_eq!(status, 401, "Should return 401 Unauthorized");
+    Ok(())
+}
+
+// Only issued receipts use quota up: a 409 for data already signed doesn't
+#[tokio::test]
+async fn test__auth__QuotaNotBurned() -> Result<(), anyhow::Error> {
+    let (_, key) = api_key(PowMode::Exempt, 2)?;
+    let body = format!(r#"{{"data_base64":"{}"}}"#, base64::encode(&unique_data()));
+
+    let (status, _) = sign(&key, body.clone()).await;
+    assert_eq!(status, 200, "Should return 200 OK");
+    let (status, _) = sign(&key, body).await;
+    assert_eq!(status, 409, "Should return 409 Conflict");
+
+    let new_body = || format!(r#"{{"data_base64":"{}"}}"#, base64::encode(&unique_data()));
+    let (status, _) = sign(&key, new_body()).await;
+    assert_eq!(status, 200, "Should return 200 OK");
+    let (status, _) = sign(&key, new_body()).await;
+    assert_eq!(status, 429, "Should return 429 Too Many Requests");
+    Ok(())
+}
+
+// In a batch too, only new receipts use quota up: duplicates don't
+#[tokio::test]
+async fn test__auth__BatchQuotaNotBurned() -> Result<(), anyhow::Error> {
+    let (_, key) = api_key(PowMode::Exempt, 2)?;
+    let res = warp::test::request()
+        .method("POST")
+        .path("/sign_data/batch")
+        .header("authorization", format!("ApiKey {}", key))
+        .body(format!(
+            r#"{{"items":[{{"data_base64":"{0}"}},{{"data_base64":"{0}"}}]}}"#,
+            base64::encode(&unique_data())
+        ))
+        .reply(&crate::router())
+        .await;
+    assert_eq!(res.status(), 200, "Should return 200 OK");
+
+    let new_body = || format!(r#"{{"data_base64":"{}"}}"#, base64::encode(&unique_data()));
+    let (status, _) = sign(&key, new_body()).await;
+    assert_eq!(status, 200, "Should return 200 OK");
+    let (status, _) = sign(&key, new_body()).await;
+    assert_eq!(status, 429, "Should return 429 Too Many Requests");
+    Ok(())
+}
+
+// An account with an unknown stored pow mode is refused, not given the default
+#[tokio::test]
+async fn test__auth__BadPowMode() -> Result<(), anyhow::Error> {
+    let mut tx = crate::config::storage().begin(Lock::None)?;
+    let account = tx.insert_account(NewAccount {
+        name: &format!("test-{}", rand::random::<u64>()),
+        pow_mode: "unlimited",
+        daily_quota: 10,
+    })?;
+    let key = auth::create_api_key(&mut *tx, &account)?;
+    tx.commit()?;
+
+    let (status, _) = sign(
+        &key,
+        format!(r#"{{"data_base64":"{}"}}"#, base64::encode(&unique_data())),
+    )
+    .await;
+    assert_eq!(status, 500, "Should return 500 Internal Server Error");
+    Ok(())
+}
+
+// Happy path for SSO users: the token's tier sets the PoW, or refuses it
+#[tokio::test]
+async fn test__auth__Bearer() -> Result<(), anyhow::Error> {
+    let new_body = || format!(r#"{{"data_base64":"{}"}}"#, base64::encode(&unique_data()));
+
+    let (status, _) = sign_as(&bearer(&["staff"])?, new_body()).await;
+    assert_eq!(status, 200, "Should return 200 OK");
+    // default tier: digest PoW
+    let (status, _) = sign_as(&bearer(&["users"])?, new_body()).await;
+    assert_eq!(status, 400, "Should return 400 Bad Request");
+
+    let mut claims = crate::tests::jwt::claims(&["staff"]);
+    claims["aud"] = "other-api".into();
+    let token = format!("Bearer {}", crate::tests::jwt::token(claims)?);
+    let (status, body) = sign_as(&token, new_body()).await;
+    assert_eq!(status, 401, "Should return 401 Unauthorized");
+    assert_eq!(
+        body,
+        r#"{"code":401,"message":"Invalid bearer token","status":"error"}"#
+    );
+    Ok(())
+}
+
+// SSO users are held to their tier's daily quota
+#[tokio::test]
+async fn test__auth__BearerQuota() -> Result<(), anyhow::Error> {
+    let token = bearer(&["trial"])?;
+    let new_body = || format!(r#"{{"data_base64":"{}"}}"#, base64::encode(&unique_data()));
+
+    let (status, _) = sign_as(&token, new_body()).await;
+    assert_eq!(status, 200, "Should return 200 OK");
+    let (status, body) = sign_as(&token, new_body()).await;
+    assert_eq!(status, 429, "Should return 429 Too Many Requests");
+    assert_eq!(
+        body,
+        r#"{"code":429,"message":"Daily quota exceeded","status":"error"}"#
+    );
+    Ok(())
+}
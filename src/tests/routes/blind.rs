@@ -0,0 +1,96 @@
+use crate::models::{Lock, NewAccount};
+use crate::routes::blind::{BlindKeyResp, BlindSignResp};
+use crate::routes::middleware::auth::{self, PowMode};
+use crate::utils::blind_sign::Blinded;
+
+async fn blind_sign(key: &str, body: String) -> (u16, Vec<u8>) {
+    let res = warp::test::request()
+        .method("POST")
+        .path("/blind/sign")
+        .header("authorization", format!("ApiKey {}", key))
+        .body(body)
+        .reply(&crate::router())
+        .await;
+    (res.status().as_u16(), res.body().to_vec())
+}
+
+// Happy path: blind, sign, unblind into a receipt verifying against the pinned key
+#[tokio::test]
+async fn test__blind__OK() -> Result<(), anyhow::Error> {
+    let mut tx = crate::config::storage().begin(Lock::None)?;
+    let account = tx.insert_account(NewAccount {
+        name: &format!("test-{}", rand::random::<u64>()),
+        pow_mode: PowMode::Exempt.as_str(),
+        daily_quota: 10,
+    })?;
+    let api_key = auth::create_api_key(&mut *tx, &account)?;
+    tx.commit()?;
+
+    let res = warp::test::request()
+        .method("GET")
+        .path("/blind/key")
+        .reply(&crate::router())
+        .await;
+    assert_eq!(res.status(), 200, "Should return 200 OK");
+    let key_resp: BlindKeyResp = serde_json::from_slice(res.body())?;
+    let anchor = crate::config::trust_anchor();
+    assert!(key_resp.epoch.verify(&anchor));
+
+    let data = b"blind route data";
+    let blinded = Blinded::new(&key_resp.epoch, data)?;
+    let body = |key_id: &str| {
+        format!(
+            r#"{{"key_id":"{}","blinded_base64":"{}"}}"#,
+            key_id,
+            base64::encode(&blinded.blinded)
+        )
+    };
+
+    let (status, resp_body) = blind_sign(&api_key, body("0123456789abcdef")).await;
+    assert_eq!(status, 409, "Should return 409 Conflict");
+    assert_eq!(
+        resp_body,
+        br#"{"code":409,"message":"Key epoch is over, blind again for the current key","status":"error"}"#.to_vec()
+    );
+
+    let (status, resp_body) = blind_sign(&api_key, body(&key_resp.key_id)).await;
+    assert_eq!(status, 200, "Should return 200 OK");
+    let bs_resp: BlindSignResp = serde_json::from_slice(&resp_body)?;
+    let receipt = blinded.finalize(&base64::decode(&bs_resp.blind_signature_base64)?)?;
+    assert!(receipt.verify(data, &anchor));
+    Ok(())
+}
+
+// A blinded message the key can't sign is refused before the client is charged
+#[tokio::test]
+async fn test__blind__BadBlindedNotCharged() -> Result<(), anyhow::Error> {
+    let mut tx = crate::config::storage().begin(Lock::None)?;
+    let account = tx.insert_account(NewAccount {
+        name: &format!("test-{}", rand::random::<u64>()),
+        pow_mode: PowMode::Exempt.as_str(),
+        daily_quota: 1,
+    })?;
+    let api_key = auth::create_api_key(&mut *tx, &account)?;
+    tx.commit()?;
+
+    let res = warp::test::request()
+        .method("GET")
+        .path("/blind/key")
+        .reply(&crate::router())
+        .await;
+    let key_resp: BlindKeyResp = serde_json::from_slice(res.body())?;
+    let body = |blinded: &[u8]| {
+        format!(
+            r#"{{"key_id":"{}","blinded_base64":"{}"}}"#,
+            key_resp.key_id,
+            base64::encode(blinded)
+        )
+    };
+
+    let (status, _) = blind_sign(&api_key, body(&[1u8; 32])).await;
+    assert_eq!(status, 400, "Should return 400 Bad Request");
+    let blinded = Blinded::new(&key_resp.epoch, b"blind route data, charged once")?;
+    let (status, _) = blind_sign(&api_key, body(&blinded.blinded)).await;
+    assert_eq!(status, 200, "Should return 200 OK");
+    Ok(())
+}
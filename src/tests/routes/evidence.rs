@@ -0,0 +1,67 @@
+use crate::routes::middleware::pow_ratelimit::solve_pow_proof_b64;
+use crate::routes::SignDataResp;
+use crate::utils::evidence::{self, EvidenceRecord};
+
+// Happy path: a receipt is chained through every renewal since it was issued
+#[tokio::test]
+async fn test__evidence_record__OK() -> Result<(), anyhow::Error> {
+    let data_bytes = b"test__evidence_record__OK";
+    let res = warp::test::request()
+        .method("POST")
+        .path("/sign_data")
+        .body(format!(
+            r#"{{"data_base64":"{}","pow_proof_base64":"{}"}}"#,
+            base64::encode(&data_bytes),
+            solve_pow_proof_b64(data_bytes)
+        ))
+        .reply(&crate::router()) // Server routes to respond with
+        .await;
+    let mut sd_resp: SignDataResp = serde_json::from_slice(&res.body())?;
+    evidence::renew_now()?;
+    evidence::renew_now()?; // chains the first renewal
+
+    let res = warp::test::request()
+        .method("POST")
+        .path("/evidence_record")
+        .body(format!(
+            r#"{{"data_hash_base64":"{}"}}"#,
+            sd_resp.fields_signed.data_hash_base64
+        ))
+        .reply(&crate::router())
+        .await;
+    let record: EvidenceRecord = serde_json::from_slice(&res.body())?;
+
+    assert_eq!(res.status(), 200, "Should return 200 OK.");
+    assert!(record.renewals.len() >= 2);
+    let trust_anchor = crate::config::trust_anchor();
+    assert!(
+        record.verify(&sd_resp, &trust_anchor),
+        "failed verifying record"
+    );
+
+    // doesn't vouch for a tampered receipt
+    sd_resp.fields_signed.timestamp = sd_resp.fields_signed.timestamp - chrono::Duration::days(1);
+    assert!(!record.verify(&sd_resp, &trust_anchor));
+    Ok(())
+}
+
+// No receipt for this data
+#[tokio::test]
+async fn test__evidence_record__UnknownReceipt() -> Result<(), anyhow::Error> {
+    let res = warp::test::request()
+        .method("POST")
+        .path("/evidence_record")
+        .body(format!(
+            r#"{{"data_hash_base64":"{}"}}"#,
+            base64::encode(blake3::hash(b"never signed").as_bytes())
+        ))
+        .reply(&crate::router())
+        .await;
+
+    assert_eq!(res.status(), 404, "Should return 404 Not found.");
+    assert_eq!(
+        res.body(),
+        r#"{"code":404,"message":"Unknown receipt","status":"error"}"#
+    );
+    Ok(())
+}
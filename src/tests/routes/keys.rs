@@ -0,0 +1,36 @@
+use crate::routes::keys::KeyStatus;
+use crate::utils::crypto_sign;
+
+// Happy path: the signing key is active
+#[tokio::test]
+async fn test__key_status__OK() -> Result<(), anyhow::Error> {
+    let key_id = crypto_sign::key_id(&crate::config::signer().pubkey());
+    let res = warp::test::request()
+        .method("GET")
+        .path(&format!("/keys/{}/status", key_id))
+        .reply(&crate::router()) // Server routes to respond with
+        .await;
+    let ks_resp: crate::routes::KeyStatusResp = serde_json::from_slice(&res.body())?;
+
+    assert_eq!(res.status(), 200, "Should return 200 OK.");
+    assert_eq!(ks_resp.key_id, key_id);
+    assert_eq!(ks_resp.status, KeyStatus::Active);
+    Ok(())
+}
+
+// Unknown key id
+#[tokio::test]
+async fn test__key_status__NotFound() -> Result<(), anyhow::Error> {
+    let res = warp::test::request()
+        .method("GET")
+        .path("/keys/0123456789abcdef/status")
+        .reply(&crate::router()) // Server routes to respond with
+        .await;
+
+    assert_eq!(res.status(), 404, "Should return 404 Not found.");
+    assert_eq!(
+        res.body(),
+        r#"{"code":404,"message":"Unknown key","status":"error"}"#
+    );
+    Ok(())
+}
@@ -1,5 +1,11 @@
+mod auth;
+mod blind;
+mod evidence;
+mod keys;
 mod pubkey;
 mod sign_data;
+mod sign_data_batch;
+mod tokens;
 
 #[tokio::test]
 async fn test_getRoot() {
@@ -13,14 +19,131 @@ async fn test_getRoot() {
     assert_eq!(res.body(), "Hello world !");
 }
 
+// Liveness doesn't depend on anything
 #[tokio::test]
-async fn test_health() {
+async fn test_health_live() {
     let res = warp::test::request()
         .method("GET")
-        .path("/health")
+        .path("/health/live")
         .reply(&crate::router())
         .await;
 
     assert_eq!(res.status(), 200, "Should return 200 OK.");
-    assert_eq!(res.body(), "Hello world !");
+    assert_eq!(res.body(), r#"{"status":"ok"}"#);
+}
+
+// Readiness reports each component: the test DB is migrated, the key signs
+#[tokio::test]
+async fn test_health() -> Result<(), anyhow::Error> {
+    use crate::routes::health::HealthStatus;
+    for path in &["/health", "/health/ready"] {
+        let res = warp::test::request()
+            .method("GET")
+            .path(path)
+            .reply(&crate::router())
+            .await;
+        let health: crate::routes::HealthResp = serde_json::from_slice(res.body())?;
+
+        assert_eq!(res.status(), 200, "Should return 200 OK: {:?}", health);
+        for component in &["database", "migrations", "signer", "clock"] {
+            assert_eq!(health.components[*component].status, HealthStatus::Ok);
+        }
+    }
+    Ok(())
+}
+
+// Prometheus text format, with the pool's state and signing counters
+#[tokio::test]
+async fn test_metrics() {
+    let res = warp::test::request()
+        .method("GET")
+        .path("/metrics")
+        .reply(&crate::router())
+        .await;
+
+    assert_eq!(res.status(), 200, "Should return 200 OK.");
+    let body = String::from_utf8_lossy(res.body());
+    assert!(body.contains("# TYPE receipts_issued_total counter"));
+    assert!(body.contains("db_pool_connections "));
+}
+
+// Path parameters don't make a series per value in request metrics
+#[test]
+fn test_metrics_routeLabel() {
+    use crate::utils::metrics::route_label;
+    assert_eq!(route_label("/sign_data"), "/sign_data");
+    assert_eq!(route_label("/sign_data/batch/"), "/sign_data/batch");
+    assert_eq!(
+        route_label("/keys/5f0c6e2a0d8c4b6e9a1f3e7d2c4b6a80/status"),
+        "/keys/{key_id}/status"
+    );
+    assert_eq!(route_label("/wp-admin/login.php"), "other");
+}
+
+// Request ids are propagated when sane, generated otherwise
+#[tokio::test]
+async fn test_requestId() {
+    let request_id = |id: Option<&'static str>| async move {
+        let mut req = warp::test::request().method("GET").path("/health/live");
+        if let Some(id) = id {
+            req = req.header("x-request-id", id);
+        }
+        let res = req.reply(&crate::router()).await;
+        res.headers()["x-request-id"].to_str().unwrap().to_string()
+    };
+
+    assert_eq!(request_id(Some("abc-123")).await, "abc-123");
+    for id in &[None, Some("has spaces"), Some("")] {
+        let generated = request_id(*id).await;
+        assert_eq!(generated.len(), 32);
+        assert!(generated.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+}
+
+// The OpenAPI document lists the routes, and every schema it references is defined
+#[tokio::test]
+async fn test_openapi() -> Result<(), anyhow::Error> {
+    let res = warp::test::request()
+        .method("GET")
+        .path("/openapi.json")
+        .reply(&crate::router())
+        .await;
+    assert_eq!(res.status(), 200, "Should return 200 OK.");
+    let spec: serde_json::Value = serde_json::from_slice(res.body())?;
+
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3.0."));
+    assert!(spec["paths"]["/sign_data"]["post"].is_object());
+    let schemas = &spec["components"]["schemas"];
+    for name in &["SignDataReq", "SignDataResp", "PubkeyResp", "ErrResp"] {
+        assert!(schemas[*name].is_object(), "{} missing", name);
+    }
+
+    fn refs<'a>(value: &'a serde_json::Value, found: &mut Vec<&'a str>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                if let Some(r) = map.get("$ref").and_then(|r| r.as_str()) {
+                    found.push(r);
+                }
+                map.values().for_each(|v| refs(v, found));
+            }
+            serde_json::Value::Array(values) => values.iter().for_each(|v| refs(v, found)),
+            _ => {}
+        }
+    }
+    let mut found = vec![];
+    refs(&spec, &mut found);
+    assert!(!found.is_empty());
+    for r in found {
+        let name = r.trim_start_matches("#/components/schemas/");
+        assert!(schemas[name].is_object(), "dangling $ref {}", r);
+    }
+
+    let res = warp::test::request()
+        .method("GET")
+        .path("/docs")
+        .reply(&crate::router())
+        .await;
+    assert_eq!(res.status(), 200, "Should return 200 OK.");
+    assert!(String::from_utf8_lossy(res.body()).contains("/openapi.json"));
+    Ok(())
 }
@@ -11,7 +11,7 @@ async fn test__pubkey__OK() -> Result<(), anyhow::Error> {
     assert_eq!(res.status(), 200, "Should return 200 OK.");
     assert_eq!(
         pk_resp.pubkey.to_bytes(),
-        crate::config::keypair().pubkey().to_bytes(),
+        crate::config::trust_anchor().to_bytes(),
         "pubkey should be same as in config"
     );
     assert_eq!(
@@ -25,7 +25,7 @@ async fn test__sign_data__OK() -> Result<(), anyhow::Error> {
 
     // with fields_signed_hash being the message, the server's pubkey and the signature, we can verify:
     let signature_bytes = base64::decode(&sd_resp.signature_base64)?;
-    let sig_ok = crate::config::keypair().verify(fields_signed_hash.as_bytes(), &signature_bytes);
+    let sig_ok = crate::config::signer().verify(fields_signed_hash.as_bytes(), &signature_bytes);
 
     assert_eq!(sig_ok, true, "failed verifying signature");
     Ok(())
@@ -166,3 +166,195 @@ async fn test__sign_data__PowProof_rejected() -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+// Receipts are chained: serials increase, each links to the receipt before it
+#[tokio::test]
+async fn test__sign_data__Chained() -> Result<(), anyhow::Error> {
+    let mut receipts: Vec<crate::routes::SignDataResp> = vec![];
+    for data_bytes in &[b"test__sign_data__Chained_1", b"test__sign_data__Chained_2"] {
+        let res = warp::test::request()
+            .method("POST")
+            .path("/sign_data")
+            .body(format!(
+                r#"{{"data_base64":"{}","pow_proof_base64":"{}"}}"#,
+                base64::encode(data_bytes),
+                solve_pow_proof_b64(*data_bytes)
+            ))
+            .reply(&crate::router()) // Server routes to respond with
+            .await;
+        assert_eq!(res.status(), 200, "Should return 200 OK");
+        receipts.push(serde_json::from_slice(&res.body())?);
+    }
+    let (first, second) = (&receipts[0], &receipts[1]);
+
+    assert!(second.fields_signed.serial > first.fields_signed.serial);
+    assert!(second.fields_signed.prev_receipt_hash_base64.is_some());
+    // other tests may sign in between
+    if second.fields_signed.serial == first.fields_signed.serial + 1 {
+        assert!(
+            second.follows(first),
+            "second receipt should link to the first"
+        );
+    }
+    assert!(!first.follows(second));
+    Ok(())
+}
+
+// A receipt dated before the one it follows is detected as backdated
+#[test]
+fn test__sign_data__Backdated() -> Result<(), anyhow::Error> {
+    use crate::routes::sign_data::{FieldsSigned, SignDataResp};
+    let kp = crate::utils::crypto_sign::KeyPair::generate();
+    let receipt = |fields_signed: FieldsSigned| -> Result<SignDataResp, anyhow::Error> {
+        Ok(SignDataResp {
+            signature_base64: base64::encode(&kp.sign(&fields_signed.hash()?)[..]),
+            signature_pq_base64: None,
+            fields_signed,
+            delegation: None,
+            earliest: None,
+        })
+    };
+    let now = chrono::Local::now().naive_local();
+    let first = receipt(FieldsSigned {
+        data_hash_base64: base64::encode(blake3::hash(b"first").as_bytes()),
+        timestamp: now,
+        accuracy_ms: None,
+        serial: 7,
+        prev_receipt_hash_base64: None,
+    })?;
+    let next = |timestamp| -> Result<SignDataResp, anyhow::Error> {
+        receipt(FieldsSigned {
+            data_hash_base64: base64::encode(blake3::hash(b"next").as_bytes()),
+            timestamp,
+            accuracy_ms: None,
+            serial: 8,
+            prev_receipt_hash_base64: Some(base64::encode(&first.hash()?)),
+        })
+    };
+
+    assert!(next(now + chrono::Duration::seconds(1))?.follows(&first));
+    assert!(!next(now - chrono::Duration::days(1))?.follows(&first));
+    Ok(())
+}
+
+// Receipts are stored as returned: the stored JSON answers resubmissions, and reporting the
+// earliest receipt along doesn't change what was signed
+#[tokio::test]
+async fn test__sign_data__StoredReceipt() -> Result<(), anyhow::Error> {
+    use crate::routes::sign_data::SignDataResp;
+    let data_bytes = b"test__sign_data__StoredReceipt";
+    let res = warp::test::request()
+        .method("POST")
+        .path("/sign_data")
+        .body(format!(
+            r#"{{"data_base64":"{}","pow_proof_base64":"{}"}}"#,
+            base64::encode(&data_bytes),
+            solve_pow_proof_b64(data_bytes)
+        ))
+        .reply(&crate::router())
+        .await;
+    assert_eq!(res.status(), 200, "Should return 200 OK");
+    let mut receipt: SignDataResp = serde_json::from_slice(&res.body())?;
+    assert!(receipt.earliest.is_none());
+
+    let stored = crate::config::storage()
+        .begin(crate::models::Lock::None)?
+        .receipt_by_data_hash(&base64::encode(blake3::hash(data_bytes).as_bytes()))?
+        .expect("receipt should be stored");
+    assert_eq!(
+        stored.receipt_json.as_deref().map(str::as_bytes),
+        Some(&res.body()[..])
+    );
+
+    let hash = receipt.hash()?;
+    receipt.earliest = Some(Box::new(serde_json::from_slice(&res.body())?));
+    assert_eq!(receipt.hash()?, hash);
+    Ok(())
+}
+
+// Receipts from before the chain have no stored hash: the next one explicitly starts the chain
+#[test]
+fn test__sign_data__ChainStart() -> Result<(), anyhow::Error> {
+    use crate::models::{memory::MemStorage, Lock, NewSignedData, Storage};
+    use crate::routes::sign_data::{sign_next, DuplicatePolicy, Signed};
+    let storage = MemStorage::default();
+    let mut tx = storage.begin(Lock::Chain)?;
+    tx.insert_receipt(NewSignedData {
+        data_hash_b64: "bGVnYWN5",
+        created_at: None,
+        receipt_hash_b64: None,
+        serial: 41,
+        prev_receipt_hash_b64: None,
+        subject_issuer: None,
+        subject: None,
+        receipt_json: None,
+    })?;
+    let data_hash_base64 = base64::encode(blake3::hash(b"first chained").as_bytes());
+    let first = match sign_next(
+        &mut *tx,
+        data_hash_base64,
+        DuplicatePolicy::Strict,
+        None,
+        None,
+    )? {
+        Signed::New(receipt) => receipt,
+        Signed::Original(_) => panic!("the data wasn't signed before"),
+    };
+    assert_eq!(first.fields_signed.serial, 42);
+    assert!(first.is_chain_start());
+
+    let data_hash_base64 = base64::encode(blake3::hash(b"second chained").as_bytes());
+    let second = match sign_next(
+        &mut *tx,
+        data_hash_base64,
+        DuplicatePolicy::Strict,
+        None,
+        None,
+    )? {
+        Signed::New(receipt) => receipt,
+        Signed::Original(_) => panic!("the data wasn't signed before"),
+    };
+    assert!(!second.is_chain_start());
+    assert!(second.follows(&first));
+    Ok(())
+}
+
+// Each duplicate policy, on resubmitted data
+#[test]
+fn test__sign_data__DuplicatePolicies() -> Result<(), anyhow::Error> {
+    use crate::models::{memory::MemStorage, Lock, Storage, Tx};
+    use crate::routes::sign_data::{sign_next, DuplicatePolicy, SignDataErr, Signed};
+    let sign = |tx: &mut dyn Tx, policy| {
+        let data_hash_base64 = base64::encode(blake3::hash(b"signed twice").as_bytes());
+        sign_next(tx, data_hash_base64, policy, None, None)
+    };
+
+    let storage = MemStorage::default();
+    let mut tx = storage.begin(Lock::Chain)?;
+    let first = match sign(&mut *tx, DuplicatePolicy::Strict)? {
+        Signed::New(receipt) => receipt,
+        Signed::Original(_) => panic!("the data wasn't signed before"),
+    };
+    let err = sign(&mut *tx, DuplicatePolicy::Strict).err().unwrap();
+    assert!(matches!(err, SignDataErr::AlreadySigned), "got: {}", err);
+
+    match sign(&mut *tx, DuplicatePolicy::Idempotent)? {
+        Signed::Original(receipt) => {
+            assert_eq!(receipt.signature_base64, first.signature_base64);
+            assert_eq!(receipt.fields_signed.serial, first.fields_signed.serial);
+        }
+        Signed::New(_) => panic!("should return the stored receipt"),
+    }
+
+    match sign(&mut *tx, DuplicatePolicy::AllowMultiple)? {
+        Signed::New(receipt) => {
+            assert_eq!(receipt.fields_signed.serial, first.fields_signed.serial + 1);
+            let earliest = receipt
+                .earliest
+                .expect("should report the earliest receipt");
+            assert_eq!(earliest.signature_base64, first.signature_base64);
+        }
+        Signed::Original(_) => panic!("should issue a new receipt"),
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use crate::routes::middleware::pow_ratelimit::solve_pow_proof_b64;
+use crate::routes::sign_data_batch::{BatchStatus, SignBatchResp};
+
+async fn post_batch(body: String) -> (u16, Vec<u8>) {
+    let res = warp::test::request()
+        .method("POST")
+        .path("/sign_data/batch")
+        .body(body)
+        .reply(&crate::router())
+        .await;
+    (res.status().as_u16(), res.body().to_vec())
+}
+
+// Happy path: data and digests, signed as consecutive receipts in the request's order
+#[tokio::test]
+async fn test__sign_data_batch__OK() -> Result<(), anyhow::Error> {
+    let data_1 = b"test__sign_data_batch__OK_1";
+    let data_2 = b"test__sign_data_batch__OK_2";
+    let hash_3 = blake3::hash(b"test__sign_data_batch__OK_3");
+    let pow_bytes = [&data_1[..], &data_2[..], hash_3.as_bytes()].concat();
+    let (status, body) = post_batch(format!(
+        r#"{{"items":[{{"data_base64":"{}"}},{{"data_base64":"{}"}},{{"data_hash_base64":"{}"}}],"pow_proof_base64":"{}"}}"#,
+        base64::encode(data_1),
+        base64::encode(data_2),
+        base64::encode(hash_3.as_bytes()),
+        solve_pow_proof_b64(&pow_bytes)
+    ))
+    .await;
+    assert_eq!(status, 200, "Should return 200 OK");
+    let sb_resp: SignBatchResp = serde_json::from_slice(&body)?;
+
+    let receipts = sb_resp
+        .results
+        .iter()
+        .map(|r| {
+            assert_eq!(r.status, BatchStatus::Signed);
+            r.receipt.as_ref().expect("signed items have a receipt")
+        })
+        .collect::<Vec<_>>();
+    let hashes = [blake3::hash(data_1), blake3::hash(data_2), hash_3];
+    for (receipt, hash) in receipts.iter().zip(&hashes) {
+        assert_eq!(
+            receipt.fields_signed.data_hash_base64,
+            base64::encode(hash.as_bytes())
+        );
+    }
+    assert!(receipts[1].follows(receipts[0]));
+    assert!(receipts[2].follows(receipts[1]));
+    Ok(())
+}
+
+// The same data twice: the second item is reported as a duplicate, the batch still goes through
+#[tokio::test]
+async fn test__sign_data_batch__Duplicate() -> Result<(), anyhow::Error> {
+    let data = b"test__sign_data_batch__Duplicate";
+    let (status, body) = post_batch(format!(
+        r#"{{"items":[{{"data_base64":"{0}"}},{{"data_base64":"{0}"}}],"pow_proof_base64":"{1}"}}"#,
+        base64::encode(data),
+        solve_pow_proof_b64(&[&data[..], &data[..]].concat())
+    ))
+    .await;
+    assert_eq!(status, 200, "Should return 200 OK");
+    let sb_resp: SignBatchResp = serde_json::from_slice(&body)?;
+
+    assert_eq!(sb_resp.results[0].status, BatchStatus::Signed);
+    assert_eq!(sb_resp.results[1].status, BatchStatus::AlreadySigned);
+    assert!(sb_resp.results[1].receipt.is_none());
+    Ok(())
+}
+
+// An item with both data and a digest is rejected, with its index
+#[tokio::test]
+async fn test__sign_data_batch__BadItem() -> Result<(), anyhow::Error> {
+    let data = b"test__sign_data_batch__BadItem";
+    let (status, body) = post_batch(format!(
+        r#"{{"items":[{{"data_base64":"{0}"}},{{"data_base64":"{0}","data_hash_base64":"{1}"}}],"pow_proof_base64":"{2}"}}"#,
+        base64::encode(data),
+        base64::encode(blake3::hash(data).as_bytes()),
+        solve_pow_proof_b64(data)
+    ))
+    .await;
+
+    assert_eq!(status, 400, "Should return 400 Bad Request");
+    assert_eq!(
+        body,
+        br#"{"code":400,"message":"Bad Request: item 1: expected exactly one of data_base64 and data_hash_base64","status":"error"}"#.to_vec()
+    );
+    Ok(())
+}
+
+// An empty batch is rejected
+#[tokio::test]
+async fn test__sign_data_batch__Empty() -> Result<(), anyhow::Error> {
+    let (status, body) = post_batch(r#"{"items":[]}"#.to_string()).await;
+
+    assert_eq!(status, 400, "Should return 400 Bad Request");
+    assert_eq!(
+        body,
+        br#"{"code":400,"message":"Bad Request: expected 1 to 500 items","status":"error"}"#
+            .to_vec()
+    );
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use crate::routes::middleware::pow_ratelimit::solve_pow_proof_b64;
+use crate::routes::IssueTokensResp;
+use crate::utils::tokens::{self, BlindedToken, DleqProof, Token};
+
+async fn sign(authorization: &str, data: &[u8]) -> (u16, String) {
+    let res = warp::test::request()
+        .method("POST")
+        .path("/sign_data")
+        .header("authorization", authorization)
+        .body(format!(r#"{{"data_base64":"{}"}}"#, base64::encode(data)))
+        .reply(&crate::router())
+        .await;
+    (
+        res.status().as_u16(),
+        String::from_utf8_lossy(res.body()).into_owned(),
+    )
+}
+
+// `count` tokens, issued for a PoW
+async fn issue(count: usize) -> Result<Vec<Token>, anyhow::Error> {
+    let blinded: Vec<BlindedToken> = (0..count).map(|_| BlindedToken::generate()).collect();
+    let points: Vec<_> = blinded.iter().map(|b| b.blinded).collect();
+    let batch: Vec<u8> = points
+        .iter()
+        .flat_map(|p| p.compress().to_bytes().to_vec())
+        .collect();
+    let blinded_b64: Vec<String> = points.iter().map(tokens::point_to_b64).collect();
+    let res = warp::test::request()
+        .method("POST")
+        .path("/tokens/issue")
+        .body(serde_json::to_vec(&serde_json::json!({
+            "blinded_base64": blinded_b64,
+            "pow_proof_base64": solve_pow_proof_b64(&batch),
+        }))?)
+        .reply(&crate::router())
+        .await;
+    assert_eq!(res.status(), 200, "Should return 200 OK");
+    let it_resp: IssueTokensResp = serde_json::from_slice(res.body())?;
+
+    // the key is the one published
+    let pubkey = tokens::point_from_b64(&it_resp.pubkey_base64)?;
+    assert_eq!(pubkey, crate::config::token_key().unwrap().pubkey());
+    let evaluated = it_resp
+        .evaluated_base64
+        .iter()
+        .map(|e| tokens::point_from_b64(e))
+        .collect::<Result<Vec<_>, _>>()?;
+    let proof = DleqProof {
+        challenge: tokens::scalar_from_b64(&it_resp.proof.challenge_base64)?,
+        response: tokens::scalar_from_b64(&it_resp.proof.response_base64)?,
+    };
+    Ok(BlindedToken::unblind_batch(
+        blinded, &evaluated, &proof, &pubkey,
+    )?)
+}
+
+// Happy path: one PoW for a batch of tokens, each redeeming one sign_data call
+#[tokio::test]
+async fn test__tokens__OK() -> Result<(), anyhow::Error> {
+    let tokens = issue(2).await?;
+
+    let data = format!("token data {}", rand::random::<u64>()).into_bytes();
+    let authorization = tokens[0].authorization(blake3::hash(&data).as_bytes());
+    let (status, _) = sign(&authorization, &data).await;
+    assert_eq!(status, 200, "Should return 200 OK");
+
+    // spent
+    let data = format!("token data {}", rand::random::<u64>()).into_bytes();
+    let (status, body) = sign(&authorization, &data).await;
+    assert_eq!(status, 401, "Should return 401 Unauthorized");
+    assert_eq!(
+        body,
+        r#"{"code":401,"message":"Invalid token","status":"error"}"#
+    );
+    let authorization = tokens[0].authorization(blake3::hash(&data).as_bytes());
+    let (status, body) = sign(&authorization, &data).await;
+    assert_eq!(status, 401, "Should return 401 Unauthorized");
+    assert_eq!(
+        body,
+        r#"{"code":401,"message":"Token already spent","status":"error"}"#
+    );
+    Ok(())
+}
+
+// A token presented for data already signed isn't spent: it's spent with a receipt only
+#[tokio::test]
+async fn test__tokens__NotSpentOnConflict() -> Result<(), anyhow::Error> {
+    let tokens = issue(1).await?;
+    let data = format!("token data {}", rand::random::<u64>()).into_bytes();
+    let res = warp::test::request()
+        .method("POST")
+        .path("/sign_data")
+        .body(format!(
+            r#"{{"data_base64":"{}","pow_proof_base64":"{}"}}"#,
+            base64::encode(&data),
+            solve_pow_proof_b64(&data)
+        ))
+        .reply(&crate::router())
+        .await;
+    assert_eq!(res.status(), 200, "Should return 200 OK");
+
+    let authorization = tokens[0].authorization(blake3::hash(&data).as_bytes());
+    let (status, _) = sign(&authorization, &data).await;
+    assert_eq!(status, 409, "Should return 409 Conflict");
+
+    let data = format!("token data {}", rand::random::<u64>()).into_bytes();
+    let authorization = tokens[0].authorization(blake3::hash(&data).as_bytes());
+    let (status, _) = sign(&authorization, &data).await;
+    assert_eq!(status, 200, "Should return 200 OK");
+    Ok(())
+}
+
+// No tokens without PoW
+#[tokio::test]
+async fn test__tokens__PowRejected() -> Result<(), anyhow::Error> {
+    let blinded = tokens::point_to_b64(&BlindedToken::generate().blinded);
+    let res = warp::test::request()
+        .method("POST")
+        .path("/tokens/issue")
+        .body(format!(r#"{{"blinded_base64":["{}"]}}"#, blinded))
+        .reply(&crate::router())
+        .await;
+    assert_eq!(res.status(), 400, "Should return 400 Bad Request");
+    Ok(())
+}
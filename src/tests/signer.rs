@@ -0,0 +1,76 @@
+use chrono::Duration;
+//
+use crate::signer::{client::SignerClient, daemon, Signer};
+use crate::utils::blind_sign::{BlindErr, Blinded, EpochKeys};
+use crate::utils::crypto_sign::KeyPair;
+
+// a daemon for `keypair` on a fresh socket, serving in the background
+fn spawn_daemon(keypair: &'static KeyPair) -> Result<std::path::PathBuf, anyhow::Error> {
+    let epoch_keys: &'static EpochKeys = Box::leak(Box::new(EpochKeys::new(Duration::hours(1))));
+    let socket_path = std::env::temp_dir().join(format!(
+        "signer-test-{}-{}.sock",
+        std::process::id(),
+        rand::random::<u32>()
+    ));
+    let listener = daemon::bind(&socket_path)?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            // refusals close the connection with an error: the client sees their status
+            let _ = daemon::serve_conn(stream.unwrap(), keypair, None, epoch_keys);
+        }
+    });
+    Ok(socket_path)
+}
+
+// Happy path: the HTTP side gets signatures without ever holding the key
+#[test]
+fn test__signer__remote_sign() -> Result<(), anyhow::Error> {
+    let keypair: &'static KeyPair = Box::leak(Box::new(KeyPair::generate()));
+    let socket_path = spawn_daemon(keypair)?;
+
+    let signer = Signer::Remote(SignerClient::connect(&socket_path)?);
+    assert_eq!(
+        signer.pubkey().to_bytes(),
+        keypair.pubkey().to_bytes(),
+        "pubkey should be the daemon's"
+    );
+    let digest = *blake3::hash(b"hello dog this is data").as_bytes();
+    let sig = signer.sign(&digest)?;
+    assert_eq!(
+        keypair.verify(&digest, &sig[..]),
+        true,
+        "failed verifying signature"
+    );
+
+    std::fs::remove_file(&socket_path)?;
+    Ok(())
+}
+
+// Blind signing through the daemon: epoch keys are generated and certified there
+#[test]
+fn test__signer__remote_blind_sign() -> Result<(), anyhow::Error> {
+    let keypair: &'static KeyPair = Box::leak(Box::new(KeyPair::generate()));
+    let socket_path = spawn_daemon(keypair)?;
+    let signer = Signer::Remote(SignerClient::connect(&socket_path)?);
+
+    let cert = signer.blind_epoch()?;
+    assert!(
+        cert.verify(&keypair.pubkey()),
+        "certified by the daemon's key"
+    );
+    let data = b"data the daemon never sees";
+    let blinded = Blinded::new(&cert, data)?;
+    let blind_sig = signer.blind_sign(&cert.key_id(), &blinded.blinded)?;
+    let receipt = blinded.finalize(&blind_sig)?;
+    assert!(receipt.verify(data, &keypair.pubkey()));
+
+    let err = signer
+        .blind_sign(&"0".repeat(32), &blinded.blinded)
+        .unwrap_err();
+    assert!(matches!(err, BlindErr::EpochOver), "got: {}", err);
+    let err = signer.blind_sign(&cert.key_id(), &[1u8; 32]).unwrap_err();
+    assert!(matches!(err, BlindErr::BadBlindedMessage), "got: {}", err);
+
+    std::fs::remove_file(&socket_path)?;
+    Ok(())
+}
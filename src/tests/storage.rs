@@ -0,0 +1,133 @@
+use crate::models::kv::KvStorage;
+use crate::models::memory::MemStorage;
+use crate::models::sqlite::SqliteStorage;
+use crate::models::{Lock, ModelErr, NewAccount, NewSignedData, Storage};
+
+fn receipt(data_hash_b64: &str, serial: i64) -> NewSignedData {
+    NewSignedData {
+        data_hash_b64,
+        created_at: None,
+        receipt_hash_b64: Some("cmVjZWlwdA=="),
+        serial,
+        prev_receipt_hash_b64: None,
+        subject_issuer: None,
+        subject: None,
+        receipt_json: None,
+    }
+}
+
+// what every backend must honour, on an empty, migrated storage
+fn check_contract(storage: &dyn Storage) -> Result<(), anyhow::Error> {
+    let mut tx = storage.begin(Lock::Chain)?;
+    assert!(tx.last_receipt()?.is_none());
+    tx.insert_receipt(receipt("aGFzaA==", 1))?;
+    tx.insert_receipt(receipt("aGFzaA==", 2))?;
+    tx.commit()?;
+
+    let mut tx = storage.begin(Lock::None)?;
+    assert_eq!(tx.last_receipt()?.map(|r| r.serial), Some(2));
+    assert_eq!(
+        tx.receipt_by_data_hash("aGFzaA==")?.map(|r| r.serial),
+        Some(1),
+        "should be the earliest"
+    );
+    assert_eq!(tx.unrenewed_receipts()?.len(), 2);
+    drop(tx);
+
+    // dropped uncommitted: rolled back
+    let mut tx = storage.begin(Lock::Chain)?;
+    tx.insert_receipt(receipt("b3RoZXI=", 3))?;
+    drop(tx);
+    let mut tx = storage.begin(Lock::None)?;
+    assert!(tx.receipt_by_data_hash("b3RoZXI=")?.is_none());
+    assert!(matches!(
+        tx.insert_receipt(receipt("b3RoZXI=", 2)),
+        Err(ModelErr::AlreadyExists(_))
+    ));
+    drop(tx);
+
+    let mut tx = storage.begin(Lock::None)?;
+    tx.spend_token("dG9rZW4=")?;
+    tx.commit()?;
+    let mut tx = storage.begin(Lock::None)?;
+    assert!(matches!(
+        tx.spend_token("dG9rZW4="),
+        Err(ModelErr::AlreadyExists(_))
+    ));
+    drop(tx);
+
+    let mut tx = storage.begin(Lock::None)?;
+    let account = tx.insert_account(NewAccount {
+        name: "test",
+        pow_mode: "full",
+        daily_quota: 10,
+    })?;
+    let today = chrono::Utc::now().naive_utc().date();
+    assert_eq!(tx.count_account_usage(account.id, today, 2)?, 2);
+    assert_eq!(tx.count_account_usage(account.id, today, 3)?, 5);
+    assert_eq!(tx.count_subject_usage("iss", "sub", today, 1)?, 1);
+    tx.commit()?;
+    Ok(())
+}
+
+// Happy path, in memory
+#[test]
+fn test__storage__Memory() -> Result<(), anyhow::Error> {
+    check_contract(&MemStorage::default())
+}
+
+// Happy path, on a fresh SQLite file
+#[test]
+fn test__storage__Sqlite() -> Result<(), anyhow::Error> {
+    let path = std::env::temp_dir().join(format!("test-{}.sqlite", rand::random::<u64>()));
+    let storage = SqliteStorage::open(&path)?;
+    storage.migrate()?;
+    check_contract(&storage)?;
+    drop(storage);
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+// Happy path, in a fresh sled directory. Commits survive reopening it
+#[test]
+fn test__storage__Sled() -> Result<(), anyhow::Error> {
+    let dir = std::env::temp_dir().join(format!("test-{}.sled", rand::random::<u64>()));
+    let storage = KvStorage::open(&dir)?;
+    storage.migrate()?;
+    check_contract(&storage)?;
+    drop(storage);
+
+    let storage = KvStorage::open(&dir)?;
+    storage.check_schema()?;
+    let mut tx = storage.begin(Lock::None)?;
+    assert_eq!(tx.last_receipt()?.map(|r| r.serial), Some(2));
+    assert!(tx.receipt_by_data_hash("b3RoZXI=")?.is_none());
+    drop(tx);
+    drop(storage);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+// A short or corrupt index value in sled is an error, not a panic
+#[test]
+fn test__storage__SledCorrupt() -> Result<(), anyhow::Error> {
+    let dir = std::env::temp_dir().join(format!("test-{}.sled", rand::random::<u64>()));
+    // `account_name/`, then the length-prefixed name: its value should be an 8-byte id
+    let db = sled::open(&dir)?;
+    let key = [&b"account_name/"[..], &4u32.to_be_bytes(), b"test"].concat();
+    db.insert(key, &[1u8][..])?;
+    db.flush()?;
+    drop(db);
+
+    let storage = KvStorage::open(&dir)?;
+    storage.migrate()?;
+    let mut tx = storage.begin(Lock::None)?;
+    assert!(matches!(
+        tx.account_by_name("test"),
+        Err(ModelErr::Corrupt(_))
+    ));
+    drop(tx);
+    drop(storage);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
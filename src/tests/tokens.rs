@@ -0,0 +1,47 @@
+use crate::utils::tokens::{BlindedToken, TokenErr, TokenKey};
+
+// Happy path: unblinded tokens redeem, bound to the message they were redeemed for
+#[test]
+fn test__tokens__OK() -> Result<(), anyhow::Error> {
+    let key = TokenKey::generate();
+    let blinded: Vec<BlindedToken> = (0..3).map(|_| BlindedToken::generate()).collect();
+    let points: Vec<_> = blinded.iter().map(|b| b.blinded).collect();
+
+    let (evaluated, proof) = key.issue(&points);
+    let tokens = BlindedToken::unblind_batch(blinded, &evaluated, &proof, &key.pubkey())?;
+    assert_eq!(tokens.len(), 3);
+
+    for token in &tokens {
+        let header = token.authorization(b"data hash");
+        let (nonce_b64, mac_b64) = header["PrivateToken ".len()..].split_at(44);
+        let mut nonce = [0u8; 32];
+        nonce.copy_from_slice(&base64::decode(nonce_b64)?);
+        let mut mac = [0u8; 32];
+        mac.copy_from_slice(&base64::decode(&mac_b64[1..])?);
+        assert!(key.verify_redemption(&nonce, &mac, b"data hash"));
+        assert!(!key.verify_redemption(&nonce, &mac, b"other data hash"));
+        assert!(!TokenKey::generate().verify_redemption(&nonce, &mac, b"data hash"));
+    }
+    Ok(())
+}
+
+// Evaluations with another key than the pinned one, e.g. to tag a client, are detected
+#[test]
+fn test__tokens__BadProof() -> Result<(), anyhow::Error> {
+    let key = TokenKey::generate();
+    let blinded: Vec<BlindedToken> = (0..2).map(|_| BlindedToken::generate()).collect();
+    let points: Vec<_> = blinded.iter().map(|b| b.blinded).collect();
+
+    let (evaluated, proof) = TokenKey::generate().issue(&points);
+    let res = BlindedToken::unblind_batch(blinded, &evaluated, &proof, &key.pubkey());
+    assert!(matches!(res, Err(TokenErr::BadProof)));
+
+    // nor can a single evaluation of the batch be swapped
+    let blinded: Vec<BlindedToken> = (0..2).map(|_| BlindedToken::generate()).collect();
+    let points: Vec<_> = blinded.iter().map(|b| b.blinded).collect();
+    let (mut evaluated, proof) = key.issue(&points);
+    evaluated[1] = TokenKey::generate().issue(&points[1..]).0[0];
+    let res = BlindedToken::unblind_batch(blinded, &evaluated, &proof, &key.pubkey());
+    assert!(matches!(res, Err(TokenErr::BadProof)));
+    Ok(())
+}
@@ -0,0 +1,356 @@
+//! Blind RSA signatures (RFC 9474, RSABSSA-SHA384-PSSZERO-Deterministic), for clients that
+//! can't reveal even their data's hash. Timestamps can't be blindly signed in, so they come from
+//! the key instead: each key is certified for a short epoch, and discarded at its end.
+//! A blind receipt proves the data was signed within its key's epoch.
+//! Epoch keys live where the signing key does: in the signer daemon with a signer socket.
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::PublicKey;
+use num_bigint_dig::{BigUint, ModInverse, RandBigInt, RandPrime};
+use rand::rngs::OsRng;
+use schemars::JsonSchema;
+use sha2::{Digest, Sha384};
+use std::sync::{Arc, Mutex};
+use zeroize::Zeroize;
+//
+use super::crypto_sign::{self, KeyPair};
+use super::delegation::Delegation;
+use crate::signer::SignerErr;
+
+pub const SCHEME: &str = "RSABSSA-SHA384-PSSZERO-Deterministic";
+pub const KEY_BITS: usize = 2048;
+const PUBLIC_EXPONENT: u32 = 65537;
+const HASH_LEN: usize = 48;
+
+/// Successive epochs' keys, kept where the signing key is: only the current one is
+pub struct EpochKeys {
+    epoch: Duration,
+    current: Mutex<Option<Arc<EpochKey>>>,
+}
+impl EpochKeys {
+    pub fn new(epoch: Duration) -> Self {
+        Self {
+            epoch,
+            current: Mutex::new(None),
+        }
+    }
+    /// The key of the current epoch, generating the next one, certified by `keypair`, if it's
+    /// over. Check the signing conditions first: a revoked key or an untrusted clock mustn't
+    /// certify one
+    pub fn current(&self, keypair: &KeyPair) -> Result<Arc<EpochKey>, BlindErr> {
+        let mut current = self.current.lock().map_err(|_| BlindErr::Poisoned)?;
+        let now = Utc::now();
+        match &*current {
+            Some(key) if key.covers(now) => Ok(key.clone()),
+            _ => {
+                let key = Arc::new(EpochKey::generate(keypair, now, self.epoch)?);
+                *current = Some(key.clone());
+                Ok(key)
+            }
+        }
+    }
+    /// The current epoch's key if it's `key_id`'s: never generates one
+    pub fn get(&self, key_id: &str) -> Result<Arc<EpochKey>, BlindErr> {
+        let current = self.current.lock().map_err(|_| BlindErr::Poisoned)?;
+        match &*current {
+            Some(key) if key.cert.key_id() == key_id && key.covers(Utc::now()) => Ok(key.clone()),
+            _ => Err(BlindErr::EpochOver),
+        }
+    }
+}
+
+/// A blind signing key, valid for one epoch. The private exponent is zeroized on drop
+pub struct EpochKey {
+    n: BigUint,
+    e: BigUint,
+    d: BigUint,
+    pub cert: EpochCert,
+}
+impl Drop for EpochKey {
+    fn drop(&mut self) {
+        self.d.zeroize();
+    }
+}
+impl EpochKey {
+    /// Certified by `keypair`, without delegation: the HTTP server attaches its own
+    pub fn generate(
+        keypair: &KeyPair,
+        not_before: DateTime<Utc>,
+        epoch: Duration,
+    ) -> Result<Self, BlindErr> {
+        let (n, e, d) = generate_rsa();
+        let fields_signed = EpochCertFields {
+            scheme: SCHEME.to_string(),
+            modulus_base64: base64::encode(&n.to_bytes_be()),
+            exponent_base64: base64::encode(&e.to_bytes_be()),
+            not_before,
+            not_after: not_before + epoch,
+        };
+        let signature = keypair.sign(&fields_signed.hash()?);
+        let cert = EpochCert {
+            fields_signed,
+            signature_base64: base64::encode(&signature[..]),
+            delegation: None,
+        };
+        Ok(Self { n, e, d, cert })
+    }
+    pub fn covers(&self, at: DateTime<Utc>) -> bool {
+        self.cert.covers(at)
+    }
+
+    /// Signs a blinded message, as big-endian bytes of the modulus' length.
+    /// Refused outside the epoch: receipts would lie about their time.
+    pub fn sign_blinded(&self, blinded: &[u8]) -> Result<Vec<u8>, BlindErr> {
+        if !self.covers(Utc::now()) {
+            return Err(BlindErr::EpochOver);
+        }
+        check_blinded(&self.n, blinded)?;
+        let k = modulus_len(&self.n);
+        let m = BigUint::from_bytes_be(blinded);
+        // blinded again on our side, against timing attacks on the private exponent
+        let r = OsRng.gen_biguint_below(&self.n);
+        let r_inv = mod_inverse(&r, &self.n).ok_or(BlindErr::BadBlindedMessage)?;
+        let s = (m.clone() * r.modpow(&self.e, &self.n)).modpow(&self.d, &self.n) * r_inv % &self.n;
+        // a faulty signature could leak the key: check it before it leaves
+        if s.modpow(&self.e, &self.n) != m {
+            return Err(BlindErr::Faulty);
+        }
+        Ok(to_bytes(&s, k))
+    }
+}
+
+/// The trust anchor's certificate of an epoch's blind signing key
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct EpochCert {
+    pub fields_signed: EpochCertFields,
+    pub signature_base64: String,
+    // Chains the signing key to the root key clients pin, when signing is delegated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delegation: Option<Delegation>,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct EpochCertFields {
+    pub scheme: String,
+    // big-endian RSA modulus and public exponent
+    pub modulus_base64: String,
+    pub exponent_base64: String,
+    // UTC: an epoch never repeats, as local times do when DST ends
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+}
+impl EpochCertFields {
+    fn hash(&self) -> Result<[u8; 32], BlindErr> {
+        let json_bytes: Vec<u8> = serde_json::to_vec(&self)?;
+        Ok(*blake3::hash(&json_bytes).as_bytes())
+    }
+}
+impl EpochCert {
+    /// Identifies the key to blind for: short, URL-safe
+    pub fn key_id(&self) -> String {
+        blake3::hash(self.fields_signed.modulus_base64.as_bytes()).as_bytes()[..16]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+    pub fn covers(&self, at: DateTime<Utc>) -> bool {
+        self.fields_signed.not_before <= at && at < self.fields_signed.not_after
+    }
+    /// Whether the key is certified by `trust_anchor`, directly or through the delegation
+    pub fn verify(&self, trust_anchor: &PublicKey) -> bool {
+        let signing_key = match &self.delegation {
+            None => *trust_anchor,
+            Some(d) => match (d.verify(trust_anchor), d.online_pubkey()) {
+                (Ok(()), Ok(online)) if d.covers(self.fields_signed.not_before) => online,
+                _ => return false,
+            },
+        };
+        match (
+            self.fields_signed.hash(),
+            base64::decode(&self.signature_base64),
+        ) {
+            (Ok(hash), Ok(sig)) => crypto_sign::verify(&signing_key, &hash, &sig),
+            _ => false,
+        }
+    }
+    /// Whether `blinded` can be signed by this epoch's key: checked before the client is charged
+    pub fn check_blinded(&self, blinded: &[u8]) -> Result<(), BlindErr> {
+        check_blinded(&self.pubkey()?.0, blinded)
+    }
+    fn pubkey(&self) -> Result<(BigUint, BigUint), BlindErr> {
+        let n = BigUint::from_bytes_be(&base64::decode(&self.fields_signed.modulus_base64)?);
+        let e = BigUint::from_bytes_be(&base64::decode(&self.fields_signed.exponent_base64)?);
+        Ok((n, e))
+    }
+}
+
+/// What clients keep: the receipt's data hash never left them.
+/// Verifiers learn the data was signed within the epoch of `epoch`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BlindReceipt {
+    pub signature_base64: String,
+    pub epoch: EpochCert,
+}
+impl BlindReceipt {
+    /// Client-side verification against the data and the pinned key
+    pub fn verify(&self, data: &[u8], trust_anchor: &PublicKey) -> bool {
+        let sig = match base64::decode(&self.signature_base64) {
+            Ok(sig) => sig,
+            Err(_) => return false,
+        };
+        match self.epoch.pubkey() {
+            Ok((n, e)) => {
+                self.epoch.fields_signed.scheme == SCHEME
+                    && self.epoch.verify(trust_anchor)
+                    && verify(&n, &e, blake3::hash(data).as_bytes(), &sig)
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Client side: a message blinded for an epoch's key, and what's needed to unblind its signature
+pub struct Blinded {
+    pub blinded: Vec<u8>,
+    message: Vec<u8>,
+    r_inv: BigUint,
+    cert: EpochCert,
+}
+impl Blinded {
+    /// Blinds the data's hash. Check the certificate against the pinned key first.
+    pub fn new(cert: &EpochCert, data: &[u8]) -> Result<Self, BlindErr> {
+        let (n, e) = cert.pubkey()?;
+        let message = blake3::hash(data).as_bytes().to_vec();
+        let m = BigUint::from_bytes_be(&emsa_pss_encode(&message, &n)?);
+        let (r, r_inv) = loop {
+            let r = OsRng.gen_biguint_below(&n);
+            if let Some(r_inv) = mod_inverse(&r, &n) {
+                break (r, r_inv);
+            }
+        };
+        let blinded = m * r.modpow(&e, &n) % &n;
+        Ok(Self {
+            blinded: to_bytes(&blinded, modulus_len(&n)),
+            message,
+            r_inv,
+            cert: cert.clone(),
+        })
+    }
+    /// Unblinds the server's signature into a receipt, checking it
+    pub fn finalize(self, blind_sig: &[u8]) -> Result<BlindReceipt, BlindErr> {
+        let (n, e) = self.cert.pubkey()?;
+        let s = BigUint::from_bytes_be(blind_sig) * &self.r_inv % &n;
+        let sig = to_bytes(&s, modulus_len(&n));
+        if !verify(&n, &e, &self.message, &sig) {
+            return Err(BlindErr::BadSignature);
+        }
+        Ok(BlindReceipt {
+            signature_base64: base64::encode(&sig),
+            epoch: self.cert,
+        })
+    }
+}
+
+fn verify(n: &BigUint, e: &BigUint, message: &[u8], sig: &[u8]) -> bool {
+    let s = BigUint::from_bytes_be(sig);
+    if sig.len() != modulus_len(n) || &s >= n {
+        return false;
+    }
+    match emsa_pss_encode(message, n) {
+        Ok(em) => s.modpow(e, n) == BigUint::from_bytes_be(&em),
+        Err(_) => false,
+    }
+}
+
+// EMSA-PSS-ENCODE (RFC 8017, 9.1.1) with SHA-384, MGF1 and no salt: deterministic
+fn emsa_pss_encode(message: &[u8], n: &BigUint) -> Result<Vec<u8>, BlindErr> {
+    let em_bits = n.bits() - 1;
+    let em_len = (em_bits + 7) / 8;
+    if em_len < HASH_LEN + 2 {
+        return Err(BlindErr::KeyTooSmall);
+    }
+    let m_hash = Sha384::digest(message);
+    let h = Sha384::digest(&[&[0u8; 8][..], &m_hash[..]].concat());
+    let mut db = vec![0u8; em_len - HASH_LEN - 1];
+    let last = db.len() - 1;
+    db[last] = 0x01;
+    for (byte, mask) in db.iter_mut().zip(mgf1(&h, em_len - HASH_LEN - 1)) {
+        *byte ^= mask;
+    }
+    db[0] &= 0xff >> (8 * em_len - em_bits);
+    Ok([&db[..], &h[..], &[0xbc][..]].concat())
+}
+fn mgf1(seed: &[u8], len: usize) -> Vec<u8> {
+    let mut mask = Vec::with_capacity(len + HASH_LEN);
+    let mut counter = 0u32;
+    while mask.len() < len {
+        mask.extend_from_slice(&Sha384::digest(
+            &[seed, &counter.to_be_bytes()[..]].concat(),
+        ));
+        counter += 1;
+    }
+    mask.truncate(len);
+    mask
+}
+
+// the primes and phi are zeroized: only n, e and d are kept
+fn generate_rsa() -> (BigUint, BigUint, BigUint) {
+    let e = BigUint::from(PUBLIC_EXPONENT);
+    let one = BigUint::from(1u32);
+    loop {
+        let mut p = OsRng.gen_prime(KEY_BITS / 2);
+        let mut q = OsRng.gen_prime(KEY_BITS / 2);
+        let n = &p * &q;
+        let mut phi = (&p - &one) * (&q - &one);
+        let d = match p != q && n.bits() == KEY_BITS {
+            true => mod_inverse(&e, &phi),
+            false => None,
+        };
+        p.zeroize();
+        q.zeroize();
+        phi.zeroize();
+        if let Some(d) = d {
+            return (n, e, d);
+        }
+    }
+}
+// as big-endian bytes of the modulus' length, smaller than it
+fn check_blinded(n: &BigUint, blinded: &[u8]) -> Result<(), BlindErr> {
+    if blinded.len() != modulus_len(n) || &BigUint::from_bytes_be(blinded) >= n {
+        return Err(BlindErr::BadBlindedMessage);
+    }
+    Ok(())
+}
+fn mod_inverse(a: &BigUint, n: &BigUint) -> Option<BigUint> {
+    a.mod_inverse(n).and_then(|inv| inv.to_biguint())
+}
+fn modulus_len(n: &BigUint) -> usize {
+    (n.bits() + 7) / 8
+}
+// big-endian, left-padded to `len`
+fn to_bytes(x: &BigUint, len: usize) -> Vec<u8> {
+    let bytes = x.to_bytes_be();
+    let mut padded = vec![0u8; len.saturating_sub(bytes.len())];
+    padded.extend_from_slice(&bytes);
+    padded
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum BlindErr {
+    #[error("signer err: {0}")]
+    Signer(#[from] SignerErr),
+    #[error("ser err: {0}")]
+    Ser(#[from] serde_json::Error),
+    #[error("base64 decode err: {0}")]
+    B64(#[from] base64::DecodeError),
+    #[error("blinded message must be as long as the modulus, and smaller")]
+    BadBlindedMessage,
+    #[error("the key's epoch is over")]
+    EpochOver,
+    #[error("RSA key too small")]
+    KeyTooSmall,
+    #[error("blind signature doesn't verify")]
+    BadSignature,
+    #[error("faulty signature computation")]
+    Faulty,
+    #[error("poisoned lock")]
+    Poisoned,
+}
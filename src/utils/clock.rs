@@ -0,0 +1,260 @@
+//! Clock integrity: before signing, the local clock is checked against reference time sources.
+use chrono::{Duration, NaiveDateTime, Utc};
+use ed25519_dalek::PublicKey;
+use rand::RngCore;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Mutex;
+use std::time::{Duration as StdDuration, Instant};
+//
+use crate::roughtime;
+
+const NTP_PORT: u16 = 123;
+const QUERY_TIMEOUT: StdDuration = StdDuration::from_secs(2);
+// a check older than this many `recheck` periods means checks stopped: the clock isn't vouched for
+const STALE_AFTER_RECHECKS: u32 = 3;
+// seconds from 1900-01-01, the NTP epoch, to the Unix epoch
+const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+
+/// A reference clock, parsed from `ntp:<host>[:<port>]` or `roughtime:<pubkey_base64>@<host>[:<port>]`
+/// (IPv6 hosts in brackets)
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClockSource {
+    Ntp(String),
+    /// authenticated by the server's long-term key
+    Roughtime(String, PublicKey),
+}
+impl ClockSource {
+    pub fn parse(s: &str) -> Result<Self, ClockErr> {
+        let bad_source = || ClockErr::BadSource(s.to_string());
+        match s.trim().splitn(2, ':').collect::<Vec<&str>>().as_slice() {
+            ["ntp", addr] if !addr.is_empty() => Ok(ClockSource::Ntp(with_port(addr, NTP_PORT))),
+            ["roughtime", key_at_addr] => {
+                match key_at_addr.splitn(2, '@').collect::<Vec<&str>>()[..] {
+                    [pubkey_b64, addr] if !addr.is_empty() => {
+                        let pubkey = base64::decode(pubkey_b64).map_err(|_| bad_source())?;
+                        let pubkey = PublicKey::from_bytes(&pubkey).map_err(|_| bad_source())?;
+                        Ok(ClockSource::Roughtime(
+                            with_port(addr, roughtime::DEFAULT_PORT),
+                            pubkey,
+                        ))
+                    }
+                    _ => Err(bad_source()),
+                }
+            }
+            _ => Err(bad_source()),
+        }
+    }
+    /// Comma-separated sources, as configured
+    pub fn parse_list(s: &str) -> Result<Vec<Self>, ClockErr> {
+        s.split(',')
+            .filter(|source| !source.trim().is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+    fn query(&self) -> Result<Sample, ClockErr> {
+        match self {
+            ClockSource::Ntp(addr) => sntp_query(addr),
+            ClockSource::Roughtime(addr, pubkey) => {
+                let time = roughtime::client::query(addr, pubkey)
+                    .map_err(|e| ClockErr::BadResponse(format!("{}: {}", addr, e)))?;
+                let round_trip = time.received_at - time.sent_at;
+                Ok(Sample {
+                    offset: time.midpoint - (time.sent_at + round_trip / 2),
+                    uncertainty: time.radius + round_trip / 2,
+                })
+            }
+        }
+    }
+}
+fn with_port(addr: &str, port: u16) -> String {
+    match addr.ends_with(']') || !addr.contains(':') {
+        true => format!("{}:{}", addr, port),
+        false => addr.to_string(),
+    }
+}
+
+/// A reference clock's offset from the local clock, within ± `uncertainty`
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    pub offset: Duration,
+    pub uncertainty: Duration,
+}
+
+/// Refuses to vouch for the local clock when the reference sources disagree with it by more than `max_skew`,
+/// or when none of them answers. Sources are queried every `recheck`, off the request path, by
+/// `refresh_periodically`: `accuracy` only reads the last check's result.
+pub struct ClockGuard {
+    sources: Vec<ClockSource>,
+    max_skew: Duration,
+    recheck: StdDuration,
+    last_check: Mutex<Option<(Instant, Result<Duration, ClockErr>)>>,
+}
+impl ClockGuard {
+    pub fn new(sources: Vec<ClockSource>, max_skew: Duration, recheck: StdDuration) -> Self {
+        Self {
+            sources,
+            max_skew,
+            recheck,
+            last_check: Mutex::new(None),
+        }
+    }
+    /// How far off the local clock may be, as last checked. None without reference sources.
+    /// Fails closed when no check is recent enough, e.g before the first one.
+    pub fn accuracy(&self) -> Result<Option<Duration>, ClockErr> {
+        if self.sources.is_empty() {
+            return Ok(None);
+        }
+        let last_check = self.last_check.lock().unwrap_or_else(|e| e.into_inner());
+        match &*last_check {
+            Some((checked_at, res))
+                if checked_at.elapsed() < self.recheck * STALE_AFTER_RECHECKS =>
+            {
+                res.clone().map(Some)
+            }
+            _ => Err(ClockErr::Stale),
+        }
+    }
+    /// Queries the sources now, and keeps the result for `accuracy`. The lock isn't held while querying
+    pub fn refresh(&self) {
+        let res = self.check();
+        if let Err(e) = &res {
+            warn!("clock check failed: {}", e);
+        }
+        *self.last_check.lock().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), res));
+    }
+    pub async fn refresh_periodically(&'static self) {
+        if self.sources.is_empty() {
+            return;
+        }
+        let mut interval = tokio::time::interval(self.recheck);
+        loop {
+            interval.tick().await;
+            if let Err(e) = tokio::task::spawn_blocking(move || self.refresh()).await {
+                error!("clock check task failed: {}", e);
+            }
+        }
+    }
+    /// Queries every source: the median offset, widened by the worst uncertainty, must stay within `max_skew`
+    pub fn check(&self) -> Result<Duration, ClockErr> {
+        let samples: Vec<Sample> = self
+            .sources
+            .iter()
+            .filter_map(|source| match source.query() {
+                Ok(sample) => Some(sample),
+                Err(e) => {
+                    warn!("clock source {:?} failed: {}", source, e);
+                    None
+                }
+            })
+            .collect();
+        let mut offsets: Vec<Duration> = samples.iter().map(|s| s.offset).collect();
+        offsets.sort();
+        let median = *offsets.get(offsets.len() / 2).ok_or(ClockErr::NoSource)?;
+        let uncertainty = samples
+            .iter()
+            .map(|s| s.uncertainty)
+            .max()
+            .unwrap_or_else(Duration::zero);
+        let accuracy = abs(median) + uncertainty;
+        match accuracy > self.max_skew {
+            true => Err(ClockErr::Skewed {
+                skew_ms: accuracy.num_milliseconds(),
+            }),
+            false => Ok(accuracy),
+        }
+    }
+}
+fn abs(d: Duration) -> Duration {
+    match d < Duration::zero() {
+        true => -d,
+        false => d,
+    }
+}
+
+// SNTP (RFC 4330). The request's transmit timestamp is a random nonce the server must echo back,
+// so off-path attackers can't answer in its place.
+fn sntp_query(addr: &str) -> Result<Sample, ClockErr> {
+    let bad_response = || ClockErr::BadResponse(addr.to_string());
+    let target: SocketAddr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| ClockErr::BadSource(addr.to_string()))?;
+    let socket = match target {
+        SocketAddr::V4(_) => UdpSocket::bind("0.0.0.0:0")?,
+        SocketAddr::V6(_) => UdpSocket::bind("[::]:0")?,
+    };
+    socket.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    socket.connect(target)?;
+
+    let mut req = [0u8; 48];
+    req[0] = 0x23; // no leap warning, version 4, client mode
+    rand::rngs::OsRng.fill_bytes(&mut req[40..48]);
+    let t1 = Utc::now().naive_utc();
+    socket.send(&req)?;
+    let mut resp = [0u8; 48];
+    let len = socket.recv(&mut resp)?;
+    let t4 = Utc::now().naive_utc();
+
+    // server mode, not a kiss-o'-death, and answering our request
+    if len < 48 || resp[0] & 0x07 != 4 || resp[1] == 0 || resp[24..32] != req[40..48] {
+        return Err(bad_response());
+    }
+    let t2 = from_ntp_timestamp(&resp[32..40]).ok_or_else(bad_response)?;
+    let t3 = from_ntp_timestamp(&resp[40..48]).ok_or_else(bad_response)?;
+    let root_delay = from_ntp_short(&resp[4..8]);
+    let root_dispersion = from_ntp_short(&resp[8..12]);
+    let round_trip = (t4 - t1) - (t3 - t2);
+    Ok(Sample {
+        offset: ((t2 - t1) + (t3 - t4)) / 2,
+        uncertainty: (round_trip + root_delay) / 2 + root_dispersion,
+    })
+}
+
+pub(crate) fn to_ntp_timestamp(t: NaiveDateTime) -> [u8; 8] {
+    let secs = (t.timestamp() + NTP_UNIX_OFFSET) as u32;
+    let frac = ((t.timestamp_subsec_nanos() as u64) << 32) / 1_000_000_000;
+    let mut bytes = [0u8; 8];
+    bytes[..4].copy_from_slice(&secs.to_be_bytes());
+    bytes[4..].copy_from_slice(&(frac as u32).to_be_bytes());
+    bytes
+}
+fn from_ntp_timestamp(bytes: &[u8]) -> Option<NaiveDateTime> {
+    let secs = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64;
+    let frac = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as u64;
+    // timestamps with the top bit clear are in the next era, from 2036 on
+    let secs = match secs & 0x8000_0000 {
+        0 => secs + (1 << 32),
+        _ => secs,
+    };
+    let nanos = (frac * 1_000_000_000) >> 32;
+    NaiveDateTime::from_timestamp_opt(secs - NTP_UNIX_OFFSET, nanos as u32)
+}
+// 16.16 fixed-point seconds
+fn from_ntp_short(bytes: &[u8]) -> Duration {
+    let fixed = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64;
+    Duration::microseconds((fixed * 1_000_000) >> 16)
+}
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum ClockErr {
+    #[error("invalid clock source {0:?}, expected ntp:<host>[:<port>] or roughtime:<pubkey_base64>@<host>[:<port>]")]
+    BadSource(String),
+    #[error("invalid response from {0}")]
+    BadResponse(String),
+    #[error("no clock source answered")]
+    NoSource,
+    #[error("clock not checked against its sources recently")]
+    Stale,
+    #[error("local clock is off by up to {skew_ms}ms")]
+    Skewed { skew_ms: i64 },
+    #[error("local clock went backwards: last receipt at {last}")]
+    WentBackwards { last: NaiveDateTime },
+    #[error("IO err: {0}")]
+    Io(String),
+}
+impl From<std::io::Error> for ClockErr {
+    // for Clone (std::io::Error doesn't implement Clone)
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(std::error::Error::to_string(&e))
+    }
+}
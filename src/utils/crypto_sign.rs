@@ -2,58 +2,148 @@ use ed25519_dalek::{
     self as ed25d, Keypair, PublicKey, Signature, SignatureError, Signer, Verifier,
 };
 use rand::rngs::OsRng;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::io::ErrorKind;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use zeroize::{Zeroize, Zeroizing};
 
-#[derive(Debug)]
-pub struct KeyPair(ed25519_dalek::Keypair);
+/// The service's signing keys.
+/// Boxed so the secret stays at one heap address, which is locked against swap where possible
+/// and zeroized on drop. Deliberately not `Debug`-derived: only the public key is ever printed.
+pub struct KeyPair {
+    keypair: Box<Keypair>,
+    mlocked: bool,
+}
 impl KeyPair {
+    fn new(keypair: Keypair) -> Self {
+        let keypair = Box::new(keypair);
+        let mlocked = mlock(&*keypair);
+        if !mlocked {
+            warn!("couldn't mlock signing key memory, it may be swapped to disk");
+        }
+        Self { keypair, mlocked }
+    }
     pub fn generate() -> Self {
-        Self(Keypair::generate(&mut OsRng {}))
+        Self::new(Keypair::generate(&mut OsRng {}))
+    }
+    /// Rebuilds the keypair from its 32-byte secret alone, deriving the public half
+    pub fn from_secret_bytes(secret: &[u8]) -> Result<Self, KpErr> {
+        let secret = ed25d::SecretKey::from_bytes(secret)?;
+        let public: PublicKey = (&secret).into();
+        Ok(Self::new(Keypair { secret, public }))
     }
     pub fn pubkey(&self) -> PublicKey {
-        self.0.public
+        self.keypair.public
     }
     pub fn sign(&self, message: &[u8]) -> [u8; 64] {
-        self.0.sign(&message).to_bytes()
+        self.keypair.sign(&message).to_bytes()
     }
     pub fn verify(&self, message: &[u8], sig: impl AsRef<[u8]> + Clone + Sized) -> bool {
-        if sig.as_ref().len() != 64 {
-            return false;
+        verify(&self.keypair.public, message, sig)
+    }
+
+    /// Loads the key at `keyfile`. A new key is only ever minted when the file doesn't exist,
+    /// and only in `GenerateIfMissing` mode: any other failure is returned, never overwritten.
+    pub fn load(keyfile: &Path, mode: KeyMode) -> Result<Self, KpErr> {
+        match (Self::from_file(keyfile), mode) {
+            (Err(KpErr::Missing(_)), KeyMode::GenerateIfMissing) => {
+                warn!("no key file at {}, generating one", keyfile.display());
+                Self::generate_to_file(keyfile)
+            }
+            (res, _) => res,
         }
-        use std::convert::TryFrom;
-        let sig = match Signature::try_from(sig.as_ref()) {
-            Ok(sig) => sig,
-            Err(_) => return false,
-        };
-        self.0.public.verify(message, &sig).is_ok()
+    }
+    /// Generates a key into `keyfile`, refusing to replace an existing one
+    pub fn generate_to_file(keyfile: &Path) -> Result<Self, KpErr> {
+        let new_keys = Self::generate();
+        new_keys.to_file(keyfile)?;
+        Ok(new_keys)
     }
 
-    fn to_file(&self, keyfile: &PathBuf) -> Result<&Self, KpErr> {
+    pub fn to_file(&self, keyfile: &Path) -> Result<&Self, KpErr> {
         let dir = keyfile.parent().ok_or(KpErr::NoParentDir)?;
         fs::create_dir_all(dir)?;
-        fs::write(keyfile, self.to_str())?;
+        let content_str = Zeroizing::new(self.to_str());
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(keyfile)
+            .map_err(|e| match e.kind() {
+                ErrorKind::AlreadyExists => KpErr::AlreadyExists(keyfile.to_path_buf()),
+                _ => KpErr::from(e),
+            })?;
+        file.write_all(content_str.as_bytes())?;
         Ok(self)
     }
-    fn from_file(keyfile: &PathBuf) -> Result<Self, KpErr> {
-        let content_str = fs::read_to_string(keyfile)?;
-        Ok(Self::from_str(&content_str)?)
-    }
-    pub fn from_file_or_new(keyfile: &PathBuf) -> Result<Self, KpErr> {
-        match Self::from_file(&keyfile) {
-            Ok(keys) => Ok(keys),
-            Err(_err) => {
-                let new_keys = Self::generate();
-                new_keys.to_file(&keyfile)?;
-                Ok(new_keys)
-            }
+    fn from_file(keyfile: &Path) -> Result<Self, KpErr> {
+        let path = keyfile.to_path_buf();
+        let content_str =
+            Zeroizing::new(fs::read_to_string(keyfile).map_err(|e| match e.kind() {
+                ErrorKind::NotFound => KpErr::Missing(path.clone()),
+                _ => KpErr::Unreadable {
+                    path: path.clone(),
+                    reason: std::error::Error::to_string(&e),
+                },
+            })?);
+        Self::from_str(content_str.trim()).map_err(|e| match e {
+            KpErr::BytesLengthErr { expected, got } => KpErr::WrongLength {
+                path,
+                expected,
+                got,
+            },
+            e => KpErr::Malformed {
+                path,
+                reason: e.to_string(),
+            },
+        })
+    }
+}
+impl Drop for KeyPair {
+    fn drop(&mut self) {
+        self.keypair.secret.zeroize();
+        if self.mlocked {
+            munlock(&*self.keypair);
         }
     }
 }
+impl fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyPair")
+            .field("pubkey", &base64::encode(self.pubkey().as_bytes()))
+            .finish()
+    }
+}
+
+fn mlock<T>(val: &T) -> bool {
+    let ptr = val as *const T as *const libc::c_void;
+    unsafe { libc::mlock(ptr, std::mem::size_of::<T>()) == 0 }
+}
+fn munlock<T>(val: &T) {
+    let ptr = val as *const T as *const libc::c_void;
+    unsafe { libc::munlock(ptr, std::mem::size_of::<T>()) };
+}
+
+/// Verifies a signature with only the public key at hand
+pub fn verify(pubkey: &PublicKey, message: &[u8], sig: impl AsRef<[u8]>) -> bool {
+    if sig.as_ref().len() != 64 {
+        return false;
+    }
+    use std::convert::TryFrom;
+    let sig = match Signature::try_from(sig.as_ref()) {
+        Ok(sig) => sig,
+        Err(_) => return false,
+    };
+    pubkey.verify(message, &sig).is_ok()
+}
+
 impl AsBytes for KeyPair {
     type Err = KpErr;
     fn to_bytes(&self) -> [u8; 64] {
-        self.0.to_bytes()
+        self.keypair.to_bytes()
     }
     fn from_bytes(bytes: &[u8]) -> Result<Self, KpErr> {
         if bytes.len() != 64 {
@@ -62,23 +152,53 @@ impl AsBytes for KeyPair {
                 got: bytes.len(),
             });
         }
-        Ok(Self(ed25d::Keypair::from_bytes(bytes)?))
+        Ok(Self::new(ed25d::Keypair::from_bytes(bytes)?))
     }
 }
 
+/// What to do when the key file doesn't exist yet
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyMode {
+    /// Refuse to start: the key must be provisioned beforehand (e.g with `keygen`)
+    Strict,
+    /// Mint a fresh key into the missing file. For development only.
+    GenerateIfMissing,
+}
+
 #[derive(thiserror::Error, Debug, Clone)]
 pub enum KpErr {
+    #[error("no key file at {}: create one with the `keygen` command", .0.display())]
+    Missing(PathBuf),
+    #[error("key file at {} is unreadable (check its permissions): {reason}", .path.display())]
+    Unreadable { path: PathBuf, reason: String },
+    #[error("key file at {} is malformed, restore it from backup: {reason}", .path.display())]
+    Malformed { path: PathBuf, reason: String },
+    #[error("key file at {} has wrong length: expected {expected} bytes, got {got}. restore it from backup", .path.display())]
+    WrongLength {
+        path: PathBuf,
+        expected: usize,
+        got: usize,
+    },
+    #[error("refusing to overwrite existing key file at {}", .0.display())]
+    AlreadyExists(PathBuf),
     #[error("unexpected bytes length: expected: {expected}, got: {got}")]
     BytesLengthErr { expected: usize, got: usize },
     #[error("signature err: {0}")]
     SignatureErr(String),
-    #[error(transparent)]
-    B64Err(#[from] B64Err),
+    #[error("invalid base64 key encoding")]
+    B64Err,
     #[error("IO err: {0}")]
     IoErr(String),
     #[error("no parent directory")]
     NoParentDir,
 }
+impl From<B64Err> for KpErr {
+    // detail dropped: base64 decode errors echo bytes of the key material
+    fn from(_e: B64Err) -> Self {
+        Self::B64Err
+    }
+}
 impl From<SignatureError> for KpErr {
     // for Clone (SignatureError doesn't implement Clone)
     fn from(e: SignatureError) -> Self {
@@ -113,11 +233,19 @@ where
 {
     type Err = <Self as AsBytes>::Err;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self::from_bytes(
-            &base64::decode(s).map_err(B64Err::from)?.to_vec(),
-        )?)
+        let bytes = Zeroizing::new(base64::decode(s).map_err(B64Err::from)?);
+        Ok(Self::from_bytes(&bytes)?)
     }
     fn to_str(&self) -> String {
-        base64::encode(self.to_bytes().to_vec())
+        let bytes = Zeroizing::new(self.to_bytes());
+        base64::encode(&bytes[..])
     }
 }
+
+/// Short, URL-safe identifier of a public key
+pub fn key_id(pubkey: &PublicKey) -> String {
+    blake3::hash(pubkey.as_bytes()).as_bytes()[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
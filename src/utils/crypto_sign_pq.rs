@@ -0,0 +1,147 @@
+use pqcrypto_dilithium::dilithium3;
+use pqcrypto_traits::sign::{DetachedSignature as _, PublicKey as _, SecretKey as _};
+use std::fmt;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use zeroize::{Zeroize, Zeroizing};
+//
+use super::crypto_sign::{KeyMode, KpErr};
+
+/// Post-quantum scheme used alongside Ed25519 for hybrid receipts
+pub const PQ_SCHEME: &str = "dilithium3";
+// signed and verified when loading a key file
+const PROBE: &[u8] = b"crypto-timestamp-api pq key probe";
+
+/// Post-quantum signing keys. Like `KeyPair`, never `Debug`-prints the secret.
+pub struct PqKeyPair {
+    pubkey: dilithium3::PublicKey,
+    secret: Box<PqSecret>,
+}
+/// The secret is built once and boxed, so it stays at one address, which is zeroized on drop:
+/// the library's type is `Copy` and doesn't zeroize itself
+struct PqSecret(dilithium3::SecretKey);
+impl Drop for PqSecret {
+    fn drop(&mut self) {
+        // the secret key is a plain byte array: overwriting it with zeroes is sound
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(
+                &mut self.0 as *mut dilithium3::SecretKey as *mut u8,
+                std::mem::size_of::<dilithium3::SecretKey>(),
+            )
+        };
+        bytes.zeroize();
+    }
+}
+impl PqKeyPair {
+    pub fn generate() -> Self {
+        let (pubkey, secret) = dilithium3::keypair();
+        Self {
+            pubkey,
+            secret: Box::new(PqSecret(secret)),
+        }
+    }
+    pub fn pubkey_bytes(&self) -> &[u8] {
+        self.pubkey.as_bytes()
+    }
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        dilithium3::detached_sign(message, &self.secret.0)
+            .as_bytes()
+            .to_vec()
+    }
+
+    /// Same semantics as `KeyPair::load`: only a missing file is ever replaced by a new key
+    pub fn load(keyfile: &Path, mode: KeyMode) -> Result<Self, KpErr> {
+        match (Self::from_file(keyfile), mode) {
+            (Err(KpErr::Missing(_)), KeyMode::GenerateIfMissing) => {
+                warn!("no PQ key file at {}, generating one", keyfile.display());
+                Self::generate_to_file(keyfile)
+            }
+            (res, _) => res,
+        }
+    }
+    pub fn generate_to_file(keyfile: &Path) -> Result<Self, KpErr> {
+        let new_keys = Self::generate();
+        new_keys.to_file(keyfile)?;
+        Ok(new_keys)
+    }
+
+    // file format: `<pubkey base64>.<secret base64>`
+    fn to_file(&self, keyfile: &Path) -> Result<&Self, KpErr> {
+        let dir = keyfile.parent().ok_or(KpErr::NoParentDir)?;
+        fs::create_dir_all(dir)?;
+        let content_str = Zeroizing::new(format!(
+            "{}.{}",
+            base64::encode(self.pubkey_bytes()),
+            base64::encode(self.secret.0.as_bytes())
+        ));
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(keyfile)
+            .map_err(|e| match e.kind() {
+                ErrorKind::AlreadyExists => KpErr::AlreadyExists(keyfile.to_path_buf()),
+                _ => KpErr::from(e),
+            })?;
+        file.write_all(content_str.as_bytes())?;
+        Ok(self)
+    }
+    fn from_file(keyfile: &Path) -> Result<Self, KpErr> {
+        let path = keyfile.to_path_buf();
+        let content_str =
+            Zeroizing::new(fs::read_to_string(keyfile).map_err(|e| match e.kind() {
+                ErrorKind::NotFound => KpErr::Missing(path.clone()),
+                _ => KpErr::Unreadable {
+                    path: path.clone(),
+                    reason: std::error::Error::to_string(&e),
+                },
+            })?);
+        let malformed = |reason: &str| KpErr::Malformed {
+            path: path.clone(),
+            reason: reason.into(),
+        };
+        let mut parts = content_str.trim().splitn(2, '.');
+        let (pubkey_b64, secret_b64) = match (parts.next(), parts.next()) {
+            (Some(pubkey_b64), Some(secret_b64)) => (pubkey_b64, secret_b64),
+            _ => return Err(malformed("expected <pubkey>.<secret>")),
+        };
+        let pubkey_bytes = base64::decode(pubkey_b64).map_err(|_| KpErr::B64Err)?;
+        let secret_bytes = Zeroizing::new(base64::decode(secret_b64).map_err(|_| KpErr::B64Err)?);
+        let pubkey = dilithium3::PublicKey::from_bytes(&pubkey_bytes)
+            .map_err(|_| malformed("wrong pubkey length"))?;
+        let secret = Box::new(PqSecret(
+            dilithium3::SecretKey::from_bytes(&secret_bytes)
+                .map_err(|_| malformed("wrong secret length"))?,
+        ));
+        let keypair = Self { pubkey, secret };
+        // a pubkey from another key would make every receipt's PQ signature unverifiable
+        let probe = keypair.sign(PROBE);
+        if !verify_pq(keypair.pubkey_bytes(), PROBE, &probe) {
+            return Err(malformed("pubkey doesn't match the secret"));
+        }
+        Ok(keypair)
+    }
+}
+impl fmt::Debug for PqKeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PqKeyPair")
+            .field("scheme", &PQ_SCHEME)
+            .field("pubkey_hash", &blake3::hash(self.pubkey_bytes()).to_hex())
+            .finish()
+    }
+}
+
+/// Verifies a post-quantum signature with only the public key at hand
+pub fn verify_pq(pubkey_bytes: &[u8], message: &[u8], sig: &[u8]) -> bool {
+    match (
+        dilithium3::PublicKey::from_bytes(pubkey_bytes),
+        dilithium3::DetachedSignature::from_bytes(sig),
+    ) {
+        (Ok(pubkey), Ok(sig)) => {
+            dilithium3::verify_detached_signature(&sig, message, &pubkey).is_ok()
+        }
+        _ => false,
+    }
+}
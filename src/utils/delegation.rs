@@ -0,0 +1,121 @@
+use chrono::offset::LocalResult;
+use chrono::{DateTime, Duration, Local, NaiveDateTime, TimeZone, Utc};
+use ed25519_dalek::PublicKey;
+use schemars::JsonSchema;
+use std::fs;
+use std::path::Path;
+//
+use super::crypto_sign::{self, KeyPair, KpErr};
+
+/// A certificate from the offline root key, allowing an online key to sign receipts for a limited time.
+/// Clients pin the root pubkey only: online keys can be rotated without them noticing.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct Delegation {
+    pub fields_signed: DelegationFields,
+    pub signature_base64: String, // Signature by the root key
+}
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct DelegationFields {
+    pub root_pubkey_base64: String,
+    pub online_pubkey_base64: String,
+    // The online post-quantum key, certified alongside when hybrid signatures are enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub online_pq_pubkey_base64: Option<String>,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+}
+impl DelegationFields {
+    fn hash(&self) -> Result<[u8; 32], DelegationErr> {
+        let json_bytes: Vec<u8> = serde_json::to_vec(&self)?;
+        Ok(*blake3::hash(&json_bytes).as_bytes())
+    }
+}
+
+impl Delegation {
+    /// Signs a delegation to `online_pubkey` (and `online_pq_pubkey`), valid from now for `validity`
+    pub fn issue(
+        root: &KeyPair,
+        online_pubkey: &PublicKey,
+        online_pq_pubkey: Option<&[u8]>,
+        validity: Duration,
+    ) -> Result<Self, DelegationErr> {
+        let now = Utc::now();
+        let fields_signed = DelegationFields {
+            root_pubkey_base64: base64::encode(root.pubkey().as_bytes()),
+            online_pubkey_base64: base64::encode(online_pubkey.as_bytes()),
+            online_pq_pubkey_base64: online_pq_pubkey.map(base64::encode),
+            not_before: now,
+            not_after: now + validity,
+        };
+        let signature = root.sign(&fields_signed.hash()?);
+        Ok(Self {
+            fields_signed,
+            signature_base64: base64::encode(&signature[..]),
+        })
+    }
+    /// Loads a delegation, which must be signed by the pinned `root`
+    pub fn from_file(path: &Path, root: &PublicKey) -> Result<Self, DelegationErr> {
+        let delegation: Self = serde_json::from_slice(&fs::read(path)?)?;
+        delegation.verify(root)?;
+        Ok(delegation)
+    }
+
+    pub fn root_pubkey(&self) -> Result<PublicKey, DelegationErr> {
+        pubkey_from_b64(&self.fields_signed.root_pubkey_base64)
+    }
+    pub fn online_pubkey(&self) -> Result<PublicKey, DelegationErr> {
+        pubkey_from_b64(&self.fields_signed.online_pubkey_base64)
+    }
+    /// None if the delegation doesn't certify a post-quantum key
+    pub fn online_pq_pubkey(&self) -> Result<Option<Vec<u8>>, DelegationErr> {
+        match &self.fields_signed.online_pq_pubkey_base64 {
+            Some(b64) => Ok(Some(base64::decode(b64)?)),
+            None => Ok(None),
+        }
+    }
+    /// Checks the delegation is from the pinned `root`, and its signature over the delegation.
+    /// The root key in the fields is informative only: a self-signed file must not pass
+    pub fn verify(&self, root: &PublicKey) -> Result<(), DelegationErr> {
+        if self.root_pubkey()? != *root {
+            return Err(DelegationErr::OtherRoot);
+        }
+        let sig = base64::decode(&self.signature_base64)?;
+        match crypto_sign::verify(root, &self.fields_signed.hash()?, &sig) {
+            true => Ok(()),
+            false => Err(DelegationErr::BadSignature),
+        }
+    }
+    pub fn covers(&self, at: DateTime<Utc>) -> bool {
+        self.fields_signed.not_before <= at && at <= self.fields_signed.not_after
+    }
+    /// For the server's local times receipts carry. Either reading of a time repeated by DST will do
+    pub fn covers_local(&self, at: NaiveDateTime) -> bool {
+        match Local.from_local_datetime(&at) {
+            LocalResult::Single(t) => self.covers(t.with_timezone(&Utc)),
+            LocalResult::Ambiguous(a, b) => {
+                self.covers(a.with_timezone(&Utc)) || self.covers(b.with_timezone(&Utc))
+            }
+            LocalResult::None => false,
+        }
+    }
+}
+
+fn pubkey_from_b64(s: &str) -> Result<PublicKey, DelegationErr> {
+    Ok(PublicKey::from_bytes(&base64::decode(s)?).map_err(KpErr::from)?)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DelegationErr {
+    #[error("delegation signature doesn't verify against its root key")]
+    BadSignature,
+    #[error("delegation is from another root key than the pinned one")]
+    OtherRoot,
+    #[error("IO err: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("ser err: {0}")]
+    Ser(#[from] serde_json::Error),
+    #[error("invalid base64 field: {0}")]
+    B64(#[from] base64::DecodeError),
+    #[error(transparent)]
+    Kp(#[from] KpErr),
+}
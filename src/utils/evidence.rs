@@ -0,0 +1,346 @@
+//! Evidence records, after RFC 4998: receipts are periodically re-timestamped, Merkle-aggregated,
+//! under the current keys and algorithms. Each renewal also covers the previous one,
+//! so a receipt stays provable through the chain of renewals after its own key or algorithm is retired.
+use chrono::{Local, NaiveDateTime, Timelike};
+use ed25519_dalek::PublicKey;
+use schemars::JsonSchema;
+use std::convert::TryFrom;
+use std::time::Duration;
+//
+use super::crypto_sign;
+use super::crypto_sign_pq::PQ_SCHEME;
+use super::db_conn::DbConnErr;
+use super::delegation::Delegation;
+use super::merkle::{self, PathStep};
+use crate::models::{EvidenceRenewal, Lock, ModelErr, NewEvidenceRenewal, SignedData, Tx};
+use crate::routes::sign_data::SignDataResp;
+use crate::signer::SignerErr;
+
+/// How leaves are aggregated and what signed the roots
+const ALGORITHM: &str = "blake3-merkle+ed25519";
+
+/// A signed Merkle root over receipts and the previous renewal
+#[derive(Serialize, Clone, Debug, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+pub struct Renewal {
+    pub fields_signed: RenewalFields,
+    pub signature_base64: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature_pq_base64: Option<String>,
+    // Certifies the online key that signed, when signing is delegated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delegation: Option<Delegation>,
+}
+#[derive(Serialize, Clone, Debug, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+pub struct RenewalFields {
+    pub merkle_root_base64: String,
+    pub leaf_count: i64,
+    pub algorithm: String,
+    pub timestamp: NaiveDateTime,
+}
+impl RenewalFields {
+    pub(crate) fn hash(&self) -> Result<[u8; 32], EvidenceErr> {
+        let json_bytes: Vec<u8> = serde_json::to_vec(&self)?;
+        Ok(*blake3::hash(&json_bytes).as_bytes())
+    }
+}
+impl Renewal {
+    /// The leaf standing for this renewal in the next one
+    pub fn hash(&self) -> Result<[u8; 32], EvidenceErr> {
+        let sig = base64::decode(&self.signature_base64)?;
+        Ok(chain_hash(&self.fields_signed.hash()?, &sig))
+    }
+    /// Whether the renewal is signed by `trust_anchor`, or by an online key it delegated to then
+    pub fn verify(&self, trust_anchor: &PublicKey) -> bool {
+        let signing_key = match &self.delegation {
+            None => *trust_anchor,
+            Some(d) => match (d.verify(trust_anchor), d.online_pubkey()) {
+                (Ok(()), Ok(online)) if d.covers_local(self.fields_signed.timestamp) => online,
+                _ => return false,
+            },
+        };
+        match (
+            self.fields_signed.hash(),
+            base64::decode(&self.signature_base64),
+        ) {
+            (Ok(hash), Ok(sig)) => crypto_sign::verify(&signing_key, &hash, &sig),
+            _ => false,
+        }
+    }
+}
+impl TryFrom<&EvidenceRenewal> for Renewal {
+    type Error = EvidenceErr;
+    fn try_from(row: &EvidenceRenewal) -> Result<Self, EvidenceErr> {
+        let delegation = match &row.delegation_json {
+            Some(json) => Some(serde_json::from_str(json)?),
+            None => None,
+        };
+        Ok(Self {
+            fields_signed: RenewalFields {
+                merkle_root_base64: row.merkle_root_b64.clone(),
+                leaf_count: row.leaf_count,
+                algorithm: row.algorithm.clone(),
+                timestamp: row.created_at,
+            },
+            signature_base64: row.signature_b64.clone(),
+            signature_pq_base64: row.signature_pq_b64.clone(),
+            delegation,
+        })
+    }
+}
+
+/// Chains a receipt to the newest renewal: each step proves the previous link
+/// (first the receipt, then each renewal) is a leaf under the step's signed root.
+#[derive(Serialize, Debug, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+pub struct EvidenceRecord {
+    pub receipt_hash_base64: String,
+    pub renewals: Vec<EvidenceStep>,
+}
+#[derive(Serialize, Debug, JsonSchema)]
+#[cfg_attr(test, derive(Deserialize))]
+pub struct EvidenceStep {
+    pub leaf_index: usize,
+    pub merkle_path: Vec<PathStep>,
+    pub renewal: Renewal,
+}
+impl EvidenceRecord {
+    /// Client-side verification that the record covers `receipt`, and that its newest renewal
+    /// is signed under the pinned `trust_anchor`. Older renewals only had to be valid when the
+    /// next one was made.
+    pub fn verify(&self, receipt: &SignDataResp, trust_anchor: &PublicKey) -> bool {
+        let mut leaf = match receipt.hash() {
+            Ok(hash) => hash,
+            Err(_) => return false,
+        };
+        if base64::encode(&leaf) != self.receipt_hash_base64 {
+            return false;
+        }
+        for step in &self.renewals {
+            let root = merkle::root_from_path(&leaf, &step.merkle_path);
+            if root.map(|root| base64::encode(&root)).as_ref()
+                != Some(&step.renewal.fields_signed.merkle_root_base64)
+            {
+                return false;
+            }
+            leaf = match step.renewal.hash() {
+                Ok(hash) => hash,
+                Err(_) => return false,
+            };
+        }
+        match self.renewals.last() {
+            Some(newest) => newest.verify(trust_anchor),
+            None => false,
+        }
+    }
+}
+
+/// What a receipt hashes to as a Merkle leaf, and renewals alike: the signed fields and the signature
+pub fn chain_hash(fields_hash: &[u8; 32], signature: &[u8]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(fields_hash);
+    hasher.update(signature);
+    *hasher.finalize().as_bytes()
+}
+
+/// Re-timestamps the receipts issued since the last renewal, and that renewal, under the current keys.
+/// None when there was nothing to renew.
+pub fn renew(db: &mut dyn Tx) -> Result<Option<Renewal>, EvidenceErr> {
+    let previous = db.latest_renewal()?;
+    let receipts = db.unrenewed_receipts()?;
+
+    let mut leaves = vec![];
+    if let Some(previous) = &previous {
+        leaves.push(Renewal::try_from(previous)?.hash()?);
+    }
+    for receipt in &receipts {
+        leaves.push(receipt_leaf(receipt)?);
+    }
+    let root = match merkle::root(&leaves) {
+        Some(root) => root,
+        None => return Ok(None),
+    };
+
+    let signer = crate::config::signer();
+    let mut fields_signed = RenewalFields {
+        merkle_root_base64: base64::encode(&root),
+        leaf_count: leaves.len() as i64,
+        algorithm: ALGORITHM.to_string(),
+        timestamp: now_micros(),
+    };
+    if signer.pq_pubkey().is_some() {
+        fields_signed.algorithm = format!("{}+{}", ALGORITHM, PQ_SCHEME);
+    }
+    // as receipts: a renewal signed past the delegation wouldn't verify
+    let delegation_json = match crate::config::delegation() {
+        Some(d) if !d.covers_local(fields_signed.timestamp) => {
+            return Err(EvidenceErr::DelegationExpired)
+        }
+        Some(d) => Some(serde_json::to_string(d)?),
+        None => None,
+    };
+    let hash = fields_signed.hash()?;
+    let signature_base64 = base64::encode(&signer.sign(&hash)?[..]);
+    let signature_pq_base64 = signer.sign_pq(&hash)?.map(base64::encode);
+    // the previous renewal is the first leaf: its path is kept for evidence records
+    let previous_path_json = match &previous {
+        Some(_) => Some(serde_json::to_string(
+            &merkle::path(&leaves, 0).expect("index of a leaf"),
+        )?),
+        None => None,
+    };
+
+    let row = NewEvidenceRenewal {
+        created_at: fields_signed.timestamp,
+        merkle_root_b64: &fields_signed.merkle_root_base64,
+        leaf_count: fields_signed.leaf_count,
+        algorithm: &fields_signed.algorithm,
+        signature_b64: &signature_base64,
+        signature_pq_b64: signature_pq_base64.as_deref(),
+        delegation_json: delegation_json.as_deref(),
+        previous_path_json: previous_path_json.as_deref(),
+    };
+    let row = db.insert_renewal(row)?;
+    let ids: Vec<i64> = receipts.iter().map(|r| r.id).collect();
+    db.set_renewal(&ids, row.id)?;
+    Ok(Some(Renewal::try_from(&row)?))
+}
+
+/// Builds the evidence record of the receipt for `data_hash_b64`, up to the newest renewal.
+/// Only the receipt's own renewal is rehashed: later ones have their chain path stored
+pub fn evidence_record(
+    db: &mut dyn Tx,
+    data_hash_b64: &str,
+) -> Result<EvidenceRecord, EvidenceErr> {
+    let receipt = db
+        .receipt_by_data_hash(data_hash_b64)?
+        .ok_or(EvidenceErr::UnknownReceipt)?;
+    let renewal_id = match (&receipt.receipt_hash_b64, receipt.renewal_id) {
+        (None, _) => return Err(EvidenceErr::PredatesEvidence),
+        (Some(_), None) => return Err(EvidenceErr::NotRenewedYet),
+        (Some(_), Some(renewal_id)) => renewal_id,
+    };
+    let mut leaf = receipt_leaf(&receipt)?;
+    let mut previous = db.previous_renewal(renewal_id)?;
+    let mut steps = vec![];
+    for row in db.renewals_since(renewal_id)? {
+        let inconsistent = || EvidenceErr::Inconsistent { renewal_id: row.id };
+        // past the receipt's renewal, the leaf is the previous renewal: the first
+        let (leaf_index, merkle_path) = match (&row.previous_path_json, steps.is_empty()) {
+            (Some(path), false) => (0, serde_json::from_str(path)?),
+            _ => {
+                let leaves = renewal_leaves(db, &row, previous.as_ref())?;
+                let leaf_index = leaves
+                    .iter()
+                    .position(|l| *l == leaf)
+                    .ok_or_else(inconsistent)?;
+                let path = merkle::path(&leaves, leaf_index).expect("index of a leaf");
+                (leaf_index, path)
+            }
+        };
+        if merkle::root_from_path(&leaf, &merkle_path)
+            .map(|root| base64::encode(&root))
+            .as_ref()
+            != Some(&row.merkle_root_b64)
+        {
+            return Err(inconsistent());
+        }
+        let renewal = Renewal::try_from(&row)?;
+        leaf = renewal.hash()?;
+        steps.push(EvidenceStep {
+            leaf_index,
+            merkle_path,
+            renewal,
+        });
+        previous = Some(row);
+    }
+    Ok(EvidenceRecord {
+        receipt_hash_base64: receipt.receipt_hash_b64.unwrap_or_default(),
+        renewals: steps,
+    })
+}
+
+/// Renews every `period`, starting one period from now
+pub async fn renew_periodically(period: Duration) {
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    loop {
+        interval.tick().await;
+        match tokio::task::spawn_blocking(renew_now).await {
+            Ok(Ok(Some(renewal))) => info!(
+                "evidence renewal over {} leaves, root {}",
+                renewal.fields_signed.leaf_count, renewal.fields_signed.merkle_root_base64
+            ),
+            Ok(Ok(None)) => {}
+            Ok(Err(e)) => error!("evidence renewal failed: {}", e),
+            Err(e) => error!("evidence renewal task failed: {}", e),
+        }
+    }
+}
+pub fn renew_now() -> Result<Option<Renewal>, EvidenceErr> {
+    crate::config::storage().transaction(Lock::Renewals, renew)
+}
+
+// the previous renewal first, then the receipts it first covered, oldest first
+fn renewal_leaves(
+    db: &mut dyn Tx,
+    row: &EvidenceRenewal,
+    previous: Option<&EvidenceRenewal>,
+) -> Result<Vec<[u8; 32]>, EvidenceErr> {
+    let mut leaves = vec![];
+    if let Some(previous) = previous {
+        leaves.push(Renewal::try_from(previous)?.hash()?);
+    }
+    for receipt in db.receipts_renewed_by(row.id)? {
+        leaves.push(receipt_leaf(&receipt)?);
+    }
+    Ok(leaves)
+}
+fn receipt_leaf(receipt: &SignedData) -> Result<[u8; 32], EvidenceErr> {
+    receipt
+        .receipt_hash_b64
+        .as_deref()
+        .and_then(merkle::decode_hash)
+        .ok_or(EvidenceErr::Inconsistent {
+            renewal_id: receipt.renewal_id.unwrap_or_default(),
+        })
+}
+// Postgres timestamps keep microseconds: signed timestamps must survive the round trip
+fn now_micros() -> NaiveDateTime {
+    let now = Local::now().naive_local();
+    now.with_nanosecond(now.nanosecond() / 1_000 * 1_000)
+        .unwrap_or(now)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EvidenceErr {
+    #[error("db conn err: {0}")]
+    DbConn(#[from] DbConnErr),
+    #[error("model err: {0}")]
+    Model(#[from] ModelErr),
+    #[error("ser err: {0}")]
+    Ser(#[from] serde_json::Error),
+    #[error("base64 err: {0}")]
+    B64(#[from] base64::DecodeError),
+    #[error("signer err: {0}")]
+    Signer(#[from] SignerErr),
+    #[error("task err: {0}")]
+    Task(String),
+    #[error("no receipt for this data hash")]
+    UnknownReceipt,
+    #[error("receipt not covered by a renewal yet")]
+    NotRenewedYet,
+    #[error("receipt issued before evidence records")]
+    PredatesEvidence,
+    #[error("stored renewal {renewal_id} doesn't match its leaves")]
+    Inconsistent { renewal_id: i64 },
+    #[error("signing key delegation isn't valid now")]
+    DelegationExpired,
+}
+
+impl warp::reject::Reject for EvidenceErr {}
+impl From<EvidenceErr> for warp::Rejection {
+    fn from(e: EvidenceErr) -> Self {
+        warp::reject::custom(e)
+    }
+}
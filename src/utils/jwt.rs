@@ -0,0 +1,253 @@
+use jsonwebtoken::{self as jwt, Algorithm, DecodingKey, Validation};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+//
+use crate::routes::middleware::auth::PowMode;
+
+// unknown key ids trigger a JWKS refetch, at most this often
+const JWKS_REFETCH_INTERVAL: Duration = Duration::from_secs(60);
+const JWKS_FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+const LEEWAY_SECS: u64 = 30;
+
+/// The SSO issuers whose bearer tokens are accepted instead of PoW, from a JSON file like:
+/// `{"issuers": [{"issuer": "https://sso.example.com", "audience": "timestamps",
+///   "jwks_url": "https://sso.example.com/jwks.json", "tier_claim": "groups",
+///   "tiers": [{"claim_value": "staff", "pow_mode": "exempt", "daily_quota": 10000}],
+///   "default_tier": {"pow_mode": "digest", "daily_quota": 100}}]}`
+pub struct Issuers {
+    issuers: Vec<Issuer>,
+}
+impl Issuers {
+    pub fn from_file(path: &Path) -> Result<Self, JwtErr> {
+        let file: IssuersFile = serde_json::from_slice(&fs::read(path)?)?;
+        let issuers = file
+            .issuers
+            .into_iter()
+            .map(Issuer::new)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { issuers })
+    }
+
+    /// The user a token was issued to, if signed by a configured issuer for our audience
+    pub fn verify(&self, token: &str) -> Result<SsoUser, JwtErr> {
+        let header = jwt::decode_header(token)?;
+        match header.alg {
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => {}
+            Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512 => {}
+            alg => return Err(JwtErr::UnsupportedAlg(alg)),
+        }
+        // the issuer picks the keys: only read here, the signature is checked below
+        let unverified = jwt::dangerous_unsafe_decode::<Claims>(token)?.claims;
+        let issuer = self
+            .issuers
+            .iter()
+            .find(|i| Some(&i.config.issuer) == unverified.iss.as_ref())
+            .ok_or(JwtErr::UnknownIssuer)?;
+        let jwk = issuer.key(header.kid.as_deref())?;
+
+        let mut validation = Validation {
+            leeway: LEEWAY_SECS,
+            validate_nbf: true,
+            iss: Some(issuer.config.issuer.clone()),
+            algorithms: vec![header.alg],
+            ..Validation::default()
+        };
+        validation.set_audience(&[&issuer.config.audience]);
+        let key = DecodingKey::from_rsa_components(&jwk.n, &jwk.e);
+        let claims = jwt::decode::<Claims>(token, &key, &validation)?.claims;
+
+        Ok(SsoUser {
+            issuer: issuer.config.issuer.clone(),
+            subject: claims.sub.clone().ok_or(JwtErr::NoSubject)?,
+            limits: issuer.config.tier(&claims).ok_or(JwtErr::NoTier)?,
+        })
+    }
+}
+
+/// Someone authenticated by a configured SSO issuer
+#[derive(Debug, Clone)]
+pub struct SsoUser {
+    pub issuer: String,
+    pub subject: String,
+    pub limits: TierLimits,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct TierLimits {
+    pub pow_mode: PowMode,
+    pub daily_quota: i64,
+}
+
+#[derive(Deserialize)]
+struct IssuersFile {
+    issuers: Vec<IssuerConfig>,
+}
+#[derive(Deserialize, Clone, Debug)]
+struct IssuerConfig {
+    issuer: String,
+    audience: String,
+    // exactly one of the two
+    jwks_path: Option<PathBuf>,
+    jwks_url: Option<String>,
+    // the claim, a string or array of strings, that tiers are matched against
+    tier_claim: Option<String>,
+    #[serde(default)]
+    tiers: Vec<Tier>,
+    // for users not matching any tier. Without it, they are refused.
+    default_tier: Option<TierLimits>,
+}
+impl IssuerConfig {
+    // the first tier in the config matching the claim's values wins
+    fn tier(&self, claims: &Claims) -> Option<TierLimits> {
+        let values: Vec<&str> = match self.tier_claim.as_ref().and_then(|c| claims.other.get(c)) {
+            Some(Value::String(value)) => vec![value.as_str()],
+            Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        self.tiers
+            .iter()
+            .find(|tier| values.contains(&tier.claim_value.as_str()))
+            .map(|tier| tier.limits)
+            .or(self.default_tier)
+    }
+}
+#[derive(Deserialize, Clone, Debug)]
+struct Tier {
+    claim_value: String,
+    #[serde(flatten)]
+    limits: TierLimits,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    iss: Option<String>,
+    sub: Option<String>,
+    #[serde(flatten)]
+    other: HashMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+#[derive(Deserialize, Clone)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    // base64url RSA modulus and exponent
+    #[serde(default)]
+    n: String,
+    #[serde(default)]
+    e: String,
+}
+
+struct Issuer {
+    config: IssuerConfig,
+    keys: Mutex<KeyCache>,
+}
+struct KeyCache {
+    keys: Vec<Jwk>,
+    // last fetch from the issuer's URL, None until one succeeds
+    fetched_at: Option<Instant>,
+}
+impl Issuer {
+    // keys from a URL may be fetched later: the SSO being down mustn't stop the server
+    fn new(config: IssuerConfig) -> Result<Self, JwtErr> {
+        let (keys, fetched_at) = match (&config.jwks_path, &config.jwks_url) {
+            (Some(path), None) => (rsa_keys(serde_json::from_slice(&fs::read(path)?)?), None),
+            (None, Some(url)) => match fetch_jwks(url) {
+                Ok(keys) => (keys, Some(Instant::now())),
+                Err(e) => {
+                    warn!(
+                        "failed fetching JWKS for {}, retrying on use: {}",
+                        config.issuer, e
+                    );
+                    (vec![], None)
+                }
+            },
+            _ => {
+                return Err(JwtErr::BadConfig(format!(
+                    "issuer {} needs one of jwks_path or jwks_url",
+                    config.issuer
+                )))
+            }
+        };
+        Ok(Self {
+            config,
+            keys: Mutex::new(KeyCache { keys, fetched_at }),
+        })
+    }
+    // refetches keys from the URL once an unknown key id shows up, as issuers rotate keys.
+    // Not under the lock: a slow issuer mustn't hold up tokens whose keys are known
+    fn key(&self, kid: Option<&str>) -> Result<Jwk, JwtErr> {
+        let url = {
+            let mut cache = self.keys.lock().map_err(|_| JwtErr::Poisoned)?;
+            if let Some(jwk) = find_key(&cache.keys, kid) {
+                return Ok(jwk);
+            }
+            match (&self.config.jwks_url, cache.fetched_at) {
+                (Some(url), at) if at.map_or(true, |at| at.elapsed() >= JWKS_REFETCH_INTERVAL) => {
+                    // claimed before fetching, so concurrent misses don't all fetch
+                    cache.fetched_at = Some(Instant::now());
+                    url
+                }
+                _ => return Err(JwtErr::UnknownKey),
+            }
+        };
+        let keys = fetch_jwks(url)?;
+        let jwk = find_key(&keys, kid);
+        self.keys.lock().map_err(|_| JwtErr::Poisoned)?.keys = keys;
+        jwk.ok_or(JwtErr::UnknownKey)
+    }
+}
+// without a key id, only an issuer with a single key can be used
+fn find_key(keys: &[Jwk], kid: Option<&str>) -> Option<Jwk> {
+    match kid {
+        Some(kid) => keys.iter().find(|k| k.kid.as_deref() == Some(kid)).cloned(),
+        None if keys.len() == 1 => keys.first().cloned(),
+        None => None,
+    }
+}
+fn fetch_jwks(url: &str) -> Result<Vec<Jwk>, JwtErr> {
+    let resp = ureq::get(url).timeout(JWKS_FETCH_TIMEOUT).call();
+    if !resp.ok() {
+        return Err(JwtErr::Fetch(resp.status_line().to_string()));
+    }
+    let jwks = resp
+        .into_json_deserialize::<Jwks>()
+        .map_err(|e| JwtErr::Fetch(e.to_string()))?;
+    Ok(rsa_keys(jwks))
+}
+fn rsa_keys(jwks: Jwks) -> Vec<Jwk> {
+    jwks.keys.into_iter().filter(|k| k.kty == "RSA").collect()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum JwtErr {
+    #[error("IO err: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("json err: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("bad issuers config: {0}")]
+    BadConfig(String),
+    #[error("failed fetching JWKS: {0}")]
+    Fetch(String),
+    #[error("invalid token: {0}")]
+    Token(#[from] jwt::errors::Error),
+    #[error("unsupported token algorithm {0:?}")]
+    UnsupportedAlg(Algorithm),
+    #[error("token from an unknown issuer")]
+    UnknownIssuer,
+    #[error("token signed by an unknown key")]
+    UnknownKey,
+    #[error("token without subject")]
+    NoSubject,
+    #[error("token matches no tier")]
+    NoTier,
+    #[error("poisoned lock")]
+    Poisoned,
+}
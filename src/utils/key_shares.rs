@@ -0,0 +1,138 @@
+use sharks::{Share, Sharks};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use zeroize::Zeroizing;
+//
+use super::crypto_sign::{AsBytes, KeyPair, KpErr};
+
+// A share is printed as `CTSS1-<fingerprint>-<threshold>-<share>-<checksum>`, all uppercase hex
+// so it fits QR alphanumeric mode and can be read out or typed back without ambiguity.
+// fingerprint: of the pubkey, to detect shares of different keys. checksum: of the rest, to detect typos.
+const PREFIX: &str = "CTSS1";
+const SECRET_LEN: usize = 32;
+
+/// Splits the signing key's secret into `n` shares, any `k` of which reconstruct it
+pub fn split(keypair: &KeyPair, k: u8, n: u8) -> Result<Vec<String>, SharesErr> {
+    if k < 2 || n < k {
+        return Err(SharesErr::InvalidThreshold { k, n });
+    }
+    let keypair_bytes = Zeroizing::new(keypair.to_bytes());
+    let fingerprint = fingerprint(keypair);
+    let shares = Sharks(k)
+        .dealer(&keypair_bytes[..SECRET_LEN])
+        .take(n as usize)
+        .map(|share| {
+            let share_bytes = Zeroizing::new(Vec::from(&share));
+            let body = format!("{}-{}-{}-{}", PREFIX, fingerprint, k, to_hex(&share_bytes));
+            format!("{}-{}", body, checksum(&body))
+        })
+        .collect();
+    Ok(shares)
+}
+
+/// Reconstructs the signing key from at least `k` shares, checking each share's integrity first
+pub fn combine(share_strs: &[String]) -> Result<KeyPair, SharesErr> {
+    let mut fingerprints = HashSet::new();
+    let mut thresholds = HashSet::new();
+    let mut shares = Vec::new();
+    for (i, share_str) in share_strs.iter().enumerate() {
+        let (fingerprint, k, share) = parse(i, share_str.trim())?;
+        fingerprints.insert(fingerprint);
+        thresholds.insert(k);
+        shares.push(share);
+    }
+    if fingerprints.len() > 1 || thresholds.len() > 1 {
+        return Err(SharesErr::MixedKeys);
+    }
+    let (fingerprint, k) = match (fingerprints.iter().next(), thresholds.iter().next()) {
+        (Some(fingerprint), Some(k)) => (fingerprint.clone(), *k),
+        _ => return Err(SharesErr::NotEnoughShares { needed: 2, got: 0 }),
+    };
+    let distinct = shares.iter().map(|s| s.x.0).collect::<HashSet<u8>>().len();
+    if distinct < k as usize {
+        return Err(SharesErr::NotEnoughShares {
+            needed: k,
+            got: distinct,
+        });
+    }
+
+    let secret = Zeroizing::new(
+        Sharks(k)
+            .recover(&shares)
+            .map_err(|e| SharesErr::Recover(e.to_string()))?,
+    );
+    let keypair = KeyPair::from_secret_bytes(&secret)?;
+    if self::fingerprint(&keypair) != fingerprint {
+        return Err(SharesErr::FingerprintMismatch);
+    }
+    Ok(keypair)
+}
+
+fn parse(i: usize, share_str: &str) -> Result<(String, u8, Share), SharesErr> {
+    let malformed = |reason: &str| SharesErr::Malformed {
+        share: i + 1,
+        reason: reason.into(),
+    };
+    let (body, check) = match share_str.rfind('-') {
+        Some(pos) => (&share_str[..pos], &share_str[pos + 1..]),
+        None => return Err(malformed("missing checksum")),
+    };
+    if checksum(body) != check {
+        return Err(SharesErr::BadChecksum { share: i + 1 });
+    }
+    let parts: Vec<&str> = body.split('-').collect();
+    match parts.as_slice() {
+        [PREFIX, fingerprint, k, share_hex] => {
+            let k = k
+                .parse::<u8>()
+                .map_err(|_| malformed("invalid threshold"))?;
+            let share_bytes =
+                Zeroizing::new(from_hex(share_hex).ok_or_else(|| malformed("invalid hex"))?);
+            if share_bytes.len() != 1 + SECRET_LEN {
+                return Err(malformed("wrong length"));
+            }
+            let share = Share::try_from(&share_bytes[..]).map_err(malformed)?;
+            Ok((fingerprint.to_string(), k, share))
+        }
+        _ => Err(malformed("unexpected format")),
+    }
+}
+
+fn fingerprint(keypair: &KeyPair) -> String {
+    to_hex(&blake3::hash(keypair.pubkey().as_bytes()).as_bytes()[..4])
+}
+fn checksum(body: &str) -> String {
+    to_hex(&blake3::hash(body.as_bytes()).as_bytes()[..4])
+}
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SharesErr {
+    #[error("invalid threshold: need 2 <= k <= n <= 255, got k={k}, n={n}")]
+    InvalidThreshold { k: u8, n: u8 },
+    #[error("share #{share} is malformed: {reason}")]
+    Malformed { share: usize, reason: String },
+    #[error("share #{share} failed its checksum: it was mistyped or damaged")]
+    BadChecksum { share: usize },
+    #[error("shares come from different keys or splits")]
+    MixedKeys,
+    #[error("not enough distinct shares: need {needed}, got {got}")]
+    NotEnoughShares { needed: u8, got: usize },
+    #[error("failed recovering secret: {0}")]
+    Recover(String),
+    #[error("recovered key doesn't match the shares' fingerprint")]
+    FingerprintMismatch,
+    #[error(transparent)]
+    Kp(#[from] KpErr),
+}
@@ -0,0 +1,98 @@
+//! Structured logs: one span per request, carrying its id, route and outcome.
+//! `log` macros are bridged, so their records land in the request's span too.
+use tracing::field::Empty;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, EnvFilter};
+//
+use crate::utils::metrics;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    // One JSON object per line
+    Json,
+    // Human-readable, for dev
+    Text,
+}
+
+/// Keep until exit: spans still buffered for export are flushed when it's dropped
+pub struct Guard {
+    #[cfg(feature = "otlp")]
+    _otlp: Option<opentelemetry_otlp::Uninstall>,
+}
+
+pub fn init() -> Result<Guard, anyhow::Error> {
+    let filter = EnvFilter::try_new(crate::config::rust_log())?;
+    let (json, text) = match crate::config::log_format() {
+        LogFormat::Json => (Some(fmt::layer().json()), None),
+        LogFormat::Text => (None, Some(fmt::layer())),
+    };
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(json)
+        .with(text);
+
+    #[cfg(feature = "otlp")]
+    let (otlp, uninstall) = match crate::config::otlp_endpoint() {
+        Some(endpoint) => {
+            let (tracer, uninstall) = opentelemetry_otlp::new_pipeline()
+                .with_endpoint(endpoint)
+                .with_service_name(env!("CARGO_PKG_NAME"))
+                .install()?;
+            (
+                Some(tracing_opentelemetry::layer().with_tracer(tracer)),
+                Some(uninstall),
+            )
+        }
+        None => (None, None),
+    };
+    #[cfg(feature = "otlp")]
+    let registry = registry.with(otlp);
+
+    registry.try_init()?;
+    Ok(Guard {
+        #[cfg(feature = "otlp")]
+        _otlp: uninstall,
+    })
+}
+
+/// For `warp::trace`: fields left empty are recorded as the request goes
+pub fn request_span(info: warp::trace::Info) -> tracing::Span {
+    tracing::info_span!(
+        "request",
+        request_id = Empty,
+        method = %info.method(),
+        route = metrics::route_label(info.path()),
+        status = Empty,
+        pow_ms = Empty,
+        db_ms = Empty,
+    )
+}
+
+/// For `warp::log::custom`, within the request's span
+pub fn request_completed(info: warp::log::Info) {
+    metrics::observe_request(&info);
+    let span = tracing::Span::current();
+    span.record("status", &info.status().as_u16());
+    match info.status().is_server_error() {
+        true => warn!(
+            "{} {} {} in {}ms",
+            info.method(),
+            info.path(),
+            info.status().as_u16(),
+            info.elapsed().as_millis()
+        ),
+        false => info!(
+            "{} {} {} in {}ms",
+            info.method(),
+            info.path(),
+            info.status().as_u16(),
+            info.elapsed().as_millis()
+        ),
+    }
+}
+
+/// Records a duration on the current request's span, as `pow_ms` or `db_ms`
+pub fn record_ms(field: &str, duration: std::time::Duration) {
+    tracing::Span::current().record(field, &(duration.as_millis() as u64));
+}
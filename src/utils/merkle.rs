@@ -0,0 +1,94 @@
+//! Binary Merkle trees over 32-byte leaves, with Blake3.
+//! Leaves and inner nodes are domain-separated, and a node left without a sibling
+//! is promoted to the next level as is.
+use schemars::JsonSchema;
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// One level of an inclusion proof, from the leaf up
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PathStep {
+    pub sibling_base64: String,
+    pub sibling_is_left: bool,
+}
+
+pub fn root(leaves: &[[u8; 32]]) -> Option<[u8; 32]> {
+    let mut level: Vec<[u8; 32]> = leaves.iter().map(leaf_hash).collect();
+    if level.is_empty() {
+        return None;
+    }
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    Some(level[0])
+}
+
+/// Inclusion proof of `leaves[index]`
+pub fn path(leaves: &[[u8; 32]], index: usize) -> Option<Vec<PathStep>> {
+    let mut level: Vec<[u8; 32]> = leaves.iter().map(leaf_hash).collect();
+    if index >= level.len() {
+        return None;
+    }
+    let mut index = index;
+    let mut steps = vec![];
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if let Some(hash) = level.get(sibling) {
+            steps.push(PathStep {
+                sibling_base64: base64::encode(hash),
+                sibling_is_left: sibling < index,
+            });
+        }
+        level = next_level(&level);
+        index /= 2;
+    }
+    Some(steps)
+}
+
+/// The root `path` leads to from `leaf`. None if the path isn't well-formed.
+pub fn root_from_path(leaf: &[u8; 32], path: &[PathStep]) -> Option<[u8; 32]> {
+    let mut hash = leaf_hash(leaf);
+    for step in path {
+        let sibling = decode_hash(&step.sibling_base64)?;
+        hash = match step.sibling_is_left {
+            true => node_hash(&sibling, &hash),
+            false => node_hash(&hash, &sibling),
+        };
+    }
+    Some(hash)
+}
+
+pub fn decode_hash(hash_b64: &str) -> Option<[u8; 32]> {
+    let bytes = base64::decode(hash_b64).ok()?;
+    if bytes.len() != 32 {
+        return None;
+    }
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&bytes);
+    Some(hash)
+}
+
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!("chunks of 2"),
+        })
+        .collect()
+}
+fn leaf_hash(leaf: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[LEAF_PREFIX]);
+    hasher.update(leaf);
+    *hasher.finalize().as_bytes()
+}
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    *hasher.finalize().as_bytes()
+}
@@ -0,0 +1,153 @@
+//! Prometheus metrics, served by `GET /metrics`
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    TextEncoder,
+};
+
+lazy_static::lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::register();
+}
+
+pub struct Metrics {
+    pub receipts_issued: IntCounter,
+    // by outcome: ok, rejected
+    pub pow_verifications: IntCounterVec,
+    pub pow_duration: Histogram,
+    // by duplicate policy
+    pub duplicates: IntCounterVec,
+    // by SignDataErr variant
+    pub sign_data_errors: IntCounterVec,
+    // by route, method and status
+    pub request_duration: HistogramVec,
+    pub db_pool_connections: IntGauge,
+    pub db_pool_idle_connections: IntGauge,
+}
+impl Metrics {
+    fn register() -> Self {
+        Self {
+            receipts_issued: register(IntCounter::new(
+                "receipts_issued_total",
+                "Receipts signed, single or in batches",
+            )),
+            pow_verifications: register(IntCounterVec::new(
+                Opts::new("pow_verifications_total", "PoW proofs verified, by outcome"),
+                &["outcome"],
+            )),
+            pow_duration: register(Histogram::with_opts(
+                HistogramOpts::new(
+                    "pow_verification_duration_seconds",
+                    "Time spent verifying PoW proofs",
+                )
+                .buckets(vec![0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0]),
+            )),
+            duplicates: register(IntCounterVec::new(
+                Opts::new(
+                    "duplicates_total",
+                    "Data submitted again after being signed, by duplicate policy",
+                ),
+                &["policy"],
+            )),
+            sign_data_errors: register(IntCounterVec::new(
+                Opts::new(
+                    "sign_data_errors_total",
+                    "Failed signing requests, by error",
+                ),
+                &["error"],
+            )),
+            request_duration: register(HistogramVec::new(
+                HistogramOpts::new("http_request_duration_seconds", "HTTP request latency"),
+                &["route", "method", "status"],
+            )),
+            db_pool_connections: register(IntGauge::new(
+                "db_pool_connections",
+                "Connections open in the Postgres pool",
+            )),
+            db_pool_idle_connections: register(IntGauge::new(
+                "db_pool_idle_connections",
+                "Idle connections in the Postgres pool",
+            )),
+        }
+    }
+}
+
+fn register<C: Collector + Clone + 'static>(collector: prometheus::Result<C>) -> C {
+    let collector = collector.expect("invalid metric");
+    prometheus::register(Box::new(collector.clone())).expect("metric registered twice");
+    collector
+}
+
+pub fn pow_verified(ok: bool, duration: std::time::Duration) {
+    let outcome = if ok { "ok" } else { "rejected" };
+    METRICS
+        .pow_verifications
+        .with_label_values(&[outcome])
+        .inc();
+    METRICS.pow_duration.observe(duration.as_secs_f64());
+}
+
+/// The latency of each request, by route
+pub fn observe_request(info: &warp::log::Info) {
+    METRICS
+        .request_duration
+        .with_label_values(&[
+            route_label(info.path()),
+            info.method().as_str(),
+            info.status().as_str(),
+        ])
+        .observe(info.elapsed().as_secs_f64());
+}
+
+// path parameters are replaced, so each route is one series
+pub fn route_label(path: &str) -> &'static str {
+    match path.trim_matches('/').split('/').collect::<Vec<&str>>()[..] {
+        [""] => "/",
+        ["health"] => "/health",
+        ["health", "live"] => "/health/live",
+        ["health", "ready"] => "/health/ready",
+        ["pubkey"] => "/pubkey",
+        ["keys", _, "status"] => "/keys/{key_id}/status",
+        ["sign_data"] => "/sign_data",
+        ["sign_data", "batch"] => "/sign_data/batch",
+        ["tokens", "issue"] => "/tokens/issue",
+        ["tokens", "pubkey"] => "/tokens/pubkey",
+        ["blind", "sign"] => "/blind/sign",
+        ["blind", "key"] => "/blind/key",
+        ["evidence_record"] => "/evidence_record",
+        ["metrics"] => "/metrics",
+        ["openapi.json"] => "/openapi.json",
+        ["docs"] => "/docs",
+        _ => "other",
+    }
+}
+
+/// The text exposition format, with the pool's state as of now
+pub fn render() -> Result<String, MetricsErr> {
+    if let Some(state) = crate::config::storage().pool_state() {
+        METRICS
+            .db_pool_connections
+            .set(i64::from(state.connections));
+        METRICS
+            .db_pool_idle_connections
+            .set(i64::from(state.idle_connections));
+    }
+
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum MetricsErr {
+    #[error("prometheus err: {0}")]
+    Prometheus(#[from] prometheus::Error),
+    #[error("utf8 err: {0}")]
+    Utf8(#[from] std::string::FromUtf8Error),
+}
+
+impl warp::reject::Reject for MetricsErr {}
+impl From<MetricsErr> for warp::Rejection {
+    fn from(e: MetricsErr) -> Self {
+        warp::reject::custom(e)
+    }
+}
@@ -1,2 +1,14 @@
+pub mod blind_sign;
+pub mod clock;
 pub mod crypto_sign;
+pub mod crypto_sign_pq;
 pub mod db_conn;
+pub mod delegation;
+pub mod evidence;
+pub mod jwt;
+pub mod key_shares;
+pub mod logging;
+pub mod merkle;
+pub mod metrics;
+pub mod revocation;
+pub mod tokens;
@@ -0,0 +1,176 @@
+use chrono::{Local, NaiveDateTime};
+use ed25519_dalek::PublicKey;
+use schemars::JsonSchema;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+//
+use super::crypto_sign::{self, KeyPair, KpErr};
+
+// revocations are files dropped in `revocations_dir`: re-read them at most this often
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+lazy_static::lazy_static! {
+    static ref REVOCATIONS: RwLock<(Instant, Arc<RevocationList>)> = RwLock::new((
+        Instant::now(),
+        Arc::new(load_current().expect("failed loading revocations")),
+    ));
+}
+
+/// A signed statement that a key must no longer be trusted.
+/// Signed either by the revoked key itself, or by the trust anchor (root key).
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct Revocation {
+    pub fields_signed: RevocationFields,
+    pub signature_base64: String,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct RevocationFields {
+    pub key_id: String,
+    pub revoked_at: NaiveDateTime,
+    // Set if the key leaked: receipts timestamped from then on are untrusted. Earlier ones stay valid.
+    pub compromised_since: Option<NaiveDateTime>,
+    pub reason: String,
+    pub issuer_pubkey_base64: String,
+}
+impl RevocationFields {
+    fn hash(&self) -> Result<[u8; 32], RevocationErr> {
+        let json_bytes: Vec<u8> = serde_json::to_vec(&self)?;
+        Ok(*blake3::hash(&json_bytes).as_bytes())
+    }
+}
+
+impl Revocation {
+    pub fn issue(
+        issuer: &KeyPair,
+        revoked: &PublicKey,
+        compromised_since: Option<NaiveDateTime>,
+        reason: &str,
+    ) -> Result<Self, RevocationErr> {
+        let fields_signed = RevocationFields {
+            key_id: crypto_sign::key_id(revoked),
+            revoked_at: Local::now().naive_local(),
+            compromised_since,
+            reason: reason.into(),
+            issuer_pubkey_base64: base64::encode(issuer.pubkey().as_bytes()),
+        };
+        let signature = issuer.sign(&fields_signed.hash()?);
+        Ok(Self {
+            fields_signed,
+            signature_base64: base64::encode(&signature[..]),
+        })
+    }
+    /// Checks the signature, and that the issuer may revoke this key
+    pub fn verify(&self, trust_anchor: &PublicKey) -> Result<(), RevocationErr> {
+        let issuer =
+            PublicKey::from_bytes(&base64::decode(&self.fields_signed.issuer_pubkey_base64)?)
+                .map_err(KpErr::from)?;
+        let issuer_id = crypto_sign::key_id(&issuer);
+        if issuer != *trust_anchor && issuer_id != self.fields_signed.key_id {
+            return Err(RevocationErr::UnauthorizedIssuer);
+        }
+        let sig = base64::decode(&self.signature_base64)?;
+        match crypto_sign::verify(&issuer, &self.fields_signed.hash()?, &sig) {
+            true => Ok(()),
+            false => Err(RevocationErr::BadSignature),
+        }
+    }
+    /// Whether a signature from the revoked key, made at `timestamp`, can still be trusted
+    pub fn trusts(&self, timestamp: NaiveDateTime) -> bool {
+        let untrusted_since = self
+            .fields_signed
+            .compromised_since
+            .unwrap_or(self.fields_signed.revoked_at);
+        timestamp < untrusted_since
+    }
+}
+
+/// All valid revocations, by revoked key id
+#[derive(Default)]
+pub struct RevocationList {
+    by_key_id: HashMap<String, Revocation>,
+}
+impl RevocationList {
+    /// Loads `<key_id>.json` revocations from `dir`, skipping (and reporting) unreadable or invalid ones.
+    /// Only an unreadable `dir` fails the load
+    pub fn load(dir: &Path, trust_anchor: &PublicKey) -> Result<Self, RevocationErr> {
+        let mut by_key_id = HashMap::new();
+        if !dir.exists() {
+            return Ok(Self { by_key_id });
+        }
+        for entry in fs::read_dir(dir)? {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(e) => {
+                    warn!("ignoring a revocation in {}: {}", dir.display(), e);
+                    continue;
+                }
+            };
+            if path.extension().map_or(true, |ext| ext != "json") {
+                continue;
+            }
+            let revocation = fs::read(&path)
+                .map_err(RevocationErr::from)
+                .and_then(|bytes| Ok(serde_json::from_slice::<Revocation>(&bytes)?))
+                .and_then(|r| r.verify(trust_anchor).map(|_| r));
+            match revocation {
+                Ok(r) => {
+                    by_key_id.insert(r.fields_signed.key_id.clone(), r);
+                }
+                Err(e) => warn!("ignoring revocation at {}: {}", path.display(), e),
+            }
+        }
+        Ok(Self { by_key_id })
+    }
+    pub fn get(&self, key_id: &str) -> Option<&Revocation> {
+        self.by_key_id.get(key_id)
+    }
+    pub fn is_revoked(&self, pubkey: &PublicKey) -> bool {
+        self.get(&crypto_sign::key_id(pubkey)).is_some()
+    }
+}
+
+/// The revocations currently in `revocations_dir`, reloaded periodically
+pub fn current() -> Arc<RevocationList> {
+    {
+        let cached = REVOCATIONS.read().expect("revocations lock poisoned");
+        if cached.0.elapsed() < RELOAD_INTERVAL {
+            return cached.1.clone();
+        }
+    }
+    let mut cached = REVOCATIONS.write().expect("revocations lock poisoned");
+    match load_current() {
+        Ok(fresh) => *cached = (Instant::now(), Arc::new(fresh)),
+        // keep the last known revocations rather than failing open
+        Err(e) => {
+            error!("failed reloading revocations: {}", e);
+            cached.0 = Instant::now();
+        }
+    }
+    cached.1.clone()
+}
+
+fn load_current() -> Result<RevocationList, RevocationErr> {
+    RevocationList::load(
+        crate::config::revocations_dir(),
+        &crate::config::trust_anchor(),
+    )
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RevocationErr {
+    #[error("revocation signature doesn't verify")]
+    BadSignature,
+    #[error("revocation issuer is neither the revoked key nor the root key")]
+    UnauthorizedIssuer,
+    #[error("IO err: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("ser err: {0}")]
+    Ser(#[from] serde_json::Error),
+    #[error("invalid base64 field: {0}")]
+    B64(#[from] base64::DecodeError),
+    #[error(transparent)]
+    Kp(#[from] KpErr),
+}
@@ -0,0 +1,261 @@
+//! Privacy Pass style tokens: a VOPRF over ristretto255.
+//! Clients get blinded token nonces evaluated with the server's key, with a proof that the same
+//! key was used for everyone, then unblind them. Redeeming a token reveals its nonce, which the
+//! server never saw at issuance: redemptions can't be linked to issuances, nor to each other.
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT as G;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha512;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use zeroize::{Zeroize, Zeroizing};
+//
+use super::crypto_sign::KeyMode;
+
+pub const MAX_BATCH: usize = 64;
+const HASH_TO_POINT_CTX: &[u8] = b"crypto-timestamp-api token v1";
+const DLEQ_CTX: &[u8] = b"crypto-timestamp-api token dleq v1";
+const BATCH_CTX: &[u8] = b"crypto-timestamp-api token batch v1";
+const MAC_CTX: &str = "crypto-timestamp-api token mac v1";
+
+/// The server's VOPRF key
+pub struct TokenKey {
+    k: Scalar,
+}
+impl TokenKey {
+    pub fn generate() -> Self {
+        Self {
+            k: Scalar::random(&mut OsRng),
+        }
+    }
+    pub fn pubkey(&self) -> RistrettoPoint {
+        self.k * G
+    }
+
+    /// Evaluates blinded elements, proving they were all evaluated with the key of `pubkey()`
+    pub fn issue(&self, blinded: &[RistrettoPoint]) -> (Vec<RistrettoPoint>, DleqProof) {
+        let evaluated: Vec<RistrettoPoint> = blinded.iter().map(|m| self.k * m).collect();
+        let (m, z) = combine(&self.pubkey(), blinded, &evaluated);
+        let nonce = Scalar::random(&mut OsRng);
+        let (a, b) = (nonce * G, nonce * m);
+        let challenge = dleq_challenge(&self.pubkey(), &m, &z, &a, &b);
+        let proof = DleqProof {
+            challenge,
+            response: nonce - challenge * self.k,
+        };
+        (evaluated, proof)
+    }
+
+    /// Whether `mac` proves knowledge of the token for `nonce`, over `message`
+    pub fn verify_redemption(&self, nonce: &[u8; 32], mac: &[u8; 32], message: &[u8]) -> bool {
+        let token = self.k * hash_to_point(nonce);
+        redemption_mac(&token, message) == blake3::Hash::from(*mac)
+    }
+
+    /// Loads the key at `keyfile`, as signing keys are
+    pub fn load(keyfile: &Path, mode: KeyMode) -> Result<Self, TokenErr> {
+        match fs::read_to_string(keyfile).map(Zeroizing::new) {
+            // detail dropped: base64 decode errors echo bytes of the key material
+            Ok(content) => {
+                let bytes = Zeroizing::new(
+                    base64::decode(content.trim()).map_err(|_| TokenErr::BadKeyFile)?,
+                );
+                Ok(Self {
+                    k: scalar_from_slice(&bytes).ok_or(TokenErr::BadKeyFile)?,
+                })
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound && mode == KeyMode::GenerateIfMissing => {
+                warn!("no token key file at {}, generating one", keyfile.display());
+                let key = Self::generate();
+                key.to_file(keyfile)?;
+                Ok(key)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+    pub fn to_file(&self, keyfile: &Path) -> Result<(), TokenErr> {
+        if let Some(dir) = keyfile.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = Zeroizing::new(base64::encode(self.k.as_bytes()));
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(keyfile)?
+            .write_all(content.as_bytes())?;
+        Ok(())
+    }
+}
+impl Drop for TokenKey {
+    fn drop(&mut self) {
+        self.k.zeroize();
+    }
+}
+
+/// Proof that `log_G(pubkey) == log_M(Z)` for the batch's combined elements
+#[derive(Debug, Clone, Copy)]
+pub struct DleqProof {
+    pub challenge: Scalar,
+    pub response: Scalar,
+}
+impl DleqProof {
+    pub fn verify(
+        &self,
+        pubkey: &RistrettoPoint,
+        blinded: &[RistrettoPoint],
+        evaluated: &[RistrettoPoint],
+    ) -> bool {
+        if blinded.len() != evaluated.len() {
+            return false;
+        }
+        let (m, z) = combine(pubkey, blinded, evaluated);
+        let a = self.response * G + self.challenge * pubkey;
+        let b = self.response * m + self.challenge * z;
+        dleq_challenge(pubkey, &m, &z, &a, &b) == self.challenge
+    }
+}
+
+/// Client side: a token nonce waiting for the server's evaluation
+pub struct BlindedToken {
+    nonce: [u8; 32],
+    blind: Scalar,
+    pub blinded: RistrettoPoint,
+}
+impl BlindedToken {
+    pub fn generate() -> Self {
+        let mut nonce = [0u8; 32];
+        OsRng.fill_bytes(&mut nonce);
+        let blind = Scalar::random(&mut OsRng);
+        let blinded = blind * hash_to_point(&nonce);
+        Self {
+            nonce,
+            blind,
+            blinded,
+        }
+    }
+    /// Checks the server's proof for the whole batch, then unblinds each evaluation
+    pub fn unblind_batch(
+        blinded: Vec<BlindedToken>,
+        evaluated: &[RistrettoPoint],
+        proof: &DleqProof,
+        pubkey: &RistrettoPoint,
+    ) -> Result<Vec<Token>, TokenErr> {
+        let points: Vec<RistrettoPoint> = blinded.iter().map(|b| b.blinded).collect();
+        if !proof.verify(pubkey, &points, evaluated) {
+            return Err(TokenErr::BadProof);
+        }
+        Ok(blinded
+            .into_iter()
+            .zip(evaluated)
+            .map(|(b, z)| Token {
+                nonce: b.nonce,
+                token: b.blind.invert() * z,
+            })
+            .collect())
+    }
+}
+
+/// Client side: a token, redeemable once
+pub struct Token {
+    pub nonce: [u8; 32],
+    token: RistrettoPoint,
+}
+impl Token {
+    /// The `Authorization` header redeeming this token for `message`, e.g. the data's hash:
+    /// a token seen in transit can't be redeemed for other data
+    pub fn authorization(&self, message: &[u8]) -> String {
+        let mac = redemption_mac(&self.token, message);
+        format!(
+            "PrivateToken {}.{}",
+            base64::encode(&self.nonce),
+            base64::encode(mac.as_bytes())
+        )
+    }
+}
+
+fn hash_to_point(nonce: &[u8]) -> RistrettoPoint {
+    RistrettoPoint::hash_from_bytes::<Sha512>(&[HASH_TO_POINT_CTX, nonce].concat())
+}
+fn redemption_mac(token: &RistrettoPoint, message: &[u8]) -> blake3::Hash {
+    let key = blake3::derive_key(MAC_CTX, token.compress().as_bytes());
+    blake3::keyed_hash(&key, message)
+}
+fn dleq_challenge(
+    pubkey: &RistrettoPoint,
+    m: &RistrettoPoint,
+    z: &RistrettoPoint,
+    a: &RistrettoPoint,
+    b: &RistrettoPoint,
+) -> Scalar {
+    let mut bytes = DLEQ_CTX.to_vec();
+    for point in &[G, *pubkey, *m, *z, *a, *b] {
+        bytes.extend_from_slice(point.compress().as_bytes());
+    }
+    Scalar::hash_from_bytes::<Sha512>(&bytes)
+}
+// random linear combination of the batch, so one proof covers it all
+fn combine(
+    pubkey: &RistrettoPoint,
+    blinded: &[RistrettoPoint],
+    evaluated: &[RistrettoPoint],
+) -> (RistrettoPoint, RistrettoPoint) {
+    let mut seed = BATCH_CTX.to_vec();
+    seed.extend_from_slice(pubkey.compress().as_bytes());
+    for point in blinded.iter().chain(evaluated) {
+        seed.extend_from_slice(point.compress().as_bytes());
+    }
+    let seed = blake3::hash(&seed);
+    let mut m = RistrettoPoint::default();
+    let mut z = RistrettoPoint::default();
+    for (i, (mi, zi)) in blinded.iter().zip(evaluated).enumerate() {
+        let c = Scalar::hash_from_bytes::<Sha512>(
+            &[seed.as_bytes(), &(i as u64).to_be_bytes()[..]].concat(),
+        );
+        m += c * mi;
+        z += c * zi;
+    }
+    (m, z)
+}
+
+fn scalar_from_slice(bytes: &[u8]) -> Option<Scalar> {
+    let mut array = [0u8; 32];
+    if bytes.len() != 32 {
+        return None;
+    }
+    array.copy_from_slice(bytes);
+    Scalar::from_canonical_bytes(array)
+}
+pub fn scalar_from_b64(s: &str) -> Result<Scalar, TokenErr> {
+    scalar_from_slice(&base64::decode(s)?).ok_or(TokenErr::BadEncoding)
+}
+pub fn point_from_b64(s: &str) -> Result<RistrettoPoint, TokenErr> {
+    let bytes = base64::decode(s)?;
+    if bytes.len() != 32 {
+        return Err(TokenErr::BadEncoding);
+    }
+    CompressedRistretto::from_slice(&bytes)
+        .decompress()
+        .ok_or(TokenErr::BadEncoding)
+}
+pub fn point_to_b64(point: &RistrettoPoint) -> String {
+    base64::encode(point.compress().as_bytes())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TokenErr {
+    #[error("IO err: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("base64 decode err: {0}")]
+    B64(#[from] base64::DecodeError),
+    #[error("token key file is malformed, expected a base64 scalar")]
+    BadKeyFile,
+    #[error("not a valid ristretto255 point or scalar")]
+    BadEncoding,
+    #[error("issuance proof didn't verify: tokens may be tagged")]
+    BadProof,
+}